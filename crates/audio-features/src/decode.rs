@@ -1,10 +1,31 @@
 use anyhow::{bail, Context, Result};
 use hound::WavReader;
+use serde::{Deserialize, Serialize};
 use std::io::Cursor;
 
+/// How multi-channel audio becomes mono.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownmixMode {
+    /// Mean of the channels (out-of-phase material can cancel).
+    #[default]
+    Average,
+    /// First channel only.
+    Left,
+    /// Second channel only (the first one for mono files).
+    Right,
+    /// Per frame, the channel sample with the largest magnitude (keeps transients).
+    Max,
+}
+
 /// Decodes WAV from memory -> (mono f32 [-1,1], sample_rate).
 /// Supports 16-bit PCM, 24/32-bit PCM, 32f, 64f. Downmix through average on channels.
 pub fn decode_wav_to_mono_f32(bytes: &[u8]) -> Result<(Vec<f32>, u32)> {
+    decode_wav_with(bytes, DownmixMode::Average)
+}
+
+/// `decode_wav_to_mono_f32` with a choice of downmix.
+pub fn decode_wav_with(bytes: &[u8], downmix: DownmixMode) -> Result<(Vec<f32>, u32)> {
     let cursor = Cursor::new(bytes);
     let mut reader = WavReader::new(cursor).context("not a valid WAV")?;
     let spec = reader.spec();
@@ -52,26 +73,96 @@ pub fn decode_wav_to_mono_f32(bytes: &[u8]) -> Result<(Vec<f32>, u32)> {
         }
     };
 
-    // mono downmix (average on channels)
+    // mono downmix (a trailing partial frame uses the channels it has)
     if ch == 1 {
         return Ok((samples_f32, sr));
     }
 
-    let mut mono = Vec::with_capacity(samples_f32.len() / ch + 1);
-    let mut acc = 0.0f32;
-    let mut cnt = 0usize;
-    for s in samples_f32 {
-        acc += s;
-        cnt += 1;
-        if cnt == ch {
-            mono.push(acc / ch as f32);
-            acc = 0.0;
-            cnt = 0;
-        }
+    let mono = samples_f32
+        .chunks(ch)
+        .map(|frame| match downmix {
+            DownmixMode::Average => frame.iter().sum::<f32>() / frame.len() as f32,
+            DownmixMode::Left => frame[0],
+            DownmixMode::Right => frame[1.min(frame.len() - 1)],
+            DownmixMode::Max => frame.iter().copied().fold(0.0f32, |m, x| if x.abs() > m.abs() { x } else { m }),
+        })
+        .collect();
+
+    Ok((mono, sr))
+}
+
+/// Trims leading/trailing samples whose magnitude is below `threshold_db` (dBFS, e.g. -50.0).
+/// A signal that never reaches the threshold is left untouched.
+pub fn trim_silence(mono: &mut Vec<f32>, threshold_db: f32) {
+    let thr = 10f32.powf(threshold_db / 20.0);
+    let first = match mono.iter().position(|x| x.abs() >= thr) {
+        Some(i) => i,
+        None => return,
+    };
+    let last = mono.iter().rposition(|x| x.abs() >= thr).unwrap_or(first);
+    mono.truncate(last + 1);
+    mono.drain(..first);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FeatureExtractor;
+
+    const SR: u32 = 8000;
+
+    fn tone(hz: f32, n: usize) -> Vec<f32> {
+        (0..n).map(|i| 0.5 * (2.0 * std::f32::consts::PI * hz * i as f32 / SR as f32).sin()).collect()
     }
-    if cnt > 0 {
-        mono.push(acc / cnt as f32);
+
+    #[test]
+    fn trimming_padding_raises_the_voiced_ratio() {
+        let pad = vec![0.0f32; 3 * SR as usize];
+        let mut x: Vec<f32> = pad.iter().chain(&tone(220.0, SR as usize)).chain(&pad).copied().collect();
+        let fe = FeatureExtractor::new(SR, 1024, 256);
+        let before = fe.analyze_mono(&x, SR).unwrap().f0.voiced_ratio;
+        trim_silence(&mut x, -50.0);
+        assert!(x.len() <= SR as usize && x.len() > SR as usize - 40, "{} samples left", x.len());
+        let after = fe.analyze_mono(&x, SR).unwrap().f0.voiced_ratio;
+        assert!(before < 0.3, "{before}");
+        assert!(after > 0.8, "{after}");
     }
 
-    Ok((mono, sr))
+    #[test]
+    fn silence_is_left_untouched() {
+        let mut x = vec![0.0f32; 100];
+        trim_silence(&mut x, -50.0);
+        assert_eq!(x.len(), 100);
+    }
+
+    /// 16-bit stereo WAV with `left` and `right` channels.
+    fn stereo_wav(left: &[f32], right: &[f32]) -> Vec<u8> {
+        let spec = hound::WavSpec { channels: 2, sample_rate: SR, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut out = Cursor::new(Vec::new());
+        let mut w = hound::WavWriter::new(&mut out, spec).unwrap();
+        for (&l, &r) in left.iter().zip(right) {
+            w.write_sample((l * 32767.0) as i16).unwrap();
+            w.write_sample((r * 32767.0) as i16).unwrap();
+        }
+        w.finalize().unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn downmix_picks_the_requested_channel() {
+        let t = tone(440.0, 800);
+        let wav = stereo_wav(&t, &vec![0.0; 800]);
+        let rms = |x: &[f32]| (x.iter().map(|s| s * s).sum::<f32>() / x.len() as f32).sqrt();
+        let (left, sr) = decode_wav_with(&wav, DownmixMode::Left).unwrap();
+        let (right, _) = decode_wav_with(&wav, DownmixMode::Right).unwrap();
+        let (avg, _) = decode_wav_with(&wav, DownmixMode::Average).unwrap();
+        let (max, _) = decode_wav_with(&wav, DownmixMode::Max).unwrap();
+        assert_eq!(sr, SR);
+        assert_eq!(left.len(), 800);
+        assert!((rms(&left) - rms(&t)).abs() < 1e-3);
+        assert_eq!(rms(&right), 0.0);
+        assert!((rms(&avg) - 0.5 * rms(&t)).abs() < 1e-3);
+        assert_eq!(max, left);
+        assert_eq!(decode_wav_to_mono_f32(&wav).unwrap().0, avg);
+    }
 }
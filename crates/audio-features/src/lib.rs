@@ -1,15 +1,23 @@
 // crates/audio-features/src/lib.rs
 pub mod decode;
-pub use decode::decode_wav_to_mono_f32;
+pub use decode::{decode_wav_to_mono_f32, decode_wav_with, trim_silence, DownmixMode};
+pub mod loudness;
+pub use loudness::integrated_lufs;
+pub mod normalized;
+pub use normalized::{NormalizedFeatures, REFERENCE_SAMPLE_RATE};
 
 use serde::{Serialize, Deserialize};
 use anyhow::Result;
+use rayon::prelude::*;
+use rustfft::{Fft, num_complex::Complex};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct F0Stats {
     pub mean_hz: f32,
     pub std_hz: f32,
     pub voiced_ratio: f32, // [0,1]
+    pub contour_hz: Vec<f32>, // one estimate per step, 0 = unvoiced
+    pub contour_hop_sec: f32, // time between contour points
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,11 +26,15 @@ pub struct AudioFeatures {
     pub rms: f32,
     pub peak: f32,
     pub crest_factor: f32,
+    pub integrated_lufs: f32,  // BS.1770, -70 for silence / < 400ms
 
     // Time-domain
     pub zcr: f32,              // zero-crossings/sec
     pub onset_rate: f32,       // onsets/sec
+    pub onset_times_sec: Vec<f32>, // frame start time of each onset
     pub tempo_bpm: f32,
+    pub beat_times_sec: Vec<f32>,  // beat grid at `tempo_bpm`, phase aligned to the flux (empty: no tempo)
+    pub downbeat_offset_sec: f32,  // first beat of the strongest of the 4 bar positions
 
     // Spectral (frame-avg)
     pub spectral_centroid_hz: f32,
@@ -31,6 +43,10 @@ pub struct AudioFeatures {
     pub spectral_flatness: f32, // [0,1] ~ geometric/arith mean
     pub spectral_bandwidth_hz: f32,
     pub spectral_entropy: f32,   // [0,1]
+    pub spectral_contrast: [f32; 6], // dB peak-valley per octave band (<200, ..., >3200 Hz)
+    pub spectral_crest: f32,     // peak / mean magnitude (1 = flat, large = one dominant peak)
+    pub spectral_slope: f32,     // dB per kHz, least-squares line through the dB spectrum (< 0 = darker)
+    pub harmonic_ratio: f32,     // [0,1] harmonic / (harmonic + percussive) energy (median-filter HPSS)
 
     // Amplitude entropy
     pub amplitude_entropy: f32,  // [0,1]
@@ -39,22 +55,234 @@ pub struct AudioFeatures {
     pub f0: F0Stats,
 }
 
+/// Analysis window applied to each frame before the FFT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowKind {
+    #[default]
+    Hann,
+    Hamming,
+    BlackmanHarris, // 4-term
+    Rectangular,
+}
+
+impl WindowKind {
+    /// Periodic window coefficients of length `n`.
+    pub fn coefficients(self, n: usize) -> Vec<f32> {
+        use std::f32::consts::PI;
+        let nf = n as f32;
+        (0..n).map(|i| {
+            let x = 2.0 * PI * (i as f32) / nf;
+            match self {
+                WindowKind::Hann => 0.5 - 0.5 * x.cos(),
+                WindowKind::Hamming => 0.54 - 0.46 * x.cos(),
+                WindowKind::BlackmanHarris => {
+                    0.35875 - 0.48829 * x.cos() + 0.14128 * (2.0 * x).cos() - 0.01168 * (3.0 * x).cos()
+                }
+                WindowKind::Rectangular => 1.0,
+            }
+        }).collect()
+    }
+}
+
+/// Frames analysed in parallel per block by `analyze_mono`.
+const PAR_BLOCK_FRAMES: usize = 256;
+
+/// Per-frame spectral measures, reduced in frame order by `analyze_mono`.
+struct FrameStats {
+    mag: Vec<f32>,
+    centroid_hz: f32,
+    bandwidth_hz: f32,
+    rolloff85_hz: f32,
+    rolloff95_hz: f32,
+    flatness: f64,
+    entropy: f64,
+    contrast: [f64; 6],
+    crest: f64,
+    slope: f64,
+}
+
+/// What every frame of one `analyze_mono` call shares.
+struct FrameCtx<'a> {
+    window: &'a [f32],
+    fft: &'a dyn Fft<f32>,
+    band_edges: &'a [usize],
+    sr: u32,
+    n_fft: usize,
+}
+
+impl FrameCtx<'_> {
+    fn analyze(&self, frame: &[f32], scratch: &mut [Complex<f32>]) -> FrameStats {
+        let (sr, fs) = (self.sr, self.n_fft);
+        let bin2hz = |k: usize| (k as f32) * (sr as f32) / (fs as f32);
+
+        // Window + copy to complex buffer (zero-padded to the FFT length)
+        let mut buf: Vec<Complex<f32>> = frame.iter()
+            .zip(self.window)
+            .map(|(x,w)| Complex{ re: x*w, im: 0.0 })
+            .collect();
+        buf.resize(fs, Complex::default());
+
+        self.fft.process_with_scratch(&mut buf, scratch);
+
+        // Power spectrum (one-sided)
+        let mut mag = vec![0.0f32; fs/2+1];
+        for k in 0..=fs/2 {
+            let c = buf[k];
+            mag[k] = (c.re*c.re + c.im*c.im).sqrt();
+        }
+
+        // Spectral centroid / bandwidth (weighted by magnitude)
+        let mut wsum = 0.0f64;
+        let mut ksum = 0.0f64;
+        for (k, &m) in mag[..=fs/2].iter().enumerate() {
+            let m = m as f64;
+            wsum += m;
+            ksum += m * (k as f64);
+        }
+        let centroid_bin = if wsum>0.0 { ksum/wsum } else { 0.0 };
+        let centroid_hz = centroid_bin as f32 * (sr as f32)/(fs as f32);
+
+        // Bandwidth (2nd central moment around centroid)
+        let mut var = 0.0f64;
+        for (k, &m) in mag[..=fs/2].iter().enumerate() {
+            let m = m as f64;
+            let d = (k as f64) - centroid_bin;
+            var += m * d*d;
+        }
+        let bw_bin = if wsum>0.0 { (var/wsum).sqrt() } else { 0.0 };
+        let bandwidth_hz = bw_bin as f32 * (sr as f32)/(fs as f32);
+
+        // Rolloff (85%, 95%)
+        let mut csum = 0.0f64;
+        let total: f64 = mag.iter().map(|&m| m as f64).sum();
+        let thr85 = 0.85 * total;
+        let thr95 = 0.95 * total;
+        let mut r85 = 0usize;
+        let mut r95 = 0usize;
+        if total > 0.0 {
+            for (k, &m) in mag[..=fs/2].iter().enumerate() {
+                csum += m as f64;
+                if r85==0 && csum>=thr85 { r85 = k; }
+                if r95==0 && csum>=thr95 { r95 = k; break; }
+            }
+        }
+
+        // Flatness (geo/arith)
+        let eps = 1e-12f64;
+        let geo = mag.iter().fold(0.0f64, |acc, &m| acc + (m as f64 + eps).ln());
+        let geo = (geo / (mag.len() as f64)).exp();
+        let arith = (total + eps) / (mag.len() as f64);
+        let flatness = (geo/arith).clamp(0.0, 1.0);
+
+        // Spectral entropy (normalize to pmf, H/logN)
+        let mut p = vec![0.0f64; mag.len()];
+        let total_p: f64 = mag.iter().map(|&m| m as f64).sum::<f64>() + eps;
+        for (i,&m) in mag.iter().enumerate() {
+            p[i] = (m as f64) / total_p;
+        }
+        let h = -p.iter().map(|&pi| if pi>0.0 { pi*(pi.ln()) } else { 0.0 }).sum::<f64>();
+        let entropy = (h / (mag.len() as f64).ln()).clamp(0.0, 1.0);
+
+        // Spectral contrast: per band, mean of the top vs bottom 20% magnitudes (dB)
+        let mut contrast = [0.0f64; 6];
+        for (b, c) in contrast.iter_mut().enumerate() {
+            let (lo, hi) = (self.band_edges[b], self.band_edges[b + 1]);
+            if hi <= lo { continue; }
+            let mut band: Vec<f32> = mag[lo..hi].to_vec();
+            band.sort_by(f32::total_cmp);
+            let q = ((band.len() as f32 * 0.2).round() as usize).max(1);
+            let valley = band[..q].iter().map(|&m| m as f64).sum::<f64>() / q as f64;
+            let peak = band[band.len() - q..].iter().map(|&m| m as f64).sum::<f64>() / q as f64;
+            *c = 20.0 * ((peak + 1e-10) / (valley + 1e-10)).log10();
+        }
+
+        // Crest: strongest bin over the mean magnitude
+        let peak = mag.iter().fold(0.0f32, |mx, &m| mx.max(m)) as f64;
+        let crest = if total > 0.0 { peak / (total / mag.len() as f64) } else { 0.0 };
+
+        // Slope: least-squares fit of the dB magnitude against frequency (kHz)
+        let n = mag.len() as f64;
+        let khz = |k: usize| bin2hz(k) as f64 / 1000.0;
+        let x_mean = (0..mag.len()).map(khz).sum::<f64>() / n;
+        let db: Vec<f64> = mag.iter().map(|&m| 20.0 * (m as f64 + eps).log10()).collect();
+        let y_mean = db.iter().sum::<f64>() / n;
+        let (mut sxy, mut sxx) = (0.0f64, 0.0f64);
+        for (k, &y) in db.iter().enumerate() {
+            let dx = khz(k) - x_mean;
+            sxy += dx * (y - y_mean);
+            sxx += dx * dx;
+        }
+        let slope = if sxx > 0.0 { sxy / sxx } else { 0.0 };
+
+        FrameStats {
+            mag, centroid_hz, bandwidth_hz, rolloff85_hz: bin2hz(r85), rolloff95_hz: bin2hz(r95),
+            flatness, entropy, contrast, crest, slope,
+        }
+    }
+}
+
 pub struct FeatureExtractor {
     pub target_sr: u32,     // e.g. 22050
     pub frame_size: usize,  // e.g. 2048
     pub hop_size: usize,    // e.g. 512
+    pub window: WindowKind, // default Hann
+    pub onset_k: f32,           // onset threshold: local mean + k * local std of the flux
+    pub onset_window: usize,    // flux frames in that local window (centred)
 }
 
+/// Defaults for `FeatureExtractor::onset_k` / `onset_window`.
+pub const DEFAULT_ONSET_K: f32 = 1.5;
+pub const DEFAULT_ONSET_WINDOW: usize = 32;
+
 impl FeatureExtractor {
     pub fn new(target_sr: u32, frame_size: usize, hop_size: usize) -> Self {
-        Self { target_sr, frame_size, hop_size }
+        Self {
+            target_sr, frame_size, hop_size, window: WindowKind::Hann,
+            onset_k: DEFAULT_ONSET_K, onset_window: DEFAULT_ONSET_WINDOW,
+        }
+    }
+
+    /// `new`, rejecting sizes `analyze_mono` can't work with (see `validate`).
+    pub fn try_new(target_sr: u32, frame_size: usize, hop_size: usize) -> Result<Self> {
+        let fe = Self::new(target_sr, frame_size, hop_size);
+        fe.validate()?;
+        Ok(fe)
+    }
+
+    /// `frame_size > 0` and `0 < hop_size <= frame_size`. A frame size that isn't a
+    /// power of two is fine: frames are zero-padded to the next one for the FFT.
+    pub fn validate(&self) -> Result<()> {
+        use anyhow::bail;
+        if self.frame_size == 0 { bail!("frame_size must be > 0"); }
+        if self.hop_size == 0 { bail!("hop_size must be > 0"); }
+        if self.hop_size > self.frame_size {
+            bail!("hop_size ({}) must not exceed frame_size ({})", self.hop_size, self.frame_size);
+        }
+        if self.onset_window == 0 { bail!("onset_window must be > 0"); }
+        if !(self.onset_k.is_finite() && self.onset_k >= 0.0) { bail!("onset_k must be >= 0"); }
+        Ok(())
+    }
+
+    pub fn with_window(mut self, window: WindowKind) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Onset threshold: `k` local standard deviations above the local mean flux,
+    /// over `window` flux frames.
+    pub fn with_onset_threshold(mut self, k: f32, window: usize) -> Self {
+        self.onset_k = k;
+        self.onset_window = window;
+        self
     }
 
     pub fn analyze_mono(&self, mono: &[f32], sr: u32) -> Result<AudioFeatures> {
-        use rustfft::{FftPlanner, num_complex::Complex};
+        use rustfft::FftPlanner;
         use anyhow::bail;
 
         if mono.is_empty() || sr == 0 { bail!("empty signal"); }
+        self.validate()?;
 
         // 1) Basic amp stats
         let mut sum2 = 0.0f64;
@@ -66,6 +294,7 @@ impl FeatureExtractor {
         }
         let rms = (sum2 / mono.len() as f64).sqrt() as f32;
         let crest = if rms > 0.0 { peak / rms } else { 0.0 };
+        let lufs = integrated_lufs(mono, sr);
 
         // 2) ZCR (per sec)
         let mut zc = 0usize;
@@ -75,33 +304,28 @@ impl FeatureExtractor {
         }
         let zcr = (zc as f32) * (sr as f32) / (mono.len().saturating_sub(1).max(1) as f32);
 
-        // Framing
+        // Framing (a signal shorter than one frame is zero-padded to a single frame)
         let n = mono.len();
         let fs = self.frame_size;
         let hop = self.hop_size;
-        let n_frames = if n < fs { 0 } else { 1 + (n - fs)/hop };
-        if n_frames == 0 {
-            return Ok(AudioFeatures {
-                rms, peak, crest_factor: crest, zcr,
-                onset_rate: 0.0, tempo_bpm: 0.0,
-                spectral_centroid_hz: 0.0, spectral_rolloff85_hz: 0.0,
-                spectral_rolloff95_hz: 0.0, spectral_flatness: 0.0,
-                spectral_bandwidth_hz: 0.0, spectral_entropy: 0.0,
-                amplitude_entropy: 0.0,
-                f0: F0Stats{mean_hz:0.0,std_hz:0.0,voiced_ratio:0.0},
-            });
-        }
+        let padded: Vec<f32>;
+        let framed = if n < fs {
+            padded = mono.iter().copied().chain(std::iter::repeat(0.0)).take(fs).collect();
+            &padded[..]
+        } else {
+            mono
+        };
+        let n_frames = 1 + (framed.len() - fs)/hop;
+        // FFT length: the frame, zero-padded to a power of two
+        let n_fft = fs.next_power_of_two();
 
-        // Hann window
-        let mut window = vec![0.0f32; fs];
-        for i in 0..fs {
-            window[i] = 0.5 - 0.5 * (2.0*std::f32::consts::PI*(i as f32)/(fs as f32)).cos();
-        }
+        // Analysis window (Hann by default)
+        let window = self.window.coefficients(fs);
 
-        // FFT
+        // FFT (the plan is shared; every worker brings its own scratch)
         let mut planner = FftPlanner::<f32>::new();
-        let fft = planner.plan_fft_forward(fs);
-        let bin2hz = |k: usize| (k as f32) * (sr as f32) / (fs as f32);
+        let fft = planner.plan_fft_forward(n_fft);
+        let band_edges = contrast_band_edges(n_fft, sr);
 
         let mut centroid_sum = 0.0f64;
         let mut roll85_sum = 0.0f64;
@@ -109,113 +333,70 @@ impl FeatureExtractor {
         let mut flatness_sum = 0.0f64;
         let mut bandwidth_sum = 0.0f64;
         let mut spec_entropy_sum = 0.0f64;
+        let mut contrast_sum = [0.0f64; 6];
+        let mut crest_sum = 0.0f64;
+        let mut slope_sum = 0.0f64;
 
         // Onset (spectral flux)
-        let mut prev_mag = vec![0.0f32; fs/2+1];
+        let mut prev_mag = vec![0.0f32; n_fft/2+1];
         let mut flux_vals = Vec::with_capacity(n_frames);
 
-        for fi in 0..n_frames {
-            let start = fi*hop;
-            let frame = &mono[start..start+fs];
-
-            // Window + copy to complex buffer
-            let mut buf: Vec<Complex<f32>> = frame.iter()
-                .zip(&window)
-                .map(|(x,w)| Complex{ re: x*w, im: 0.0 })
+        // Band-pooled magnitude spectrogram for the HPSS after the loop
+        let hpss_band_width = (n_fft / 2 + 1).div_ceil(HPSS_BANDS);
+        let hpss_bins = (n_fft / 2 + 1).div_ceil(hpss_band_width);
+        let mut spectrogram: Vec<f32> = Vec::with_capacity(n_frames * hpss_bins);
+
+        // Frames are analysed in parallel, one block at a time (bounded memory for the
+        // magnitudes); sums and the flux then run in frame order, as a serial loop would.
+        let ctx = FrameCtx { window: &window, fft: fft.as_ref(), band_edges: &band_edges, sr, n_fft };
+        for block_start in (0..n_frames).step_by(PAR_BLOCK_FRAMES) {
+            let block_end = (block_start + PAR_BLOCK_FRAMES).min(n_frames);
+            let frames: Vec<FrameStats> = (block_start..block_end)
+                .into_par_iter()
+                .map_init(
+                    || vec![Complex::default(); fft.get_inplace_scratch_len()],
+                    |scratch, fi| ctx.analyze(&framed[fi * hop..fi * hop + fs], scratch),
+                )
                 .collect();
 
-            fft.process(&mut buf);
-
-            // Power spectrum (one-sided)
-            let mut mag = vec![0.0f32; fs/2+1];
-            for k in 0..=fs/2 {
-                let c = buf[k];
-                mag[k] = (c.re*c.re + c.im*c.im).sqrt();
-            }
-
-            // Spectral centroid / bandwidth (weighted by magnitude)
-            let mut wsum = 0.0f64;
-            let mut ksum = 0.0f64;
-            for k in 0..=fs/2 {
-                let m = mag[k] as f64;
-                wsum += m;
-                ksum += m * (k as f64);
-            }
-            let centroid_bin = if wsum>0.0 { ksum/wsum } else { 0.0 };
-            let centroid_hz = centroid_bin as f32 * (sr as f32)/(fs as f32);
-            centroid_sum += centroid_hz as f64;
-
-            // Bandwidth (2nd central moment around centroid)
-            let mut var = 0.0f64;
-            for k in 0..=fs/2 {
-                let m = mag[k] as f64;
-                let d = (k as f64) - centroid_bin;
-                var += m * d*d;
-            }
-            let bw_bin = if wsum>0.0 { (var/wsum).sqrt() } else { 0.0 };
-            let bw_hz = bw_bin as f32 * (sr as f32)/(fs as f32);
-            bandwidth_sum += bw_hz as f64;
-
-            // Rolloff (85%, 95%)
-            let mut csum = 0.0f64;
-            let total: f64 = mag.iter().map(|&m| m as f64).sum();
-            let thr85 = 0.85 * total;
-            let thr95 = 0.95 * total;
-            let mut r85 = 0usize;
-            let mut r95 = 0usize;
-            if total > 0.0 {
-                for k in 0..=fs/2 {
-                    csum += mag[k] as f64;
-                    if r85==0 && csum>=thr85 { r85 = k; }
-                    if r95==0 && csum>=thr95 { r95 = k; break; }
+            for f in frames {
+                centroid_sum += f.centroid_hz as f64;
+                bandwidth_sum += f.bandwidth_hz as f64;
+                roll85_sum += f.rolloff85_hz as f64;
+                roll95_sum += f.rolloff95_hz as f64;
+                flatness_sum += f.flatness;
+                spec_entropy_sum += f.entropy;
+                for (s, &c) in contrast_sum.iter_mut().zip(&f.contrast) { *s += c; }
+                crest_sum += f.crest;
+                slope_sum += f.slope;
+
+                // HPSS input: magnitudes summed into bands of `hpss_band_width` bins
+                spectrogram.extend(f.mag.chunks(hpss_band_width).map(|c| c.iter().sum::<f32>()));
+
+                // Flux (ReLU of mag diff)
+                let mut flux = 0.0f32;
+                for (&m, &pm) in f.mag.iter().zip(&prev_mag) {
+                    let d = (m - pm).max(0.0);
+                    flux += d;
                 }
+                flux_vals.push(flux);
+                prev_mag = f.mag;
             }
-            roll85_sum += bin2hz(r85) as f64;
-            roll95_sum += bin2hz(r95) as f64;
-
-            // Flatness (geo/arith)
-            let eps = 1e-12f64;
-            let geo = mag.iter().fold(0.0f64, |acc, &m| acc + (m as f64 + eps).ln());
-            let geo = (geo / (mag.len() as f64)).exp();
-            let arith = (total + eps) / (mag.len() as f64);
-            let flat = (geo/arith).clamp(0.0, 1.0);
-            flatness_sum += flat;
-
-            // Spectral entropy (normalize to pmf, H/logN)
-            let mut p = vec![0.0f64; mag.len()];
-            let total_p: f64 = mag.iter().map(|&m| m as f64).sum::<f64>() + eps;
-            for (i,&m) in mag.iter().enumerate() {
-                p[i] = (m as f64) / total_p;
-            }
-            let h = -p.iter().map(|&pi| if pi>0.0 { pi*(pi.ln()) } else { 0.0 }).sum::<f64>();
-            let h_norm = (h / (mag.len() as f64).ln()).clamp(0.0, 1.0);
-            spec_entropy_sum += h_norm;
-
-            // Flux (ReLU of mag diff)
-            let mut flux = 0.0f32;
-            for k in 0..mag.len() {
-                let d = (mag[k] - prev_mag[k]).max(0.0);
-                flux += d;
-            }
-            flux_vals.push(flux);
-            prev_mag = mag;
         }
 
-        // Onset rate (per sec): adaptive treshold on flux
-        let mean_flux = if !flux_vals.is_empty() {
-            flux_vals.iter().sum::<f32>() / (flux_vals.len() as f32)
-        } else { 0.0 };
-        let thr = mean_flux * 1.5; // simple
-        let mut onsets = 0usize;
-        for &f in &flux_vals {
-            if f > thr { onsets += 1; }
-        }
+        let harmonic_ratio = hpss_harmonic_ratio(&spectrogram, hpss_bins);
+
+        // Onset rate (per sec): flux peaks above the local adaptive threshold
+        let onset_times_sec: Vec<f32> = onset_frames(&flux_vals, self.onset_k, self.onset_window)
+            .into_iter()
+            .map(|fi| (fi * hop) as f32 / sr as f32)
+            .collect();
         let secs = n as f32 / sr as f32;
-        let onset_rate = if secs>0.0 { onsets as f32 / secs } else { 0.0 };
+        let onset_rate = if secs>0.0 { onset_times_sec.len() as f32 / secs } else { 0.0 };
 
         // Tempo (autocorrelation on flux -> bpm peak in [50..200])
-        let bpm = {
-            if flux_vals.len() < 4 { 0.0 }
+        let (bpm, beat_lag) = {
+            if flux_vals.len() < 4 { (0.0, 0) }
             else {
                 let mut ac = vec![0.0f32; flux_vals.len()];
                 for lag in 1..flux_vals.len() {
@@ -231,19 +412,22 @@ impl FeatureExtractor {
                 // map lag->bpm
                 let fps = (sr as f32) / (hop as f32);
                 let mut best_bpm = 0.0f32;
+                let mut best_lag = 0usize;
                 let mut best_val = 0.0f32;
-                for lag in 1..ac.len() {
+                for (lag, &a) in ac.iter().enumerate().skip(1) {
                     let period_sec = (lag as f32)/fps;
                     if period_sec <= 0.0 { continue; }
                     let cand_bpm = 60.0/period_sec;
-                    if cand_bpm >= 50.0 && cand_bpm <= 200.0 && ac[lag] > best_val {
-                        best_val = ac[lag];
+                    if (50.0..=200.0).contains(&cand_bpm) && a > best_val {
+                        best_val = a;
                         best_bpm = cand_bpm;
+                        best_lag = lag;
                     }
                 }
-                best_bpm
+                (best_bpm, best_lag)
             }
         };
+        let (beat_times_sec, downbeat_offset_sec) = beat_grid(&flux_vals, beat_lag, hop as f32 / sr as f32);
 
         // Amplitude entropy (histogram 64 bins)
         let amp_entropy = {
@@ -273,6 +457,7 @@ impl FeatureExtractor {
             let win = (sr/50).max(1024) as usize; // ~20ms+
             let step = hop.max(256);
             let mut f0s = Vec::new();
+            let mut contour = Vec::new();
             let mut voiced = 0usize;
             let mut i = 0usize;
             while i + win <= mono.len() {
@@ -294,6 +479,9 @@ impl FeatureExtractor {
                     voiced += 1;
                     let hz = sr as f32 / best_p.max(1) as f32;
                     if hz.is_finite() { f0s.push(hz); }
+                    contour.push(if hz.is_finite() { hz } else { 0.0 });
+                } else {
+                    contour.push(0.0);
                 }
                 i += step;
             }
@@ -304,20 +492,280 @@ impl FeatureExtractor {
                 let v = f0s.iter().map(|&x|(x-m)*(x-m)).sum::<f32>()/(f0s.len() as f32);
                 (m, v.sqrt(), (voiced as f32)/((mono.len()/step).max(1) as f32))
             };
-            F0Stats{ mean_hz: mean, std_hz: std, voiced_ratio: vr.clamp(0.0,1.0) }
+            F0Stats{
+                mean_hz: mean, std_hz: std, voiced_ratio: vr.clamp(0.0,1.0),
+                contour_hz: contour, contour_hop_sec: step as f32 / sr as f32,
+            }
         };
 
         Ok(AudioFeatures{
-            rms, peak, crest_factor: crest, zcr,
-            onset_rate, tempo_bpm: bpm,
+            rms, peak, crest_factor: crest, integrated_lufs: lufs, zcr,
+            onset_rate, onset_times_sec, tempo_bpm: bpm,
+            beat_times_sec, downbeat_offset_sec,
             spectral_centroid_hz: (centroid_sum/n_frames as f64) as f32,
             spectral_rolloff85_hz: (roll85_sum/n_frames as f64) as f32,
             spectral_rolloff95_hz: (roll95_sum/n_frames as f64) as f32,
             spectral_flatness: (flatness_sum/n_frames as f64) as f32,
             spectral_bandwidth_hz: (bandwidth_sum/n_frames as f64) as f32,
             spectral_entropy: (spec_entropy_sum/n_frames as f64) as f32,
+            spectral_contrast: contrast_sum.map(|c| (c/n_frames as f64) as f32),
+            spectral_crest: (crest_sum/n_frames as f64) as f32,
+            spectral_slope: (slope_sum/n_frames as f64) as f32,
+            harmonic_ratio,
             amplitude_entropy: amp_entropy,
             f0,
         })
     }
 }
+
+/// An onset's flux is at least this multiple of the local mean...
+const ONSET_MIN_RATIO: f64 = 1.5;
+/// ...and this fraction of the largest flux (numerical ripple on a steady tone is not an onset).
+const ONSET_MIN_OF_MAX: f64 = 0.05;
+
+/// Frames whose flux is a local peak above `mean + k * std` (and `ONSET_MIN_RATIO * mean`)
+/// of the `window` frames around it (prefix sums, so linear in the frame count).
+fn onset_frames(flux: &[f32], k: f32, window: usize) -> Vec<usize> {
+    let n = flux.len();
+    let mut sum = vec![0.0f64; n + 1];
+    let mut sum2 = vec![0.0f64; n + 1];
+    for (i, &f) in flux.iter().enumerate() {
+        sum[i + 1] = sum[i] + f as f64;
+        sum2[i + 1] = sum2[i] + (f as f64) * (f as f64);
+    }
+    let half = window / 2;
+    let floor = flux.iter().copied().fold(0.0f32, f32::max) as f64 * ONSET_MIN_OF_MAX;
+    let mut onsets = Vec::new();
+    for i in 0..n {
+        let (a, b) = (i.saturating_sub(half), (i + half + 1).min(n));
+        let len = (b - a) as f64;
+        let mean = (sum[b] - sum[a]) / len;
+        let var = ((sum2[b] - sum2[a]) / len - mean * mean).max(0.0);
+        // steady material has a small spread; it must still rise well above its mean
+        let thr = (mean + k as f64 * var.sqrt()).max(mean * ONSET_MIN_RATIO);
+        let f = flux[i] as f64;
+        let peak = (i == 0 || flux[i] >= flux[i - 1]) && (i + 1 == n || flux[i] > flux[i + 1]);
+        if f > thr && f > floor && peak { onsets.push(i); }
+    }
+    onsets
+}
+
+/// Beats per bar assumed when picking the downbeat.
+const BEATS_PER_BAR: usize = 4;
+
+/// Beat grid for a period of `lag` flux frames: the phase whose grid collects the most
+/// flux, then the bar position (of `BEATS_PER_BAR`) with the most flux is the downbeat.
+/// Returns (beat times, first downbeat time); empty when there is no clear tempo: no
+/// period (`lag` 0), less than a bar of beats, or a grid barely above the average flux.
+fn beat_grid(flux: &[f32], lag: usize, frame_sec: f32) -> (Vec<f32>, f32) {
+    if lag == 0 || lag >= flux.len() { return (Vec::new(), 0.0); }
+    let grid_sum = |phase: usize| flux.iter().skip(phase).step_by(lag).sum::<f32>();
+    let phase = (0..lag).max_by(|&a, &b| grid_sum(a).total_cmp(&grid_sum(b))).unwrap_or(0);
+    let beats: Vec<usize> = (phase..flux.len()).step_by(lag).collect();
+    let mean = flux.iter().sum::<f32>() / flux.len() as f32;
+    if beats.len() < BEATS_PER_BAR || grid_sum(phase) / (beats.len() as f32) < 1.2 * mean {
+        return (Vec::new(), 0.0);
+    }
+    let bar_sum = |o: usize| beats.iter().skip(o).step_by(BEATS_PER_BAR).map(|&f| flux[f]).sum::<f32>();
+    let bar_pos = (0..BEATS_PER_BAR.min(beats.len())).max_by(|&a, &b| bar_sum(a).total_cmp(&bar_sum(b))).unwrap_or(0);
+    let times = beats.iter().map(|&f| f as f32 * frame_sec).collect();
+    (times, beats[bar_pos] as f32 * frame_sec)
+}
+
+/// Upper bound on the frequency bands of the spectrogram behind `harmonic_ratio` (bounds its memory).
+const HPSS_BANDS: usize = 128;
+/// Median filter length (frames across time, bands across frequency) for the HPSS.
+const HPSS_KERNEL: usize = 17;
+
+/// Median-filter HPSS (Fitzgerald): the median across time enhances steady partials
+/// (harmonic), the median across frequency enhances broadband clicks (percussive).
+/// Each cell's energy is split with soft (Wiener) masks; returns the harmonic share.
+/// `spec` is frame-major with `bins` values per frame.
+fn hpss_harmonic_ratio(spec: &[f32], bins: usize) -> f32 {
+    if bins == 0 || spec.is_empty() { return 0.0; }
+    let frames = spec.len() / bins;
+    let half = HPSS_KERNEL / 2;
+    let mut window = Vec::with_capacity(HPSS_KERNEL);
+    let mut median = |vals: &mut dyn Iterator<Item = f32>| {
+        window.clear();
+        window.extend(vals);
+        let mid = window.len() / 2;
+        *window.select_nth_unstable_by(mid, f32::total_cmp).1
+    };
+    let (mut e_h, mut e_p) = (0.0f64, 0.0f64);
+    for f in 0..frames {
+        let (t0, t1) = (f.saturating_sub(half), (f + half + 1).min(frames));
+        for b in 0..bins {
+            let (b0, b1) = (b.saturating_sub(half), (b + half + 1).min(bins));
+            let h = median(&mut (t0..t1).map(|t| spec[t * bins + b]));
+            let p = median(&mut spec[f * bins + b0..f * bins + b1].iter().copied());
+            let (h2, p2) = ((h * h) as f64, (p * p) as f64);
+            if h2 + p2 <= 0.0 { continue; }
+            let e = (spec[f * bins + b] as f64).powi(2);
+            e_h += e * h2 / (h2 + p2);
+            e_p += e * p2 / (h2 + p2);
+        }
+    }
+    if e_h + e_p > 0.0 { (e_h / (e_h + e_p)) as f32 } else { 0.0 }
+}
+
+/// Bin edges of the six spectral-contrast bands: <200, 200-400, 400-800,
+/// 800-1600, 1600-3200 and >3200 Hz (last edge = Nyquist bin + 1).
+fn contrast_band_edges(fs: usize, sr: u32) -> [usize; 7] {
+    let n_bins = fs / 2 + 1;
+    let hz2bin = |hz: f32| ((hz * fs as f32 / sr as f32).round() as usize).min(n_bins);
+    [0, hz2bin(200.0), hz2bin(400.0), hz2bin(800.0), hz2bin(1600.0), hz2bin(3200.0), n_bins]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SR: u32 = 8000;
+
+    fn tone(hz: f32, secs: f32) -> Vec<f32> {
+        let n = (secs * SR as f32) as usize;
+        // phase in f64: an f32 phase drifts enough to leak out of the tone's bin
+        (0..n).map(|i| 0.5 * (2.0 * std::f64::consts::PI * hz as f64 * i as f64 / SR as f64).sin() as f32).collect()
+    }
+
+    /// Uniform white noise in [-0.5, 0.5) (xorshift, fixed seed).
+    fn noise(secs: f32) -> Vec<f32> {
+        let mut x = 0x2545F4914F6CDD1Du64;
+        (0..(secs * SR as f32) as usize).map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            (x >> 40) as f32 / (1u64 << 24) as f32 - 0.5
+        }).collect()
+    }
+
+    fn extractor() -> FeatureExtractor {
+        FeatureExtractor::new(SR, 1024, 256)
+    }
+
+    #[test]
+    fn rectangular_window_keeps_a_bin_centred_sine_in_one_bin() {
+        // bin 64 of 1024 at 8 kHz
+        let x = tone(500.0, 1.0);
+        let hann = extractor().analyze_mono(&x, SR).unwrap();
+        let rect = extractor().with_window(WindowKind::Rectangular).analyze_mono(&x, SR).unwrap();
+        assert!((rect.spectral_centroid_hz - 500.0).abs() < 1.0, "{}", rect.spectral_centroid_hz);
+        assert!((hann.spectral_centroid_hz - 500.0).abs() < 5.0, "{}", hann.spectral_centroid_hz);
+        assert!(rect.spectral_bandwidth_hz < 0.5 * hann.spectral_bandwidth_hz,
+            "rect {} vs hann {}", rect.spectral_bandwidth_hz, hann.spectral_bandwidth_hz);
+        assert!(rect.spectral_crest > hann.spectral_crest);
+    }
+
+    #[test]
+    fn tone_has_more_spectral_contrast_than_noise() {
+        let t = extractor().analyze_mono(&tone(1000.0, 1.0), SR).unwrap();
+        let n = extractor().analyze_mono(&noise(1.0), SR).unwrap();
+        // 1 kHz sits in the 800-1600 Hz band
+        assert!(t.spectral_contrast[3] > 40.0, "{:?}", t.spectral_contrast);
+        assert!(n.spectral_contrast.iter().all(|&c| c < 20.0), "{:?}", n.spectral_contrast);
+        assert!(t.spectral_contrast[3] > n.spectral_contrast[3] + 20.0);
+    }
+
+    #[test]
+    fn bad_frame_and_hop_sizes_are_rejected() {
+        assert!(FeatureExtractor::try_new(SR, 1024, 0).is_err());
+        assert!(FeatureExtractor::try_new(SR, 0, 0).is_err());
+        assert!(FeatureExtractor::try_new(SR, 1024, 2048).is_err());
+        assert!(FeatureExtractor::try_new(SR, 1000, 250).is_ok(), "non-power-of-two frames are padded");
+        // `new` doesn't validate, `analyze_mono` does (no division by a zero hop)
+        assert!(FeatureExtractor::new(SR, 1024, 0).analyze_mono(&tone(440.0, 0.5), SR).is_err());
+    }
+
+    #[test]
+    fn signal_shorter_than_a_frame_is_one_padded_frame() {
+        let x = tone(500.0, 0.05); // 400 samples
+        let f = extractor().analyze_mono(&x, SR).unwrap();
+        assert!(f.spectral_centroid_hz > 300.0 && f.spectral_centroid_hz < 700.0, "{}", f.spectral_centroid_hz);
+        assert!(f.onset_times_sec.len() <= 1);
+        assert!(f.rms > 0.3);
+        assert!(extractor().analyze_mono(&[], SR).is_err());
+    }
+
+    #[test]
+    fn click_train_onsets_are_counted() {
+        // 4 clicks per second for 4 s, each a short decaying burst
+        let mut x = vec![0.0f32; 4 * SR as usize];
+        for c in 0..16 {
+            let at = c * SR as usize / 4 + 100;
+            for (j, s) in x[at..at + 80].iter_mut().enumerate() {
+                *s = if j % 2 == 0 { 0.8 } else { -0.8 } * (1.0 - j as f32 / 80.0);
+            }
+        }
+        let f = extractor().analyze_mono(&x, SR).unwrap();
+        let found = f.onset_times_sec.len();
+        assert!((15..=17).contains(&found), "{found} onsets: {:?}", f.onset_times_sec);
+        assert!((f.onset_rate - 4.0).abs() < 0.5, "{}", f.onset_rate);
+    }
+
+    /// `x` through a one-pole low-pass at `hz`, twice.
+    fn low_pass(x: &[f32], hz: f32) -> Vec<f32> {
+        let a = (-2.0 * std::f32::consts::PI * hz / SR as f32).exp();
+        let mut out = x.to_vec();
+        for _ in 0..2 {
+            let mut y = 0.0;
+            for s in out.iter_mut() {
+                y = (1.0 - a) * *s + a * y;
+                *s = y;
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn bright_noise_has_a_flatter_slope_than_a_dark_tone() {
+        let bright = extractor().analyze_mono(&noise(1.0), SR).unwrap();
+        let mixed: Vec<f32> = tone(200.0, 1.0).iter().zip(noise(1.0)).map(|(t, n)| t + 0.2 * n).collect();
+        let dark = extractor().analyze_mono(&low_pass(&mixed, 300.0), SR).unwrap();
+        assert!(bright.spectral_slope.abs() < 1.0, "{}", bright.spectral_slope);
+        assert!(dark.spectral_slope < -5.0, "{}", dark.spectral_slope);
+        assert!(dark.spectral_crest > bright.spectral_crest);
+        // neither end pins in the normalized view
+        let (b, d) = (bright.normalized_at(SR).spectral_slope, dark.normalized_at(SR).spectral_slope);
+        assert!((b - 0.5).abs() < 0.05, "{b}");
+        assert!(d > 0.0 && d < 0.3, "{d}");
+    }
+
+    #[test]
+    fn parallel_frames_match_a_serial_pass() {
+        // small hop: 3.5 blocks of `PAR_BLOCK_FRAMES` frames in 7 s; pulsed so there are onsets
+        let fe = FeatureExtractor::new(SR, 256, 64);
+        let (t, n) = (tone(440.0, 7.0), noise(7.0));
+        let x: Vec<f32> = t.iter().zip(&n).enumerate()
+            .map(|(i, (a, b))| if (i / 2000) % 2 == 0 { a + 0.3 * b } else { 0.2 * b })
+            .collect();
+        assert!(x.len() / 64 > 3 * PAR_BLOCK_FRAMES);
+        let on = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            pool.install(|| fe.analyze_mono(&x, SR).unwrap())
+        };
+        let (serial, par) = (on(1), on(4));
+        let close = |a: f32, b: f32| (a - b).abs() <= 1e-5 * a.abs().max(1.0);
+        let pairs = [
+            (serial.spectral_centroid_hz, par.spectral_centroid_hz),
+            (serial.spectral_rolloff85_hz, par.spectral_rolloff85_hz),
+            (serial.spectral_rolloff95_hz, par.spectral_rolloff95_hz),
+            (serial.spectral_flatness, par.spectral_flatness),
+            (serial.spectral_bandwidth_hz, par.spectral_bandwidth_hz),
+            (serial.spectral_entropy, par.spectral_entropy),
+            (serial.spectral_crest, par.spectral_crest),
+            (serial.spectral_slope, par.spectral_slope),
+            (serial.harmonic_ratio, par.harmonic_ratio),
+            (serial.onset_rate, par.onset_rate),
+            (serial.tempo_bpm, par.tempo_bpm),
+        ];
+        for (i, (a, b)) in pairs.into_iter().enumerate() {
+            assert!(close(a, b), "feature {i}: serial {a} vs parallel {b}");
+        }
+        for (a, b) in serial.spectral_contrast.iter().zip(&par.spectral_contrast) {
+            assert!(close(*a, *b), "contrast: serial {a} vs parallel {b}");
+        }
+        assert_eq!(serial.onset_times_sec.len(), par.onset_times_sec.len());
+        assert!(serial.onset_rate > 0.0);
+    }
+}
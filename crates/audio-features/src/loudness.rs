@@ -0,0 +1,83 @@
+//! ITU-R BS.1770 integrated loudness (mono): K-weighting + gated 400ms blocks.
+
+/// Returned for silence and signals shorter than one 400ms block.
+pub const LUFS_FLOOR: f32 = -70.0;
+
+/// Direct-form I biquad, coefficients already normalized by a0.
+struct Biquad { b: [f64; 3], a: [f64; 2], x1: f64, x2: f64, y1: f64, y2: f64 }
+
+impl Biquad {
+    fn new(b: [f64; 3], a0: f64, a1: f64, a2: f64) -> Self {
+        Self { b: [b[0] / a0, b[1] / a0, b[2] / a0], a: [a1 / a0, a2 / a0], x1: 0.0, x2: 0.0, y1: 0.0, y2: 0.0 }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x1 + self.b[2] * self.x2 - self.a[0] * self.y1 - self.a[1] * self.y2;
+        self.x2 = self.x1; self.x1 = x;
+        self.y2 = self.y1; self.y1 = y;
+        y
+    }
+}
+
+/// K-weighting stages (high shelf + RLB high-pass), designed for any sample rate.
+fn k_weighting(sr: u32) -> (Biquad, Biquad) {
+    use std::f64::consts::PI;
+    let fs = sr as f64;
+
+    // Stage 1: +4 dB high shelf around 1.7 kHz
+    let (g_db, q, fc) = (3.999_843_853_973_347, 0.707_175_236_955_419_3, 1_681.974_450_955_533);
+    let a = 10f64.powf(g_db / 40.0);
+    let w0 = 2.0 * PI * fc / fs;
+    let (cw, alpha) = (w0.cos(), w0.sin() / (2.0 * q));
+    let sa = 2.0 * a.sqrt() * alpha;
+    let shelf = Biquad::new(
+        [a * ((a + 1.0) + (a - 1.0) * cw + sa), -2.0 * a * ((a - 1.0) + (a + 1.0) * cw), a * ((a + 1.0) + (a - 1.0) * cw - sa)],
+        (a + 1.0) - (a - 1.0) * cw + sa,
+        2.0 * ((a - 1.0) - (a + 1.0) * cw),
+        (a + 1.0) - (a - 1.0) * cw - sa,
+    );
+
+    // Stage 2: high-pass around 38 Hz
+    let (q, fc) = (0.500_327_037_323_877_3, 38.135_470_876_024_44);
+    let w0 = 2.0 * PI * fc / fs;
+    let (cw, alpha) = (w0.cos(), w0.sin() / (2.0 * q));
+    let hp = Biquad::new(
+        [(1.0 + cw) / 2.0, -(1.0 + cw), (1.0 + cw) / 2.0],
+        1.0 + alpha,
+        -2.0 * cw,
+        1.0 - alpha,
+    );
+    (shelf, hp)
+}
+
+/// Integrated loudness in LUFS of a mono signal.
+/// 400ms blocks with 75% overlap, absolute gate at -70 LUFS, relative gate at -10 LU.
+pub fn integrated_lufs(mono: &[f32], sr: u32) -> f32 {
+    if sr == 0 { return LUFS_FLOOR; }
+    let block = (0.4 * sr as f64).round() as usize;
+    let step = (0.1 * sr as f64).round().max(1.0) as usize;
+    if block == 0 || mono.len() < block { return LUFS_FLOOR; }
+
+    let (mut s1, mut s2) = k_weighting(sr);
+    let sq: Vec<f64> = mono.iter().map(|&x| {
+        let y = s2.process(s1.process(x as f64));
+        y * y
+    }).collect();
+
+    // mean square per block
+    let mut z = Vec::with_capacity((sq.len() - block) / step + 1);
+    let mut start = 0usize;
+    while start + block <= sq.len() {
+        z.push(sq[start..start + block].iter().sum::<f64>() / block as f64);
+        start += step;
+    }
+
+    let loud = |ms: f64| -0.691 + 10.0 * ms.max(1e-20).log10();
+    let abs_gated: Vec<f64> = z.iter().copied().filter(|&m| loud(m) > LUFS_FLOOR as f64).collect();
+    if abs_gated.is_empty() { return LUFS_FLOOR; }
+    let rel_gate = loud(abs_gated.iter().sum::<f64>() / abs_gated.len() as f64) - 10.0;
+    let rel_gated: Vec<f64> = abs_gated.into_iter().filter(|&m| loud(m) > rel_gate).collect();
+    if rel_gated.is_empty() { return LUFS_FLOOR; }
+
+    (loud(rel_gated.iter().sum::<f64>() / rel_gated.len() as f64) as f32).max(LUFS_FLOOR)
+}
@@ -0,0 +1,105 @@
+// crates/audio-features/src/normalized.rs
+//! `AudioFeatures` mapped to roughly 0..1, for comparing files or feeding models.
+
+use serde::{Serialize, Deserialize};
+
+use crate::AudioFeatures;
+use crate::loudness::LUFS_FLOOR;
+
+/// Sample rate `AudioFeatures::normalized` assumes (Nyquist 22050 Hz).
+pub const REFERENCE_SAMPLE_RATE: u32 = 44_100;
+
+/// dBFS mapped to 0 (`LEVEL_FLOOR_DB` and below) .. 1 (0 dBFS), for `rms` and `peak`.
+const LEVEL_FLOOR_DB: f32 = -60.0;
+/// Crest factors (peak/RMS and spectral) in dB: 0 dB .. these.
+const CREST_RANGE_DB: f32 = 30.0;
+const SPECTRAL_CREST_RANGE_DB: f32 = 60.0;
+/// Onsets per second at 1.
+const ONSET_RATE_MAX: f32 = 10.0;
+/// Tempo range (BPM).
+const TEMPO_MIN: f32 = 40.0;
+const TEMPO_MAX: f32 = 240.0;
+/// Spectral contrast (dB) at 1.
+const CONTRAST_MAX_DB: f32 = 60.0;
+/// Spectral slope range (dB per kHz), centred on a flat spectrum; low-passed, tonal
+/// material measures around -9.
+const SLOPE_RANGE_DB_PER_KHZ: f32 = 12.0;
+/// F0 range (Hz), mapped on a log scale.
+const F0_MIN_HZ: f32 = 50.0;
+const F0_MAX_HZ: f32 = 1000.0;
+/// F0 standard deviation (Hz) at 1.
+const F0_STD_MAX_HZ: f32 = 200.0;
+
+/// `AudioFeatures` on a common, roughly 0..1 scale (clamped). Each field notes the
+/// reference range mapped to 0..1; values outside it pin to the ends. Lists (onsets,
+/// beats, the F0 contour) are left out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NormalizedFeatures {
+    pub rms: f32,                 // -60..0 dBFS
+    pub peak: f32,                // -60..0 dBFS
+    pub crest_factor: f32,        // 0..30 dB
+    pub integrated_lufs: f32,     // -70..0 LUFS
+    pub zcr: f32,                 // 0..sample rate crossings/sec
+    pub onset_rate: f32,          // 0..10 onsets/sec
+    pub tempo: f32,               // 40..240 BPM (0 also when no tempo was found)
+    pub spectral_centroid: f32,   // 0..Nyquist
+    pub spectral_rolloff85: f32,  // 0..Nyquist
+    pub spectral_rolloff95: f32,  // 0..Nyquist
+    pub spectral_bandwidth: f32,  // 0..Nyquist
+    pub spectral_flatness: f32,   // already 0..1
+    pub spectral_entropy: f32,    // already 0..1
+    pub spectral_contrast: [f32; 6], // 0..60 dB per band
+    pub spectral_crest: f32,      // 0..60 dB
+    pub spectral_slope: f32,      // -12..+12 dB/kHz (0.5 = flat)
+    pub harmonic_ratio: f32,      // already 0..1
+    pub amplitude_entropy: f32,   // already 0..1
+    pub f0_mean: f32,             // 50..1000 Hz, log scale (0 = unvoiced)
+    pub f0_std: f32,              // 0..200 Hz
+    pub voiced_ratio: f32,        // already 0..1
+}
+
+impl AudioFeatures {
+    /// `normalized_at(REFERENCE_SAMPLE_RATE)`.
+    pub fn normalized(&self) -> NormalizedFeatures {
+        self.normalized_at(REFERENCE_SAMPLE_RATE)
+    }
+
+    /// Every field on a roughly 0..1 scale; `sr` is the rate the features were analysed
+    /// at (frequency features are relative to its Nyquist, `zcr` to `sr`).
+    pub fn normalized_at(&self, sr: u32) -> NormalizedFeatures {
+        let sr = sr.max(1) as f32;
+        let nyquist = sr / 2.0;
+        let unit = |x: f32, lo: f32, hi: f32| if x.is_finite() { ((x - lo) / (hi - lo)).clamp(0.0, 1.0) } else { 0.0 };
+        let db = |x: f32| 20.0 * x.max(1e-9).log10();
+        let level = |x: f32| unit(db(x), LEVEL_FLOOR_DB, 0.0);
+        let hz = |x: f32| unit(x, 0.0, nyquist);
+        let f0_mean = if self.f0.mean_hz > 0.0 {
+            unit(self.f0.mean_hz.ln(), F0_MIN_HZ.ln(), F0_MAX_HZ.ln())
+        } else {
+            0.0
+        };
+        NormalizedFeatures {
+            rms: level(self.rms),
+            peak: level(self.peak),
+            crest_factor: unit(db(self.crest_factor), 0.0, CREST_RANGE_DB),
+            integrated_lufs: unit(self.integrated_lufs, LUFS_FLOOR, 0.0),
+            zcr: unit(self.zcr, 0.0, sr),
+            onset_rate: unit(self.onset_rate, 0.0, ONSET_RATE_MAX),
+            tempo: unit(self.tempo_bpm, TEMPO_MIN, TEMPO_MAX),
+            spectral_centroid: hz(self.spectral_centroid_hz),
+            spectral_rolloff85: hz(self.spectral_rolloff85_hz),
+            spectral_rolloff95: hz(self.spectral_rolloff95_hz),
+            spectral_bandwidth: hz(self.spectral_bandwidth_hz),
+            spectral_flatness: unit(self.spectral_flatness, 0.0, 1.0),
+            spectral_entropy: unit(self.spectral_entropy, 0.0, 1.0),
+            spectral_contrast: self.spectral_contrast.map(|c| unit(c, 0.0, CONTRAST_MAX_DB)),
+            spectral_crest: unit(db(self.spectral_crest), 0.0, SPECTRAL_CREST_RANGE_DB),
+            spectral_slope: unit(self.spectral_slope, -SLOPE_RANGE_DB_PER_KHZ, SLOPE_RANGE_DB_PER_KHZ),
+            harmonic_ratio: unit(self.harmonic_ratio, 0.0, 1.0),
+            amplitude_entropy: unit(self.amplitude_entropy, 0.0, 1.0),
+            f0_mean,
+            f0_std: unit(self.f0.std_hz, 0.0, F0_STD_MAX_HZ),
+            voiced_ratio: unit(self.f0.voiced_ratio, 0.0, 1.0),
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Small in-memory LRU cache for conversions, keyed on a SHA-256 digest of the whole
+//! request and bounded by the total size of the cached responses.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use sha2::{Digest, Sha256};
+
+use crate::{ConvertRequest, ConvertResponse, OutputArtifact};
+
+/// SHA-256 of a request's JSON form.
+pub type CacheKey = [u8; 32];
+
+/// Thread-safe LRU of `ConvertResponse`s; share it behind an `Arc`.
+pub struct ConvertCache {
+    max_bytes: usize,
+    inner: Mutex<Inner>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Default)]
+struct Inner {
+    map: HashMap<CacheKey, (ConvertResponse, usize)>,
+    order: VecDeque<CacheKey>, // front = least recently used
+    bytes: usize,
+}
+
+/// Writes the serialized request straight into the digest (no JSON string in between).
+struct HashWriter(Sha256);
+
+impl std::io::Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> { Ok(()) }
+}
+
+/// Approximate size of a response: its base64 artifacts, plus the JSON ones serialized.
+fn response_bytes(resp: &ConvertResponse) -> usize {
+    resp.artifacts
+        .iter()
+        .map(|a| match a {
+            OutputArtifact::WavBase64 { data_b64 }
+            | OutputArtifact::FlacBase64 { data_b64 }
+            | OutputArtifact::MidiJsonBase64 { data_b64 }
+            | OutputArtifact::MidiBase64 { data_b64 }
+            | OutputArtifact::PngBase64 { data_b64 } => data_b64.len(),
+            OutputArtifact::Json { data } => serde_json::to_vec(data).map_or(0, |v| v.len()),
+        })
+        .sum()
+}
+
+impl ConvertCache {
+    /// `max_bytes` bounds the summed size of the cached responses (at least 1); a response
+    /// larger than that is not cached.
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes: max_bytes.max(1),
+            inner: Mutex::new(Inner::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Digest of (from, to, options, payload) via their JSON form; None if the request
+    /// doesn't serialize (it is then converted uncached).
+    pub fn key(req: &ConvertRequest) -> Option<CacheKey> {
+        let mut w = HashWriter(Sha256::new());
+        serde_json::to_writer(&mut w, req).ok()?;
+        Some(w.0.finalize().into())
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<ConvertResponse> {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        match inner.map.get(key).map(|(resp, _)| resp.clone()) {
+            Some(resp) => {
+                inner.order.retain(|k| k != key);
+                inner.order.push_back(*key);
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(resp)
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    pub fn insert(&self, key: CacheKey, resp: ConvertResponse) {
+        let size = response_bytes(&resp);
+        if size > self.max_bytes { return; }
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, old)) = inner.map.insert(key, (resp, size)) {
+            inner.bytes -= old;
+            inner.order.retain(|k| *k != key);
+        }
+        inner.bytes += size;
+        inner.order.push_back(key);
+        while inner.bytes > self.max_bytes {
+            match inner.order.pop_front() {
+                Some(old) => {
+                    if let Some((_, n)) = inner.map.remove(&old) { inner.bytes -= n; }
+                }
+                None => break,
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).map.len()
+    }
+
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+
+    /// Summed size of the cached responses (see `new`).
+    pub fn bytes(&self) -> usize {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).bytes
+    }
+
+    pub fn hits(&self) -> u64 { self.hits.load(Ordering::Relaxed) }
+    pub fn misses(&self) -> u64 { self.misses.load(Ordering::Relaxed) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handle_convert_cached, InputPayload, OutputArtifact, TransformOpts};
+
+    fn text_json(text: &str) -> ConvertRequest {
+        ConvertRequest {
+            from: "text".into(),
+            to: "json".into(),
+            options: TransformOpts::default(),
+            payload: InputPayload::Text { text: text.into() },
+        }
+    }
+
+    fn json(resp: &ConvertResponse) -> &serde_json::Value {
+        match &resp.artifacts[0] {
+            OutputArtifact::Json { data } => data,
+            other => panic!("expected JSON, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn second_identical_request_is_a_hit() {
+        let cache = ConvertCache::new(1 << 20);
+        let first = handle_convert_cached(text_json("hello there"), &cache).unwrap();
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (0, 1, 1));
+        let second = handle_convert_cached(text_json("hello there"), &cache).unwrap();
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (1, 1, 1));
+        assert_eq!(json(&first), json(&second));
+
+        // any change to the request is another entry
+        let mut seeded = text_json("hello there");
+        seeded.options.seed = Some(1);
+        handle_convert_cached(seeded, &cache).unwrap();
+        assert_eq!((cache.hits(), cache.misses(), cache.len()), (1, 2, 2));
+    }
+
+    #[test]
+    fn errors_are_not_cached() {
+        let cache = ConvertCache::new(1 << 20);
+        let mut bad = text_json("x");
+        bad.to = "video".into();
+        assert!(handle_convert_cached(bad, &cache).is_err());
+        assert!(cache.is_empty());
+    }
+
+    /// A response of `n` bytes (one WAV artifact).
+    fn sized(n: usize) -> ConvertResponse {
+        ConvertResponse { artifacts: vec![OutputArtifact::WavBase64 { data_b64: "A".repeat(n) }], meta: None }
+    }
+
+    #[test]
+    fn least_recently_used_entry_is_evicted() {
+        let cache = ConvertCache::new(250);
+        cache.insert([1; 32], sized(100));
+        cache.insert([2; 32], sized(100));
+        assert!(cache.get(&[1; 32]).is_some()); // 2 is now the oldest
+        cache.insert([3; 32], sized(100));
+        assert_eq!((cache.len(), cache.bytes()), (2, 200));
+        assert!(cache.get(&[2; 32]).is_none());
+        assert!(cache.get(&[1; 32]).is_some() && cache.get(&[3; 32]).is_some());
+
+        // larger than the whole budget: not cached, nothing evicted
+        cache.insert([4; 32], sized(300));
+        assert_eq!((cache.len(), cache.bytes()), (2, 200));
+    }
+}
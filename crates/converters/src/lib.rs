@@ -1,6 +1,7 @@
 //! converters - zero-knobs pipeline: content -> music
 //! - Text -> Audio: duration from word count; style from text-features
 //! - Image -> Audio: duration from rezolution; parsing without loop; style from image-features
+//! - Audio -> Audio: melody follows the source's F0 contour; style from audio-features
 //! - (optional) *-features rute for debug (audio/text/image -> json)
 
 use anyhow::{anyhow, Context, Result};
@@ -8,13 +9,25 @@ use base64::Engine;
 use base64::engine::general_purpose::STANDARD as B64;
 use serde::{Deserialize, Serialize};
 
-use melody_core::{MonophonicMidi, ScaleKind, degree_to_midi};
-use melody_synth::{Osc, StyleParams, render_wav_bytes_styled};
+use melody_core::{Note, degree_to_midi, hz_to_midi};
+pub use melody_core::{MonophonicMidi, ScaleKind};
+pub use melody_synth::{NoiseColor, Osc, StyleParams};
+use melody_synth::{drum_hits, multi_track_midi, music_start_sec, wav_to_flac, write_wav_i16, DrumPattern, VelocityCurve, render_mono_styled_with_progress};
+
+pub mod cache;
+pub use cache::ConvertCache;
+
+pub mod piano_roll;
+pub use piano_roll::render_piano_roll;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 /// External feature extractors (must be provided by sibling crates)
-use audio_features::FeatureExtractor as AudioFE;
-use text_features::{analyze_text, TextFeatures};
-use visual_features::{analyze_image_bytes, ImageFeatures};
+use audio_features::{AudioFeatures, FeatureExtractor as AudioFE};
+use text_features::{analyze_text, is_question, normalize_token, split_paragraphs, split_sentences, TextFeatures};
+use image::DynamicImage;
+use visual_features::{analyze_image_bytes_mode, analyze_image_on, AnalyzeMode, to_rgb8_over, ImageFeatures, DEFAULT_BACKGROUND, DEFAULT_THUMB};
 
 /// Public request/response types used by CLI and any service layer.
 
@@ -25,13 +38,53 @@ pub enum InputPayload {
     Text { text: String },
     /// Raw image, base64-encoded (PNG/JPEG etc.)
     ImageBase64 { data_b64: String },
-    /// Raw audio (WAV) base64 - used only for audio->json features
+    /// Raw audio (WAV) base64 - audio->json features and audio->audio
     AudioBase64 { data_b64: String },
+    /// Standard MIDI file, base64 - melody is rendered with the default style
+    MidiBase64 { data_b64: String },
+    /// Text plus an image (base64), blended into one piece - used by multi->audio
+    TextAndImage { text: String, image_b64: String },
+}
+
+impl InputPayload {
+    /// Variant name, as in the `kind` tag.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            InputPayload::Text { .. } => "Text",
+            InputPayload::ImageBase64 { .. } => "ImageBase64",
+            InputPayload::AudioBase64 { .. } => "AudioBase64",
+            InputPayload::MidiBase64 { .. } => "MidiBase64",
+            InputPayload::TextAndImage { .. } => "TextAndImage",
+        }
+    }
+
+    /// The `from` this payload belongs to.
+    pub fn source(&self) -> &'static str {
+        match self {
+            InputPayload::Text { .. } => "text",
+            InputPayload::ImageBase64 { .. } => "image",
+            InputPayload::AudioBase64 { .. } => "audio",
+            InputPayload::MidiBase64 { .. } => "midi",
+            InputPayload::TextAndImage { .. } => "multi",
+        }
+    }
+}
+
+/// Payload kind `from` expects (None: not a known source).
+fn payload_kind_for(from: &str) -> Option<&'static str> {
+    Some(match from {
+        "text" => "Text",
+        "image" => "ImageBase64",
+        "audio" => "AudioBase64",
+        "midi" => "MidiBase64",
+        "multi" => "TextAndImage",
+        _ => return None,
+    })
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ConvertRequest {
-    pub from: String, // "text" | "image" | "audio"
+    pub from: String, // "text" | "image" | "audio" | "midi" | "multi"
     pub to: String,   // "audio" | "json"
     pub options: TransformOpts,
     pub payload: InputPayload,
@@ -42,8 +95,15 @@ pub struct ConvertRequest {
 pub enum OutputArtifact {
     /// 16-bit PCM WAV, base64-encoded
     WavBase64 { data_b64: String },
+    /// Same audio as lossless FLAC (`TransformOpts.audio_codec = flac`), base64-encoded
+    FlacBase64 { data_b64: String },
     /// MIDI timeline as JSON, base64 (to preserve binary safety across transports)
     MidiJsonBase64 { data_b64: String },
+    /// Standard MIDI file (`TransformOpts.midi_file`), base64: melody, harmony, bass
+    /// and drums on separate tracks/channels
+    MidiBase64 { data_b64: String },
+    /// Piano-roll PNG of the MIDI timeline (`TransformOpts.piano_roll`), base64
+    PngBase64 { data_b64: String },
     /// Generic JSON (features etc.)
     Json { data: serde_json::Value },
 }
@@ -51,8 +111,94 @@ pub enum OutputArtifact {
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct ConvertResponse {
     pub artifacts: Vec<OutputArtifact>,
+    /// What the auto style chose (text/image/multi/audio -> audio), None for the other routes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub meta: Option<ConvertMeta>,
+}
+
+/// Summary of a generated piece, e.g. for "a 120 BPM piece in C minor".
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ConvertMeta {
+    pub tempo_bpm: u32,
+    /// Tempo at the end of a tempo ramp (None = constant `tempo_bpm`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tempo_end_bpm: Option<u32>,
+    pub root_midi: i32,
+    pub scale: ScaleKind,
+    pub polyphony: usize,
+    /// Length of the rendered WAV (0 for a dry run).
+    pub duration_sec: f32,
+    /// Where the music starts in the WAV (after the count-in; 0 without one or for a dry run).
+    #[serde(default)]
+    pub music_start_sec: f32,
+}
+
+/// Audio artifact encoding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioCodec {
+    /// 16-bit PCM WAV (`OutputArtifact::WavBase64`)
+    #[default]
+    Wav,
+    /// Lossless FLAC of the same samples (`OutputArtifact::FlacBase64`)
+    Flac,
+}
+
+impl std::str::FromStr for AudioCodec {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "wav" => Ok(AudioCodec::Wav),
+            "flac" => Ok(AudioCodec::Flac),
+            other => Err(anyhow!("unknown audio format '{other}' (expected wav|flac)")),
+        }
+    }
+}
+
+/// `(from, to)` pairs `handle_convert` accepts.
+pub const SUPPORTED_ROUTES: &[(&str, &str)] = &[
+    ("text", "audio"),
+    ("image", "audio"),
+    ("multi", "audio"),
+    ("midi", "audio"),
+    ("audio", "audio"),
+    ("audio", "json"),
+    ("text", "json"),
+    ("image", "json"),
+];
+
+/// Request errors (the caller's fault, as opposed to failures while converting);
+/// returned inside `anyhow::Error`, recover them with `downcast_ref`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConvertError {
+    /// No route for this `(from, to)` pair.
+    Unsupported { from: String, to: String },
+    /// `from` names a known source, but the payload is another source's variant.
+    MismatchedPayload { from: String, payload: &'static str },
+    /// A decoded input (`input`: "text", "image", "audio" or "midi") exceeds its `InputLimits` entry.
+    InputTooLarge { input: &'static str, size: usize, limit: usize },
+}
+
+impl std::fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConvertError::Unsupported { from, to } => {
+                let routes: Vec<String> = SUPPORTED_ROUTES.iter().map(|(a, b)| format!("{a} -> {b}")).collect();
+                write!(f, "unsupported conversion: {from} -> {to} (supported: {})", routes.join(", "))
+            }
+            ConvertError::MismatchedPayload { from, payload } => {
+                let expected = payload_kind_for(from).unwrap_or("?");
+                write!(f, "from = \"{from}\" expects a {expected} payload, got {payload}")
+            }
+            ConvertError::InputTooLarge { input, size, limit } => {
+                write!(f, "{input} input too large: {size} bytes (limit {limit})")
+            }
+        }
+    }
 }
 
+impl std::error::Error for ConvertError {}
+
 /// Zero-knobs options – only keep the operational controllers (not the creative ones).
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct TransformOpts {
@@ -60,8 +206,295 @@ pub struct TransformOpts {
     pub text_sec_per_word: Option<f32>,  // default 0.30
     pub text_min_sec: Option<f32>,       // default 10
     pub text_max_sec: Option<f32>,       // default 180
-    /// (optional) only for images; if missing, extracting from resolution
+    /// (optional) only for images; if missing, extracting from resolution.
+    /// Sets the base note count (~2 notes per second); `image_note_density` then scales it.
     pub target_seconds: Option<f32>,
+    /// (optional) only for images; multiplier on the note count derived from the
+    /// resolution (or from `target_seconds`), 0.05..8, default 1
+    pub image_note_density: Option<f32>,
+    /// (optional) only for images; hard bounds on the number of tiles/notes,
+    /// applied last (always within 1..`IMAGE_NOTES_CEIL`)
+    pub min_notes: Option<usize>,
+    pub max_notes: Option<usize>,
+    /// (optional) snap note starts to this grid (in beats, e.g. 0.25 = sixteenths) before rendering
+    pub quantize: Option<f32>,
+    /// (optional) no note shorter than this many beats: shorter ones merge into the note
+    /// they follow (after `quantize`), at most `MAX_MIN_NOTE_BEATS`
+    pub min_note_beats: Option<f32>,
+
+    /// Opt-in creative overrides: when set, they replace the auto-deduced value.
+    pub root_midi: Option<i32>,
+    pub scale: Option<ScaleKind>,
+    pub tempo_bpm: Option<u32>,
+    /// Oscillator stack (first = primary), 1..=`MAX_LAYERS` entries.
+    pub layering: Option<Vec<Osc>>,
+    /// Drum noise: white (bright), pink or brown (softer kits).
+    pub noise_color: Option<NoiseColor>,
+
+    /// Also emit a JSON artifact with the analysed features and the derived style.
+    #[serde(default)]
+    pub include_features: bool,
+
+    /// (optional) RGB drawn under transparent image pixels; default white
+    pub image_background: Option<[u8; 3]>,
+
+    /// audio -> json / audio: drop leading/trailing silence (below `SILENCE_THRESHOLD_DB`) before analysis
+    #[serde(default)]
+    pub trim_silence: bool,
+
+    /// (optional) cut the note timeline at this many seconds before rendering;
+    /// may not exceed `HARD_MAX_OUTPUT_SEC`
+    pub max_output_sec: Option<f32>,
+
+    /// (optional) take number: a different seed gives a different (but reproducible)
+    /// melody/timing variation of the same input; None = seed 0
+    pub seed: Option<u64>,
+
+    /// (optional) output sample rate in Hz, `MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE`;
+    /// default `DEFAULT_SAMPLE_RATE`. Audio analysis keeps the input's own rate.
+    pub sample_rate: Option<u32>,
+
+    /// (optional) text/image: octaves the melodic walk may span either side of the root,
+    /// 1..=`MAX_RANGE_OCTAVES` (7 scale degrees each); default ±`DEFAULT_DEGREE_RANGE` degrees
+    pub range_octaves: Option<u8>,
+
+    /// Encoding of the audio artifact; default WAV.
+    #[serde(default)]
+    pub audio_codec: AudioCodec,
+
+    /// text/image: now and then approach the next note from a semitone away
+    /// (chromatic passing/neighbour tones)
+    #[serde(default)]
+    pub chromatic: bool,
+
+    /// audio outputs: bars of metronome clicks before the music, at most
+    /// `MAX_COUNT_IN_BARS` (`meta.music_start_sec` says where the music starts)
+    #[serde(default)]
+    pub count_in_bars: u32,
+
+    /// (optional) audio -> json: analysis frame/hop in samples; default 2048/512
+    /// (frame at most `MAX_FRAME_SIZE`, hop at most the frame)
+    pub frame_size: Option<usize>,
+    pub hop_size: Option<usize>,
+    /// (optional) image -> json: thumbnail size the features are measured on,
+    /// `MIN_THUMB..=MAX_THUMB`; default `DEFAULT_THUMB`
+    pub thumb: Option<u32>,
+    /// image -> json: resample the thumbnail with a Lanczos filter instead of fast sampling
+    /// (slower, steadier color/edge stats)
+    #[serde(default)]
+    pub accurate: bool,
+
+    /// image -> audio: how tiles become pitches; default `HueSteps`
+    #[serde(default)]
+    pub image_map: ImageMap,
+
+    /// image -> audio: order the tiles are played in; default `Snake`
+    #[serde(default)]
+    pub scan_order: ScanOrder,
+
+    /// (optional) audio outputs: integrated loudness (LUFS, `MIN_TARGET_LUFS..=MAX_TARGET_LUFS`)
+    /// the rendered audio is brought to with one gain before encoding; boosts stop at
+    /// `MAX_LOUDNESS_GAIN_DB` and before the peak clips. None = peak protection only
+    pub target_lufs: Option<f32>,
+
+    /// audio outputs: also emit the piece as a multi-track Standard MIDI file
+    /// (`OutputArtifact::MidiBase64`)
+    #[serde(default)]
+    pub midi_file: bool,
+
+    /// audio outputs: also emit a piano roll of the melody (`OutputArtifact::PngBase64`)
+    #[serde(default)]
+    pub piano_roll: bool,
+
+    /// text/image/multi/audio -> audio: stop after the analysis and return only the
+    /// features + style JSON and `meta` (no melody building, no rendering), e.g. to
+    /// preview the style before a full render
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// Largest inputs the conversion accepts. Set by the host (e.g. services/api from its
+    /// environment), never by the request: not part of the JSON.
+    #[serde(skip)]
+    pub limits: InputLimits,
+}
+
+/// Tile -> pitch mapping of `image_to_audio`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageMap {
+    /// Hue distance from the image's mean hue sets the step size of a zig-zag walk.
+    #[default]
+    HueSteps,
+    /// The pitch follows each tile's luminance (brighter = higher), tracing the
+    /// image's brightness along the scan path.
+    LumaContour,
+}
+
+/// Path of `image_to_audio` over the tile grid (one note per tile, each tile once).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanOrder {
+    /// Boustrophedon: rows top to bottom, alternating left-to-right and right-to-left.
+    #[default]
+    Snake,
+    /// Rows top to bottom, each left to right (a jump back at every row end).
+    RowMajor,
+    /// Columns left to right, each top to bottom.
+    ColumnMajor,
+    /// Clockwise from the top-left corner towards the center (outside-in).
+    Spiral,
+}
+
+impl std::str::FromStr for ScanOrder {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().replace('_', "-").as_str() {
+            "snake" => Ok(ScanOrder::Snake),
+            "row-major" => Ok(ScanOrder::RowMajor),
+            "column-major" => Ok(ScanOrder::ColumnMajor),
+            "spiral" => Ok(ScanOrder::Spiral),
+            other => Err(anyhow!("unknown scan order '{other}' (expected snake|row-major|column-major|spiral)")),
+        }
+    }
+}
+
+impl ScanOrder {
+    /// The `(row, col)` tiles of a `rows` x `cols` grid in visiting order.
+    pub fn path(self, rows: u32, cols: u32) -> Vec<(u32, u32)> {
+        let mut out = Vec::with_capacity((rows * cols) as usize);
+        match self {
+            ScanOrder::Snake => {
+                for r in 0..rows {
+                    if r % 2 == 0 {
+                        out.extend((0..cols).map(|c| (r, c)));
+                    } else {
+                        out.extend((0..cols).rev().map(|c| (r, c)));
+                    }
+                }
+            }
+            ScanOrder::RowMajor => out.extend((0..rows).flat_map(|r| (0..cols).map(move |c| (r, c)))),
+            ScanOrder::ColumnMajor => out.extend((0..cols).flat_map(|c| (0..rows).map(move |r| (r, c)))),
+            ScanOrder::Spiral => {
+                // peel the outer ring (top, right, bottom, left side), then the next one inside;
+                // bottom/right are exclusive
+                let (mut top, mut left, mut bottom, mut right) = (0, 0, rows, cols);
+                while top < bottom && left < right {
+                    out.extend((left..right).map(|c| (top, c)));
+                    out.extend((top + 1..bottom).map(|r| (r, right - 1)));
+                    if bottom - top > 1 {
+                        out.extend((left..right - 1).rev().map(|c| (bottom - 1, c)));
+                    }
+                    if right - left > 1 {
+                        out.extend((top + 1..bottom - 1).rev().map(|r| (r, left)));
+                    }
+                    (top, left, bottom, right) = (top + 1, left + 1, bottom - 1, right - 1);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// Largest `TransformOpts::frame_size`.
+pub const MAX_FRAME_SIZE: usize = 1 << 16;
+/// Bounds of `TransformOpts::thumb`.
+pub const MIN_THUMB: u32 = 16;
+pub const MAX_THUMB: u32 = 2048;
+
+/// Bounds of `TransformOpts::target_lufs`.
+pub const MIN_TARGET_LUFS: f32 = -60.0;
+pub const MAX_TARGET_LUFS: f32 = -5.0;
+/// Largest boost (dB) `target_lufs` applies, so near-silent pieces don't get their
+/// noise floor pulled up.
+pub const MAX_LOUDNESS_GAIN_DB: f32 = 20.0;
+/// Peak a `target_lufs` boost stops at.
+const LOUDNESS_PEAK_CEILING: f32 = 0.99;
+
+/// Most oscillators `TransformOpts::layering` may stack.
+pub const MAX_LAYERS: usize = 8;
+/// Upper bound for `TransformOpts.min_note_beats`.
+pub const MAX_MIN_NOTE_BEATS: f32 = 8.0;
+
+/// Longest count-in `TransformOpts::count_in_bars` allows.
+pub const MAX_COUNT_IN_BARS: u32 = 8;
+
+/// Level under which leading/trailing audio counts as silence (dBFS).
+pub const SILENCE_THRESHOLD_DB: f32 = -50.0;
+
+/// Absolute ceiling on rendered length (seconds), protects the service from huge buffers.
+pub const HARD_MAX_OUTPUT_SEC: f32 = 600.0;
+
+/// Output sample rate when `TransformOpts.sample_rate` is None.
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+/// Accepted range for `TransformOpts.sample_rate` (Hz).
+pub const MIN_SAMPLE_RATE: u32 = 8_000;
+pub const MAX_SAMPLE_RATE: u32 = 192_000;
+
+/// Scale degrees the text/image walks may move from the root (either side) by default.
+pub const DEFAULT_DEGREE_RANGE: i32 = 12;
+/// Upper bound for `TransformOpts.range_octaves`.
+pub const MAX_RANGE_OCTAVES: u8 = 4;
+
+/// Absolute ceiling on the image route's tile/note count.
+pub const IMAGE_NOTES_CEIL: usize = 20_000;
+
+/// Default `InputLimits`, in decoded bytes.
+pub const DEFAULT_MAX_TEXT_BYTES: usize = 1 << 20;
+pub const DEFAULT_MAX_IMAGE_BYTES: usize = 20 << 20;
+pub const DEFAULT_MAX_AUDIO_BYTES: usize = 50 << 20;
+pub const DEFAULT_MAX_MIDI_BYTES: usize = 1 << 20;
+
+/// Largest inputs a conversion accepts (`TransformOpts.limits`), in decoded bytes; bigger
+/// ones fail with `ConvertError::InputTooLarge` before any decoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InputLimits {
+    pub text: usize,
+    pub image: usize,
+    pub audio: usize,
+    pub midi: usize,
+}
+
+impl Default for InputLimits {
+    fn default() -> Self {
+        Self {
+            text: DEFAULT_MAX_TEXT_BYTES,
+            image: DEFAULT_MAX_IMAGE_BYTES,
+            audio: DEFAULT_MAX_AUDIO_BYTES,
+            midi: DEFAULT_MAX_MIDI_BYTES,
+        }
+    }
+}
+
+impl InputLimits {
+    /// Largest serialized payload (base64 included) any input within the limits needs;
+    /// a sensible base for an HTTP body limit.
+    pub fn max_encoded_bytes(&self) -> usize {
+        let binary = self.image.max(self.audio).max(self.midi);
+        binary.div_ceil(3) * 4 + self.text
+    }
+
+    /// Fails with `InputTooLarge` when a part of `payload` exceeds its limit.
+    /// Base64 parts are measured by their decoded length, without decoding them.
+    pub fn check(&self, payload: &InputPayload) -> std::result::Result<(), ConvertError> {
+        fn decoded_len(b64: &str) -> usize {
+            b64.trim_end_matches('=').len() * 3 / 4
+        }
+        match payload {
+            InputPayload::Text { text } => within("text", text.len(), self.text),
+            InputPayload::ImageBase64 { data_b64 } => within("image", decoded_len(data_b64), self.image),
+            InputPayload::AudioBase64 { data_b64 } => within("audio", decoded_len(data_b64), self.audio),
+            InputPayload::MidiBase64 { data_b64 } => within("midi", decoded_len(data_b64), self.midi),
+            InputPayload::TextAndImage { text, image_b64 } => {
+                within("text", text.len(), self.text)?;
+                within("image", decoded_len(image_b64), self.image)
+            }
+        }
+    }
+}
+
+/// `InputTooLarge` when `size` bytes of `input` exceed `limit`.
+fn within(input: &'static str, size: usize, limit: usize) -> std::result::Result<(), ConvertError> {
+    if size > limit { Err(ConvertError::InputTooLarge { input, size, limit }) } else { Ok(()) }
 }
 
 /* ------------------------------------
@@ -69,56 +502,281 @@ pub struct TransformOpts {
 -------------------------------------*/
 
 pub fn handle_convert(req: ConvertRequest) -> Result<ConvertResponse> {
+    handle_convert_with_progress(req, None)
+}
+
+/// `handle_convert` reporting the finished fraction (0..1) of the conversion to `progress`:
+/// melody building (image tiling) covers the first part, rendering the rest.
+pub fn handle_convert_with_progress(req: ConvertRequest, progress: Option<&dyn Fn(f32)>) -> Result<ConvertResponse> {
+    if payload_kind_for(&req.from).is_some() && req.payload.source() != req.from {
+        return Err(ConvertError::MismatchedPayload { from: req.from.clone(), payload: req.payload.kind() }.into());
+    }
+    req.options.limits.check(&req.payload)?;
+    let p = Progress::new(progress);
+    let opts = &req.options;
     match (&*req.from, &*req.to, &req.payload) {
         ("text", "audio", InputPayload::Text { text }) => {
-            let (midi_json, wav) = text_to_audio(text, &req.options)?;
-            Ok(ConvertResponse {
-                artifacts: vec![
-                    OutputArtifact::MidiJsonBase64 { data_b64: B64.encode(midi_json) },
-                    OutputArtifact::WavBase64 { data_b64: B64.encode(wav) },
-                ],
-            })
+            let (midi, wav, features, meta) = text_to_audio(text, opts, p)?;
+            audio_response(midi, &wav, Some(features), Some(meta), opts)
         }
         ("image", "audio", InputPayload::ImageBase64 { data_b64 }) => {
             let bytes = B64.decode(data_b64).context("bad image base64")?;
-            let (midi_json, wav) = image_to_audio(&bytes, &req.options)?;
-            Ok(ConvertResponse {
-                artifacts: vec![
-                    OutputArtifact::MidiJsonBase64 { data_b64: B64.encode(midi_json) },
-                    OutputArtifact::WavBase64 { data_b64: B64.encode(wav) },
-                ],
-            })
+            let (midi, wav, features, meta) = image_to_audio(&bytes, opts, p)?;
+            audio_response(midi, &wav, Some(features), Some(meta), opts)
+        }
+        ("multi", "audio", InputPayload::TextAndImage { text, image_b64 }) => {
+            let bytes = B64.decode(image_b64).context("bad image base64")?;
+            let (midi, wav, features, meta) = multi_to_audio(text, &bytes, opts, p)?;
+            audio_response(midi, &wav, Some(features), Some(meta), opts)
+        }
+        ("midi", "audio", InputPayload::MidiBase64 { data_b64 }) => {
+            let bytes = B64.decode(data_b64).context("bad midi base64")?;
+            let (midi, wav) = midi_to_audio(&bytes, opts, p)?;
+            audio_response(midi, &wav, None, None, opts)
+        }
+
+        ("audio", "audio", InputPayload::AudioBase64 { data_b64 }) => {
+            let bytes = B64.decode(data_b64).context("bad audio base64")?;
+            let (midi, wav, features, meta) = audio_to_audio(&bytes, opts, p)?;
+            audio_response(midi, &wav, Some(features), Some(meta), opts)
         }
 
         // Debug/analytics routes (optional)
         ("audio", "json", InputPayload::AudioBase64 { data_b64 }) => {
             let bytes = B64.decode(data_b64).context("bad audio base64")?;
-            let (mono, sr) = audio_features::decode_wav_to_mono_f32(&bytes)?;
-
-            // building the extractor (parameters ok by default)
-            let fe = AudioFE::new(44_100, 2048, 512);
-
-            // running analysis on your buffer and its real rate
-            let feats = fe.analyze_mono(&mono, sr)?;
-            Ok(ConvertResponse {
-                artifacts: vec![OutputArtifact::Json { data: serde_json::to_value(feats)? }],
-            })
-        }
-        ("text", "json", InputPayload::Text { text }) => {
-            let tf = analyze_text(text)?;
-            Ok(ConvertResponse {
-                artifacts: vec![OutputArtifact::Json { data: serde_json::to_value(tf)? }],
-            })
+            convert_audio_to_json(&bytes, opts)
         }
+        ("text", "json", InputPayload::Text { text }) => text_json(text),
         ("image", "json", InputPayload::ImageBase64 { data_b64 }) => {
             let bytes = B64.decode(data_b64).context("bad image base64")?;
-            let ife = analyze_image_bytes(&bytes)?;
-            Ok(ConvertResponse {
-                artifacts: vec![OutputArtifact::Json { data: serde_json::to_value(ife)? }],
-            })
+            convert_image_to_json(&bytes, opts)
         }
 
-        _ => Err(anyhow!("unsupported conversion: {} -> {}", req.from, req.to)),
+        _ => Err(ConvertError::Unsupported { from: req.from.clone(), to: req.to.clone() }.into()),
+    }
+}
+
+/* =========================
+   Typed entry points
+   ========================= */
+
+// Same routes as `handle_convert`, for Rust callers that already hold the input
+// (raw bytes, no base64, no `from`/`to` strings). Inputs are checked against `opts.limits`.
+
+/// Text -> audio (MIDI JSON + audio, features with `include_features`).
+pub fn convert_text_to_audio(text: &str, opts: &TransformOpts) -> Result<ConvertResponse> {
+    within("text", text.len(), opts.limits.text)?;
+    let (midi, wav, features, meta) = text_to_audio(text, opts, Progress::new(None))?;
+    audio_response(midi, &wav, Some(features), Some(meta), opts)
+}
+
+/// The melody of the text -> audio route and the synth settings it renders with, without
+/// rendering (`dry_run` doesn't apply): adjust the notes or the style, then render with
+/// `melody_synth::render_wav_bytes_styled`. Program, key and drum hits are filled in as in
+/// the route's MIDI JSON.
+pub fn generate_text_melody(text: &str, opts: &TransformOpts) -> Result<(MonophonicMidi, StyleParams)> {
+    let (tf, mut sty) = text_style(text, opts)?;
+    let mut m = text_notes(text, &tf, &mut sty, opts)?;
+    let style = finish_melody(&mut m, &sty)?;
+    Ok((m, style))
+}
+
+/// Image (PNG/JPEG/... bytes) -> audio.
+pub fn convert_image_to_audio(img_bytes: &[u8], opts: &TransformOpts) -> Result<ConvertResponse> {
+    within("image", img_bytes.len(), opts.limits.image)?;
+    let (midi, wav, features, meta) = image_to_audio(img_bytes, opts, Progress::new(None))?;
+    audio_response(midi, &wav, Some(features), Some(meta), opts)
+}
+
+/// Text + image -> one audio piece blending both.
+pub fn convert_multi_to_audio(text: &str, img_bytes: &[u8], opts: &TransformOpts) -> Result<ConvertResponse> {
+    within("text", text.len(), opts.limits.text)?;
+    within("image", img_bytes.len(), opts.limits.image)?;
+    let (midi, wav, features, meta) = multi_to_audio(text, img_bytes, opts, Progress::new(None))?;
+    audio_response(midi, &wav, Some(features), Some(meta), opts)
+}
+
+/// Standard MIDI file -> audio.
+pub fn convert_midi_to_audio(mid_bytes: &[u8], opts: &TransformOpts) -> Result<ConvertResponse> {
+    within("midi", mid_bytes.len(), opts.limits.midi)?;
+    let (midi, wav) = midi_to_audio(mid_bytes, opts, Progress::new(None))?;
+    audio_response(midi, &wav, None, None, opts)
+}
+
+/// WAV -> audio following the source.
+pub fn convert_audio_to_audio(wav_bytes: &[u8], opts: &TransformOpts) -> Result<ConvertResponse> {
+    within("audio", wav_bytes.len(), opts.limits.audio)?;
+    let (midi, wav, features, meta) = audio_to_audio(wav_bytes, opts, Progress::new(None))?;
+    audio_response(midi, &wav, Some(features), Some(meta), opts)
+}
+
+/// WAV -> `AudioFeatures` JSON.
+pub fn convert_audio_to_json(wav_bytes: &[u8], opts: &TransformOpts) -> Result<ConvertResponse> {
+    within("audio", wav_bytes.len(), opts.limits.audio)?;
+    // peak-preserving downmix keeps transients for the onset detection
+    let (mut mono, sr) = audio_features::decode_wav_with(wav_bytes, audio_features::DownmixMode::Max)?;
+    if opts.trim_silence {
+        audio_features::trim_silence(&mut mono, SILENCE_THRESHOLD_DB);
+    }
+
+    // building the extractor at the file's own rate (defaults unless the caller picked frame/hop)
+    let frame = opts.frame_size.unwrap_or(2048);
+    if frame > MAX_FRAME_SIZE {
+        return Err(anyhow!("frame_size must be at most {MAX_FRAME_SIZE} (got {frame})"));
+    }
+    let fe = AudioFE::try_new(sr, frame, opts.hop_size.unwrap_or(512))?;
+
+    // running analysis on your buffer and its real rate
+    let feats = fe.analyze_mono(&mono, sr)?;
+    Ok(ConvertResponse {
+        artifacts: vec![OutputArtifact::Json { data: serde_json::to_value(feats)? }],
+        meta: None,
+    })
+}
+
+/// Text -> `TextFeatures` JSON.
+pub fn convert_text_to_json(text: &str, opts: &TransformOpts) -> Result<ConvertResponse> {
+    within("text", text.len(), opts.limits.text)?;
+    text_json(text)
+}
+
+fn text_json(text: &str) -> Result<ConvertResponse> {
+    let tf = analyze_text(text)?;
+    Ok(ConvertResponse {
+        artifacts: vec![OutputArtifact::Json { data: serde_json::to_value(tf)? }],
+        meta: None,
+    })
+}
+
+/// Image -> `ImageFeatures` JSON.
+pub fn convert_image_to_json(img_bytes: &[u8], opts: &TransformOpts) -> Result<ConvertResponse> {
+    within("image", img_bytes.len(), opts.limits.image)?;
+    let thumb = opts.thumb.unwrap_or(DEFAULT_THUMB);
+    if !(MIN_THUMB..=MAX_THUMB).contains(&thumb) {
+        return Err(anyhow!("thumb must be within {MIN_THUMB}..={MAX_THUMB} (got {thumb})"));
+    }
+    let mode = if opts.accurate { AnalyzeMode::Accurate(thumb) } else { AnalyzeMode::Fast(thumb) };
+    let ife = analyze_image_bytes_mode(img_bytes, opts.image_background.unwrap_or(DEFAULT_BACKGROUND), mode)?;
+    Ok(ConvertResponse {
+        artifacts: vec![OutputArtifact::Json { data: serde_json::to_value(ife)? }],
+        meta: None,
+    })
+}
+
+/// MIDI JSON + audio artifacts (+ the SMF with `midi_file`), then the features when
+/// the caller asked for them (a dry run has only the features).
+fn audio_response(
+    midi: MidiExport,
+    wav: &[u8],
+    features: Option<serde_json::Value>,
+    meta: Option<ConvertMeta>,
+    opts: &TransformOpts,
+) -> Result<ConvertResponse> {
+    if opts.dry_run {
+        let artifacts = features.map(|data| OutputArtifact::Json { data }).into_iter().collect();
+        return Ok(ConvertResponse { artifacts, meta });
+    }
+    let mut artifacts = vec![
+        OutputArtifact::MidiJsonBase64 { data_b64: B64.encode(midi.json) },
+        audio_artifact(wav, opts.audio_codec)?,
+    ];
+    if let Some(smf) = midi.smf {
+        artifacts.push(OutputArtifact::MidiBase64 { data_b64: B64.encode(smf) });
+    }
+    if let Some(png) = midi.png {
+        artifacts.push(OutputArtifact::PngBase64 { data_b64: B64.encode(png) });
+    }
+    if let (true, Some(data)) = (opts.include_features, features) {
+        artifacts.push(OutputArtifact::Json { data });
+    }
+    Ok(ConvertResponse { artifacts, meta })
+}
+
+/// One gain on the rendered samples towards `target` LUFS, before they are encoded:
+/// cuts are not limited, boosts stop at `MAX_LOUDNESS_GAIN_DB` and at
+/// `LOUDNESS_PEAK_CEILING`. Audio below the BS.1770 gate (silence) is left as is.
+fn normalize_loudness(mono: &mut [f32], sr: u32, target: f32) {
+    let lufs = audio_features::integrated_lufs(mono, sr);
+    if lufs <= audio_features::loudness::LUFS_FLOOR {
+        return;
+    }
+    let mut gain = 10f32.powf((target - lufs).min(MAX_LOUDNESS_GAIN_DB) / 20.0);
+    let peak = mono.iter().fold(0.0f32, |m, &x| m.max(x.abs()));
+    if gain > 1.0 && peak > 0.0 {
+        gain = gain.min((LOUDNESS_PEAK_CEILING / peak).max(1.0));
+    }
+    for x in mono.iter_mut() {
+        *x *= gain;
+    }
+}
+
+/// The rendered WAV as the artifact for `codec`.
+fn audio_artifact(wav: &[u8], codec: AudioCodec) -> Result<OutputArtifact> {
+    Ok(match codec {
+        AudioCodec::Wav => OutputArtifact::WavBase64 { data_b64: B64.encode(wav) },
+        AudioCodec::Flac => OutputArtifact::FlacBase64 { data_b64: B64.encode(wav_to_flac(wav)?) },
+    })
+}
+
+/// `n` takes of the same request, with seeds `0..n` (take 0 equals an unseeded
+/// `handle_convert`). Take `k` is reproducible on its own via `options.seed = Some(k)`.
+pub fn handle_convert_variations(req: ConvertRequest, n: usize) -> Result<Vec<ConvertResponse>> {
+    (0..n as u64)
+        .map(|k| {
+            let mut r = req.clone();
+            r.options.seed = Some(k);
+            handle_convert(r).with_context(|| format!("variation {k}"))
+        })
+        .collect()
+}
+
+/// Same as `handle_convert`, but identical requests are served from `cache`.
+/// Errors are not cached.
+pub fn handle_convert_cached(req: ConvertRequest, cache: &ConvertCache) -> Result<ConvertResponse> {
+    // before hashing: an oversized payload is rejected without reading it all
+    req.options.limits.check(&req.payload)?;
+    let Some(key) = ConvertCache::key(&req) else { return handle_convert(req) };
+    if let Some(resp) = cache.get(&key) {
+        return Ok(resp);
+    }
+    let resp = handle_convert(req)?;
+    cache.insert(key, resp.clone());
+    Ok(resp)
+}
+
+/// Share of the progress range spent before rendering starts.
+const RENDER_PROGRESS_START: f32 = 0.2;
+
+/// Progress sink of one conversion; `stage` maps a sub-step onto its slice of 0..1.
+#[derive(Clone, Copy)]
+struct Progress<'a> {
+    cb: Option<&'a dyn Fn(f32)>,
+    lo: f32,
+    hi: f32,
+}
+
+impl<'a> Progress<'a> {
+    fn new(cb: Option<&'a dyn Fn(f32)>) -> Self {
+        Self { cb, lo: 0.0, hi: 1.0 }
+    }
+
+    fn report(&self, x: f32) {
+        if let Some(cb) = self.cb {
+            cb(self.lo + (self.hi - self.lo) * x.clamp(0.0, 1.0));
+        }
+    }
+
+    fn stage(&self, lo: f32, hi: f32) -> Progress<'a> {
+        let span = self.hi - self.lo;
+        Progress { cb: self.cb, lo: self.lo + span * lo, hi: self.lo + span * hi }
+    }
+
+    fn melody(&self) -> Progress<'a> {
+        self.stage(0.0, RENDER_PROGRESS_START)
+    }
+
+    fn render(&self) -> Progress<'a> {
+        self.stage(RENDER_PROGRESS_START, 1.0)
     }
 }
 
@@ -126,24 +784,92 @@ pub fn handle_convert(req: ConvertRequest) -> Result<ConvertResponse> {
    Style deduction (auto)
 -------------------------------------*/
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 struct AutoStyle {
     tempo: u32,
+    tempo_end: Option<u32>, // accelerando/ritardando target (None = constant tempo)
     root_midi: i32,
     scale: ScaleKind,
     layering: Vec<Osc>,
+    // (beat, stack) from there on: per-section layering of long texts
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    layering_changes: LayeringChanges,
     polyphony: usize,   // 1..3
     swing: f32,         // 0..0.35
     humanize: f32,      // 0..0.4
     percussion: bool,
     jumpiness: f32,     // 0..1 (melodic leapiness)
+    tremolo_depth: f32, // 0..1 (0 = off)
+    drum_pattern: Option<DrumPattern>,
+    noise_color: NoiseColor, // snare/hat noise
+    scale_arc: Option<ScaleArc>, // mode change partway through (text only)
+    velocity_curve: VelocityCurve,
+    portamento_sec: f32, // 0 = no glide
+    unison_cents: f32,   // unison spread (0 = single voice per layer)
+    seed: u64,           // variation index (0 = default take)
+    degree_range: i32,   // walk stays within ±degree_range scale degrees
+    chromatic: bool,     // chromatic approach tones
+    count_in_bars: u32,  // metronome bars before the music
+    time_sig: (u8, u8),  // drum meter
+    mode: StyleMode,
+}
+
+/// (start beat, layer stack) pairs, as `StyleParams::layering_changes`.
+type LayeringChanges = Vec<(f32, Vec<Osc>)>;
+
+/// The melody switches to `scale` after fraction `at` (0..1) of its notes.
+#[derive(Clone, Copy, Debug, Serialize)]
+struct ScaleArc {
+    at: f32,
+    scale: ScaleKind,
+}
+
+/// Arrangement family the source asks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum StyleMode {
+    /// Melodic line as deduced from the features.
+    Melodic,
+    /// Drone for very low-energy sources: long overlapping notes, slow pads, heavy reverb, no drums.
+    Ambient,
+}
+
+/// Text richness below which the piece turns ambient.
+const AMBIENT_RICHNESS_MAX: f32 = 0.1;
+/// Image colorfulness and luma contrast below which (both) the piece turns ambient.
+const AMBIENT_COLORFULNESS_MAX: f32 = 5.0;
+const AMBIENT_CONTRAST_MAX: f32 = 0.05;
+/// Ambient notes start every `AMBIENT_NOTE_BEATS` and ring `AMBIENT_OVERLAP` times that long.
+const AMBIENT_NOTE_BEATS: f32 = 2.0;
+const AMBIENT_OVERLAP: f32 = 1.5;
+/// Image tiles per ambient note (four eighths become one held note: same piece length).
+const AMBIENT_TILES: usize = 4;
+const AMBIENT_MAX_TEMPO: u32 = 72;
+const AMBIENT_REVERB: f32 = 0.6;
+
+/// Switch a deduced style to the ambient arrangement.
+fn make_ambient(sty: &mut AutoStyle) {
+    sty.mode = StyleMode::Ambient;
+    sty.tempo = sty.tempo.min(AMBIENT_MAX_TEMPO);
+    sty.layering = vec![Osc::Sine, Osc::Saw, Osc::Sine];
+    sty.polyphony = 2;
+    sty.swing = 0.0;
+    sty.percussion = false;
+    sty.drum_pattern = None;
+    // soft kit should drums be forced back on
+    sty.noise_color = NoiseColor::Pink;
+    sty.jumpiness = sty.jumpiness.min(0.3);
+    sty.tremolo_depth = 0.0;
+    sty.velocity_curve = VelocityCurve::Linear;
+    sty.portamento_sec = 0.0;
+    sty.unison_cents = sty.unison_cents.max(8.0);
 }
 
 fn clamp_range(x: f32, lo: f32, hi: f32) -> f32 { x.max(lo).min(hi) }
 
 fn style_from_text(tf: &TextFeatures) -> AutoStyle {
-    // tempo ^ with phonetic density
-    let tempo = (95.0 + 35.0 * (tf.syllables_per_word - 1.0).clamp(0.0, 1.5)).round() as u32;
+    // tempo ^ with phonetic density, and with SHOUTING
+    let tempo = (95.0 + 35.0 * (tf.syllables_per_word - 1.0).clamp(0.0, 1.5) + 25.0 * tf.caps_ratio).round() as u32;
     let scale = if tf.sentiment_score < 0.0 { ScaleKind::Minor } else { ScaleKind::Major };
     let root_midi = 60;
 
@@ -160,16 +886,57 @@ fn style_from_text(tf: &TextFeatures) -> AutoStyle {
 
     let swing = (tf.punctuation_ratio * 1.5).clamp(0.0, 0.30);
     let humanize = (0.15 + richness * 0.25).clamp(0.0, 0.4);
-    let percussion = richness > 0.5;
-    let jumpiness = (0.3 + tf.sentiment_score.abs() * 0.5).clamp(0.0, 1.0);
-
-    AutoStyle { tempo, root_midi, scale, layering, polyphony, swing, humanize, percussion, jumpiness }
+    // stopword-heavy text carries less information -> calmer arrangement, no drums
+    let calm = tf.stopword_ratio > 0.55;
+    let percussion = richness > 0.5 && !calm;
+    // emoji-heavy text is playful: wider leaps
+    let jumpiness = (0.3 + tf.sentiment_score.abs() * 0.5 + tf.emoji_ratio * 0.4).clamp(0.0, 1.0);
+    // subtle tremolo only for lexically "spread" text (high normalized word entropy)
+    let tremolo_depth = if calm { 0.0 } else { ((tf.word_entropy_bits - 0.85) * 0.8).clamp(0.0, 0.12) };
+    let drum_pattern = None;
+    // long, flowing words (few hard stops, no shouting) lilt in three
+    let time_sig = if tf.syllables_per_word >= 2.2 && tf.punctuation_ratio < 0.03 && tf.caps_ratio < 0.1 { (3, 4) } else { (4, 4) };
+    // strongly emotional text gets exaggerated dynamics
+    let velocity_curve = if tf.sentiment_score.abs() > 0.5 {
+        VelocityCurve::Exponential(1.0 + tf.sentiment_score.abs())
+    } else {
+        VelocityCurve::Linear
+    };
+    let portamento_sec = 0.0;
+    let unison_cents = 0.0;
+
+    let mut sty = AutoStyle {
+        tempo, tempo_end: None, root_midi, scale, layering, polyphony, swing, humanize, percussion, jumpiness,
+        tremolo_depth, drum_pattern, velocity_curve, portamento_sec, unison_cents, seed: 0,
+        degree_range: DEFAULT_DEGREE_RANGE, chromatic: false, count_in_bars: 0, time_sig,
+        mode: StyleMode::Melodic,
+        noise_color: NoiseColor::White,
+        scale_arc: None,
+        layering_changes: Vec::new(),
+    };
+    // repetitive, near-empty text has too little to say for a melody: drone instead
+    if richness < AMBIENT_RICHNESS_MAX {
+        make_ambient(&mut sty);
+    }
+    sty
 }
 
 fn style_from_image(fe: &ImageFeatures) -> AutoStyle {
     let tempo = (80.0 + 60.0 * fe.hsv_mean_v).round() as u32;
     let root_midi = 48 + ((fe.hsv_mean_h / 360.0) * 24.0).round() as i32;
-    let scale = if fe.hsv_mean_v < 0.5 || fe.hsv_mean_s < 0.25 { ScaleKind::Minor } else { ScaleKind::Major };
+    let scale = if fe.colorfulness_hs > 20.0 && fe.colorfulness_hs < 60.0 && fe.contrast_luma_std > 0.25 {
+        ScaleKind::Blues      // moderately colorful, punchy contrast
+    } else if fe.contrast_luma_std < 0.12 && fe.hsv_mean_v > 0.7 {
+        ScaleKind::WholeTone  // bright, hazy, low-contrast -> dreamy
+    } else if fe.color_temperature < -0.1 {
+        ScaleKind::Minor      // clearly cool palette
+    } else if fe.color_temperature > 0.1 {
+        ScaleKind::Major      // clearly warm palette
+    } else if fe.hsv_mean_v < 0.5 || fe.hsv_mean_s < 0.25 {
+        ScaleKind::Minor
+    } else {
+        ScaleKind::Major
+    };
 
     let layering = if fe.hsv_mean_s < 0.3 {
         vec![Osc::Sine, Osc::Saw]          // soft
@@ -186,55 +953,301 @@ fn style_from_image(fe: &ImageFeatures) -> AutoStyle {
     let humanize = (0.2 + fe.contrast_luma_std * 0.4).clamp(0.0, 0.4);
     let percussion = fe.edge_density > 0.12 || fe.contrast_luma_std > 0.15;
     let jumpiness = (0.25 + fe.hsv_mean_s * 0.6).clamp(0.0, 1.0);
+    let tremolo_depth = 0.0;
+    // busy, edgy images get a busier groove
+    let drum_pattern = if fe.edge_density > 0.25 { Some(DrumPattern::busy()) } else { None };
+    let velocity_curve = VelocityCurve::Linear;
+    // smooth, soft-edged images glide gently between notes
+    let portamento_sec = if fe.edge_density < 0.05 { 0.04 } else { 0.0 };
+    // very varied palettes get a fatter, detuned unison (6..12 cents)
+    let unison_cents = if color_var > 0.5 { 6.0 + 12.0 * (color_var - 0.5) } else { 0.0 };
+
+    let mut sty = AutoStyle {
+        tempo, tempo_end: None, root_midi, scale, layering, polyphony, swing, humanize, percussion, jumpiness,
+        tremolo_depth, drum_pattern, velocity_curve, portamento_sec, unison_cents, seed: 0,
+        degree_range: DEFAULT_DEGREE_RANGE, chromatic: false, count_in_bars: 0, time_sig: (4, 4),
+        mode: StyleMode::Melodic,
+        noise_color: NoiseColor::White,
+        scale_arc: None,
+        layering_changes: Vec::new(),
+    };
+    // flat, washed-out images (near gray, little contrast) drone instead
+    if fe.colorfulness_hs < AMBIENT_COLORFULNESS_MAX && fe.contrast_luma_std < AMBIENT_CONTRAST_MAX {
+        make_ambient(&mut sty);
+    }
+    sty
+}
 
-    AutoStyle { tempo, root_midi, scale, layering, polyphony, swing, humanize, percussion, jumpiness }
+/// Pin key/scale/tempo (a pinned tempo is constant) when the caller asked for it;
+/// everything else stays automatic.
+/// Also carries the variation seed and the melodic range options over.
+fn apply_overrides(sty: &mut AutoStyle, opts: &TransformOpts) -> Result<()> {
+    sty.seed = opts.seed.unwrap_or(0);
+    if let Some(root) = opts.root_midi { sty.root_midi = root.clamp(0, 127); }
+    if let Some(scale) = opts.scale {
+        sty.scale = scale;
+        sty.scale_arc = None;
+    }
+    if let Some(bpm) = opts.tempo_bpm {
+        sty.tempo = bpm.clamp(20, 300);
+        sty.tempo_end = None;
+    }
+    if let Some(oct) = opts.range_octaves { sty.degree_range = 7 * oct.clamp(1, MAX_RANGE_OCTAVES) as i32; }
+    sty.chromatic = opts.chromatic;
+    sty.count_in_bars = opts.count_in_bars.min(MAX_COUNT_IN_BARS);
+    if let Some(layering) = layering_override(opts)? { sty.layering = layering; }
+    if let Some(color) = opts.noise_color { sty.noise_color = color; }
+    Ok(())
+}
+
+/// `TransformOpts.layering`, validated.
+fn layering_override(opts: &TransformOpts) -> Result<Option<Vec<Osc>>> {
+    match &opts.layering {
+        Some(l) if l.is_empty() => Err(anyhow!("layering must contain at least one oscillator")),
+        Some(l) if l.len() > MAX_LAYERS => Err(anyhow!("layering may stack at most {MAX_LAYERS} oscillators (got {})", l.len())),
+        l => Ok(l.clone()),
+    }
 }
 
 /* ------------------------------------
    Text -> Audio (zero-knobs)
 -------------------------------------*/
 
-fn text_to_audio(text: &str, opts: &TransformOpts) -> Result<(Vec<u8>, Vec<u8>)> {
+/// Returns (midi, wav, {features, style} json, meta)
+fn text_to_audio(text: &str, opts: &TransformOpts, progress: Progress) -> Result<(MidiExport, Vec<u8>, serde_json::Value, ConvertMeta)> {
+    let (tf, mut sty) = text_style(text, opts)?;
+    let features = serde_json::json!({ "features": tf, "style": sty });
+    if opts.dry_run {
+        return Ok(style_preview(features, &sty));
+    }
+    let mut m = text_notes(text, &tf, &mut sty, opts)?;
+
+    // 5) serious rendering (layering, poly, swing, humanize, percussion)
+    let sr = output_sample_rate(opts)?;
+    let (wav, secs) = render_auto(&mut m, &sty, sr, opts, progress.render())?;
+    let midi = midi_export(&m, &sty, opts)?;
+    let meta = convert_meta(&m, &sty, secs);
+    Ok((midi, wav, features, meta))
+}
+
+fn text_style(text: &str, opts: &TransformOpts) -> Result<(TextFeatures, AutoStyle)> {
     let tf = analyze_text(text)?;
-    let sty = style_from_text(&tf);
+    let mut sty = style_from_text(&tf);
+    // text that brightens sentence by sentence speeds up
+    if sentiment_trend(&tf.sentiment_trajectory) >= ACCEL_MIN_TREND {
+        sty.tempo_end = Some(((sty.tempo as f32 * ACCEL_RATIO).round() as u32).min(300));
+    }
+    // ... and a clear turn of mood changes the mode there
+    if let Some((at, from, to)) = sentiment_arc(text, &tf.sentiment_trajectory) {
+        sty.scale = from;
+        sty.scale_arc = Some(ScaleArc { at, scale: to });
+    }
+    apply_overrides(&mut sty, opts)?;
+    Ok((tf, sty))
+}
+
+/// Sentences needed for a sentiment trend, and the rise per sentence that ramps the tempo.
+const ACCEL_MIN_SENTENCES: usize = 3;
+const ACCEL_MIN_TREND: f32 = 0.1;
+/// Accelerando end tempo relative to the start.
+const ACCEL_RATIO: f32 = 1.25;
+/// Beats between the tempo steps of a ramp (one bar).
+const TEMPO_RAMP_STEP_BEATS: f32 = 4.0;
+
+/// Smallest change of mean sentence sentiment across a turn that switches the mode
+/// (one lexicon word).
+const ARC_MIN_SWING: f32 = 0.2;
+
+/// Where the text's mood turns: the sentence split with the largest jump between the mean
+/// sentiment before and after it, when the two sides have opposite signs and differ by at
+/// least `ARC_MIN_SWING`. Returns the turn as a fraction of the words, with the scale
+/// before (minor while negative) and after it.
+fn sentiment_arc(text: &str, trajectory: &[f32]) -> Option<(f32, ScaleKind, ScaleKind)> {
+    let words: Vec<usize> = split_sentences(text).iter().map(|s| s.split_whitespace().count()).collect();
+    let total: usize = words.iter().sum();
+    if trajectory.len() < 2 || words.len() != trajectory.len() || total == 0 { return None; }
+    let mean = |xs: &[f32]| xs.iter().sum::<f32>() / xs.len() as f32;
+    let (k, before, after) = (1..trajectory.len())
+        .map(|k| (k, mean(&trajectory[..k]), mean(&trajectory[k..])))
+        .filter(|&(_, b, a)| b * a < 0.0 && (a - b).abs() >= ARC_MIN_SWING)
+        .max_by(|x, y| (x.2 - x.1).abs().total_cmp(&(y.2 - y.1).abs()))?;
+    let scale = |x: f32| if x < 0.0 { ScaleKind::Minor } else { ScaleKind::Major };
+    let at = words[..k].iter().sum::<usize>() as f32 / total as f32;
+    Some((at, scale(before), scale(after)))
+}
+
+/// Least-squares slope of the sentence sentiment over the sentence index
+/// (0 below `ACCEL_MIN_SENTENCES` sentences).
+fn sentiment_trend(scores: &[f32]) -> f32 {
+    let n = scores.len();
+    if n < ACCEL_MIN_SENTENCES { return 0.0; }
+    let mean_x = (n - 1) as f32 / 2.0;
+    let mean_y = scores.iter().sum::<f32>() / n as f32;
+    let (mut cov, mut var) = (0.0, 0.0);
+    for (i, y) in scores.iter().enumerate() {
+        let dx = i as f32 - mean_x;
+        cov += dx * (y - mean_y);
+        var += dx * dx;
+    }
+    cov / var
+}
 
-    // 1) target duration from text (zero-knobs)
+/// Steps 1-4 of text -> audio, quantized and bounded like the rendered piece. A sectioned
+/// piece sets `sty`'s tempo to its opening one and its per-section layering.
+fn text_notes(text: &str, tf: &TextFeatures, sty: &mut AutoStyle, opts: &TransformOpts) -> Result<MonophonicMidi> {
+    // long texts get one section per chunk so the piece follows the text's mood shifts
+    let desired_seconds = text_duration(tf, opts);
+    let chunks = split_text_sections(text);
+    let mut m = if chunks.len() >= 2 {
+        let (m, layering_changes) = sectioned_text_melody(&chunks, tf.n_words, desired_seconds, opts)?;
+        sty.tempo = m.tempo_bpm;
+        // the sections' own tempos replace the whole text's ramp
+        sty.tempo_end = None;
+        if let Some((_, first)) = layering_changes.first() {
+            sty.layering = first.clone();
+        }
+        sty.layering_changes = layering_changes.into_iter().skip(1).collect();
+        m
+    } else {
+        text_melody(text, tf, sty, desired_seconds)
+    };
+
+    shape_timeline(&mut m, opts)?;
+    if let Some(end) = sty.tempo_end {
+        m.set_tempo_ramp(end, TEMPO_RAMP_STEP_BEATS);
+    }
+    Ok(m)
+}
+
+/// 1) target duration from text (zero-knobs)
+fn text_duration(tf: &TextFeatures, opts: &TransformOpts) -> f32 {
     let spw = opts.text_sec_per_word.unwrap_or(0.50);
     let min_s = opts.text_min_sec.unwrap_or(10.0);
     let max_s = opts.text_max_sec.unwrap_or(180.0);
-    let desired_seconds = clamp_range(6.0 + tf.n_words as f32 * spw, min_s, max_s);
+    clamp_range(6.0 + tf.n_words as f32 * spw, min_s, max_s)
+}
 
+/// Texts with at least this many words are split into sections.
+const LONG_TEXT_WORDS: usize = 120;
+/// Each section gathers whole paragraphs/sentences until it has this many words.
+const SECTION_MIN_WORDS: usize = 40;
+/// Breath between consecutive sections (beats).
+const SECTION_GAP_BEATS: f32 = 0.5;
+
+/// Paragraphs (or sentences, for a single paragraph) grouped into sections of
+/// at least `SECTION_MIN_WORDS` words; a short leftover joins the previous section.
+/// Returns a single chunk for texts shorter than `LONG_TEXT_WORDS`.
+fn split_text_sections(text: &str) -> Vec<String> {
+    let n_words = text.split_whitespace().count();
+    if n_words < LONG_TEXT_WORDS { return vec![text.to_string()]; }
+
+    let paragraphs = split_paragraphs(text);
+    let units = if paragraphs.len() >= 2 { paragraphs } else { split_sentences(text) };
+
+    let mut sections: Vec<String> = Vec::new();
+    let mut cur = String::new();
+    for u in units {
+        if !cur.is_empty() { cur.push(' '); }
+        cur.push_str(u);
+        if cur.split_whitespace().count() >= SECTION_MIN_WORDS {
+            sections.push(std::mem::take(&mut cur));
+        }
+    }
+    if !cur.is_empty() {
+        match sections.last_mut() {
+            Some(last) if cur.split_whitespace().count() < SECTION_MIN_WORDS / 2 => {
+                last.push(' ');
+                last.push_str(&cur);
+            }
+            _ => sections.push(cur),
+        }
+    }
+    sections
+}
+
+/// One section per chunk, each styled from its own features (tempo, scale, rhythm,
+/// leaps, layering), with lengths scaled so the sum matches `desired_seconds`. Section
+/// tempos become `tempo_changes` at the section starts; returns the melody and each
+/// section's (start beat, layering) where it differs from the previous one. Drums and
+/// the rest of the render follow the whole text's style.
+fn sectioned_text_melody(chunks: &[String], total_words: usize, desired_seconds: f32, opts: &TransformOpts) -> Result<(MonophonicMidi, LayeringChanges)> {
+    let mut m: Option<MonophonicMidi> = None;
+    let mut layering: LayeringChanges = Vec::new();
+    for chunk in chunks {
+        let ctf = analyze_text(chunk)?;
+        let mut csty = style_from_text(&ctf);
+        apply_overrides(&mut csty, opts)?;
+        let share = ctf.n_words as f32 / total_words.max(1) as f32;
+        let part = text_melody(chunk, &ctf, &csty, (desired_seconds * share).max(2.0));
+        let start = match m.as_mut() {
+            None => {
+                m = Some(part);
+                0.0
+            }
+            Some(acc) => {
+                let start = acc.end_beats() + SECTION_GAP_BEATS;
+                let bpm = acc.tempo_changes.last().map_or(acc.tempo_bpm, |c| c.1);
+                if part.tempo_bpm != bpm {
+                    acc.tempo_changes.push((start, part.tempo_bpm));
+                }
+                acc.append(&part, SECTION_GAP_BEATS);
+                start
+            }
+        };
+        if layering.last().is_none_or(|(_, l)| *l != csty.layering) {
+            layering.push((start, csty.layering));
+        }
+    }
+    let m = m.ok_or_else(|| anyhow!("no text sections"))?;
+    Ok((m, layering))
+}
+
+/// Steps 2-4 of text -> audio: random walk and rhythms over `desired_seconds` -> melody.
+fn text_melody(text: &str, tf: &TextFeatures, sty: &AutoStyle, desired_seconds: f32) -> MonophonicMidi {
     // 2) number of musical "events" (estimated)
     //    (keeping the random-walk idea, but using variations)
     let total_beats = desired_seconds * (sty.tempo as f32) / 60.0;
-    let approx_note_len_beats = (4.0 / (tf.syllables_total as f32 / 12.0 + 1.0)).clamp(0.25, 1.0);
+    let approx_note_len_beats = if sty.mode == StyleMode::Ambient {
+        AMBIENT_NOTE_BEATS
+    } else {
+        (4.0 / (tf.syllables_total as f32 / 12.0 + 1.0)).clamp(0.25, 1.0)
+    };
     let n_base = (total_beats / approx_note_len_beats).ceil().max(12.0) as usize;
 
     // 3) unit curve: random walk with "jumpiness" + small octave hops
     let step_span = (1.0 + 6.0 * sty.jumpiness).round() as i32; // 1..7
+    let shift = seed_shift(sty.seed);
     let mut degs: Vec<i32> = Vec::with_capacity((n_base as f32 * 1.2) as usize);
+    let range = sty.degree_range;
     let mut cur = 0;
     for i in 0..n_base {
-        let dir = if i % 4 == 0 { 0 } else if (i & 1) == 0 { 1 } else { -1 };
+        let k = i + shift; // the seed moves the walk's direction pattern
+        let dir = if k.is_multiple_of(4) { 0 } else if (k & 1) == 0 { 1 } else { -1 };
         let step = dir * ((1 + (i as i32 % step_span)).min(step_span));
-        cur = (cur + step).clamp(-12, 12);
+        cur = (cur + step).clamp(-range, range);
 
         // Ocasionally: octave jumps (up if the sentiment is positive and down if sentiment is negative)
         if i % 23 == 0 && sty.humanize > 0.1 {
             let oct = if tf.sentiment_score >= 0.0 { 12 } else { -12 };
-            cur = (cur + oct).clamp(-12, 12);
+            cur = (cur + oct).clamp(-range, range);
         }
         degs.push(cur);
 
         // small motive turn in the beginning of the phrase (about every ~20 units)
         if i % 20 == 0 && i > 0 && sty.jumpiness > 0.35 {
-            let a = (cur - 2).clamp(-12, 12);
+            let a = (cur - 2).clamp(-range, range);
             let b = cur;
             degs.push(a);
             degs.push(b);
         }
     }
 
+    // 3b) recurring keywords replay their phrase, the top one carries the hook;
+    //     sentence ends rise (questions) or fall
+    apply_keyword_motifs(&mut degs, text, &tf.keywords);
+    apply_hook(&mut degs, text, tf.keywords.first().map(|(w, _)| w.as_str()));
+    apply_sentence_cadences(&mut degs, text, range);
+
     // 4) variable rhythms (small pauses and patterns) - like for the image
     //    choosing the pattern by the "punctuation_ratio" (more punctuation => more syncope)
     let rhythms: &[&[f32]] = &[
@@ -243,26 +1256,34 @@ fn text_to_audio(text: &str, opts: &TransformOpts) -> Result<(Vec<u8>, Vec<u8>)>
         &[0.75, 0.25, 0.5, 0.25, 0.25], // "push-pull"
     ];
     let sync_bias = (tf.punctuation_ratio * 10.0).round() as usize; // 0..~3
+    // the prose's phrasing: rests after sentence ends, accents after commas
+    let marks = punctuation_marks(text, degs.len());
     let mut m = MonophonicMidi::new(sty.tempo);
     let mut t = 0.0f32;
-    let mut rstep_idx = 0usize;
 
-    // base velocity, influenced by sentiment
-    let base_vel = (90.0 + 30.0 * tf.sentiment_score).clamp(40.0, 120.0) as u8;
+    // base velocity, influenced by sentiment and pushed up by caps
+    let base_vel = (90.0 + 30.0 * tf.sentiment_score + 25.0 * tf.caps_ratio).clamp(40.0, 120.0) as u8;
 
     for (i, d) in degs.iter().enumerate() {
-        let pat_idx = (sync_bias + i / 32) % rhythms.len();
+        let pat_idx = (sync_bias + shift + i / 32) % rhythms.len();
         let pat = rhythms[pat_idx];
-        let dur_beats = pat[rstep_idx % pat.len()];
-        rstep_idx += 1;
+        let ambient = sty.mode == StyleMode::Ambient;
+        let dur_beats = if ambient { AMBIENT_NOTE_BEATS } else { pat[i % pat.len()] };
 
-        // small occasional pause (breathing)
-        let is_rest = (i % 19 == 0) && (sty.humanize > 0.12);
+        // small occasional pause (breathing); a drone doesn't breathe
+        let is_rest = !ambient && (((i + shift).is_multiple_of(19) && (sty.humanize > 0.12)) || marks[i] == PunctMark::Rest);
         if !is_rest {
-            let pitch = degree_to_midi(sty.root_midi, *d, sty.scale).clamp(0, 127) as u8;
-            // small accents: once every 8 events, hit a little harder
-            let vel = if i % 8 == 0 { (base_vel as i32 + 10).clamp(1, 127) as u8 } else { base_vel };
-            m.push(pitch, t, t + dur_beats, vel);
+            let scale = match sty.scale_arc {
+                Some(arc) if i as f32 >= arc.at * degs.len() as f32 => arc.scale,
+                _ => sty.scale,
+            };
+            let pitch = degree_to_midi(sty.root_midi, *d, scale) as u8;
+            // small accents: once every 8 events and after commas, hit a little harder
+            let accent = i % 8 == 0 || marks[i] == PunctMark::Accent;
+            let vel = if accent { (base_vel as i32 + 10).clamp(1, 127) as u8 } else { base_vel };
+            // ambient notes ring on into the next one
+            let len = if ambient { dur_beats * AMBIENT_OVERLAP } else { dur_beats };
+            m.push(pitch, t, t + len, vel);
         }
         t += dur_beats;
 
@@ -270,47 +1291,250 @@ fn text_to_audio(text: &str, opts: &TransformOpts) -> Result<(Vec<u8>, Vec<u8>)>
         if t >= total_beats { break; }
     }
 
-    // 5) serious rendering (layering, poly, swing, humanize, percussion)
-    let wav = render_wav_bytes_styled(&m, 44_100, &StyleParams {
-        layering: sty.layering,
-        swing: sty.swing,
-        humanize: sty.humanize,
-        polyphony: sty.polyphony,
-        percussion: sty.percussion,
-        scale: sty.scale,
-    })?;
-    let midi_json = serde_json::to_vec(&m)?;
-    Ok((midi_json, wav))
+    if sty.chromatic {
+        apply_chromatic_approach(&mut m, shift);
+    }
+    m
+}
+
+/// Sentence-end rests are at least this many notes apart.
+const PUNCT_REST_MIN_GAP: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PunctMark { None, Rest, Accent }
+
+/// Per-note marks from the punctuation (words map to proportional melody positions):
+/// the note after a word ending a sentence (`.`, `!`, `?`) rests, the note after a
+/// `,`, `;` or `:` is accented. The first note never rests, and rests keep
+/// `PUNCT_REST_MIN_GAP` notes apart, so dense punctuation can't silence the line.
+fn punctuation_marks(text: &str, n_notes: usize) -> Vec<PunctMark> {
+    let mut marks = vec![PunctMark::None; n_notes];
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.is_empty() { return marks; }
+    let mut last_rest: Option<usize> = None;
+    for (wi, w) in words.iter().enumerate() {
+        let pos = (wi + 1) * n_notes / words.len();
+        if pos == 0 || pos >= n_notes { continue; }
+        match w.trim_end_matches(['"', '\'', ')']).chars().last() {
+            Some('.' | '!' | '?') if last_rest.is_none_or(|l| pos >= l + PUNCT_REST_MIN_GAP) => {
+                marks[pos] = PunctMark::Rest;
+                last_rest = Some(pos);
+            }
+            Some(',' | ';' | ':') if marks[pos] == PunctMark::None => marks[pos] = PunctMark::Accent,
+            _ => {}
+        }
+    }
+    marks
+}
+
+/// Notes in a keyword motif.
+const MOTIF_LEN: usize = 3;
+
+/// Each word maps to a spot in the melody (proportional position); when a keyword
+/// comes back, the `MOTIF_LEN` degrees heard at its first occurrence are replayed there.
+fn apply_keyword_motifs(degs: &mut [i32], text: &str, keywords: &[(String, f32)]) {
+    let words: Vec<String> = text.split_whitespace().map(normalize_token).collect();
+    if words.is_empty() || degs.len() < MOTIF_LEN { return; }
+    let mut motifs: Vec<Option<Vec<i32>>> = vec![None; keywords.len()];
+    for (wi, w) in words.iter().enumerate() {
+        let Some(k) = keywords.iter().position(|(kw, _)| kw == w) else { continue };
+        let pos = (wi * degs.len() / words.len()).min(degs.len() - MOTIF_LEN);
+        match &motifs[k] {
+            None => motifs[k] = Some(degs[pos..pos + MOTIF_LEN].to_vec()),
+            Some(m) => degs[pos..pos + MOTIF_LEN].copy_from_slice(m),
+        }
+    }
+}
+
+/// The text's hook: fixed scale degrees (5-3-4-1) overwritten in place, so the length is unchanged.
+const HOOK_MOTIF: [i32; 4] = [4, 2, 3, 0];
+/// The most frequent content word needs at least this many occurrences to get the hook.
+const HOOK_MIN_COUNT: usize = 3;
+
+/// Every occurrence of `word` (the most frequent content word) plays `HOOK_MOTIF`
+/// at its proportional position in the melody.
+fn apply_hook(degs: &mut [i32], text: &str, word: Option<&str>) {
+    let Some(word) = word else { return };
+    let words: Vec<String> = text.split_whitespace().map(normalize_token).collect();
+    if degs.len() < HOOK_MOTIF.len() || words.iter().filter(|w| *w == word).count() < HOOK_MIN_COUNT {
+        return;
+    }
+    for (wi, _) in words.iter().enumerate().filter(|(_, w)| *w == word) {
+        let pos = (wi * degs.len() / words.len()).min(degs.len() - HOOK_MOTIF.len());
+        degs[pos..pos + HOOK_MOTIF.len()].copy_from_slice(&HOOK_MOTIF);
+    }
+}
+
+/// Like intonation: the note where a sentence ends steps up from the previous one for
+/// questions and down for statements (sentences map to proportional melody positions).
+fn apply_sentence_cadences(degs: &mut [i32], text: &str, range: i32) {
+    let n_words = text.split_whitespace().count();
+    if n_words == 0 || degs.len() < 2 { return; }
+    let mut words_so_far = 0usize;
+    for sent in split_sentences(text) {
+        words_so_far += sent.split_whitespace().count();
+        let pos = (words_so_far * degs.len() / n_words).clamp(2, degs.len()) - 1;
+        let step = if is_question(sent) { 1 } else { -1 };
+        degs[pos] = (degs[pos - 1] + step).clamp(-range, range);
+    }
 }
 
 /* ------------------------------------
    Image -> Audio (zero-knobs, no loop)
 -------------------------------------*/
 
-fn image_to_audio(img_bytes: &[u8], _opts: &TransformOpts) -> Result<(Vec<u8>, Vec<u8>)> {
-    use image::{GenericImageView};
-    use palette::{Srgb, IntoColor, Hsv};
-
-    // 1) Load & basic dims
+/// Returns (midi, wav, {features, style} json, meta)
+fn image_to_audio(img_bytes: &[u8], opts: &TransformOpts, progress: Progress) -> Result<(MidiExport, Vec<u8>, serde_json::Value, ConvertMeta)> {
+    // 1) Load (decoded once: the features and the tiling share it)
     let img = image::load_from_memory(img_bytes)?;
+    let bg = opts.image_background.unwrap_or(DEFAULT_BACKGROUND);
+
+    // 2) Global features -> style
+    let ife = analyze_image_on(&img, bg)?;
+    let mut sty = style_from_image(&ife);
+    apply_overrides(&mut sty, opts)?;
+    let features = serde_json::json!({ "features": ife, "style": sty });
+    if opts.dry_run {
+        return Ok(style_preview(features, &sty));
+    }
+
+    let mut m = image_melody(&img, &ife, &sty, opts, progress.melody())?;
+
+    shape_timeline(&mut m, opts)?;
+
+    // 6) Serious rendering with everything
+    let sr = output_sample_rate(opts)?;
+    let (wav, secs) = render_auto(&mut m, &sty, sr, opts, progress.render())?;
+    let midi = midi_export(&m, &sty, opts)?;
+    let meta = convert_meta(&m, &sty, secs);
+    Ok((midi, wav, features, meta))
+}
+
+/// Steps 3-5 of image -> audio: tile grid, walk in `opts.scan_order`, rhythms -> melody.
+fn image_melody(
+    img: &DynamicImage,
+    ife: &ImageFeatures,
+    sty: &AutoStyle,
+    opts: &TransformOpts,
+    progress: Progress,
+) -> Result<MonophonicMidi> {
+    use image::GenericImageView;
+
     let (w, h) = img.dimensions();
     if w == 0 || h == 0 { return Err(anyhow!("empty image")); }
+    if (w as u64 * h as u64) < TINY_IMAGE_PIXELS {
+        return Ok(tiny_image_phrase(ife, sty, opts));
+    }
+    let (_, degs, vels) = image_tile_walk(img, ife, sty, opts, progress);
 
-    // 2) Global features -> style
-    let ife = analyze_image_bytes(img_bytes)?;
-    let sty = style_from_image(&ife);
+    // 5) Building MIDI: note per tile, without pattern loop. Duration per note = 0.5 beat (eighth).
+    let rhythms: &[&[f32]] = &[
+        &[0.5, 0.5, 0.5, 0.5],          // "straight" eighths
+        &[0.25, 0.75, 0.5, 0.5],        // light syncope
+        &[0.75, 0.25, 0.5, 0.25, 0.25], // "push-pull"
+    ];
+    let oh = &ife.edge_orientation_hist;
+    let horizontal_edges = oh[0] > 0.4;                         // calm horizon-like lines -> steady
+    let diagonal_edges = oh[1] + oh[2] + oh[3] + oh[5] + oh[6] + oh[7] > 0.6; // slanted -> syncope
+    // busy texture (high GLCM contrast) -> up to 40% shorter notes
+    let dur_scale = 1.0 - 0.4 * (ife.texture_contrast / 8.0).clamp(0.0, 1.0);
+    let mut m = MonophonicMidi::new(sty.tempo);
+    let mut t = 0.0f32;
+    let mut rpat_idx;
+
+    let shift = seed_shift(sty.seed);
+    for (i, &d) in degs.iter().enumerate() {
+        let pitch = degree_to_midi(sty.root_midi, d, sty.scale) as u8;
+        let vel = vels[i];
+
+        // ambient: one held note per `AMBIENT_TILES` tiles, overlapping the next one
+        if sty.mode == StyleMode::Ambient {
+            if i % AMBIENT_TILES == 0 {
+                m.push(pitch, t, t + AMBIENT_NOTE_BEATS * AMBIENT_OVERLAP, vel);
+                t += AMBIENT_NOTE_BEATS;
+            }
+            continue;
+        }
+
+        // choose pattern by image "agitation" (edge_density) + progress,
+        // then let the dominant edge orientation steady or syncopate it
+        let block = (sty.swing * 10.0) as usize + shift + (i / 32);
+        rpat_idx = if horizontal_edges {
+            if (i / 32) % 2 == 0 { 0 } else { block % rhythms.len() }
+        } else if diagonal_edges {
+            1 + block % (rhythms.len() - 1)
+        } else {
+            block % rhythms.len()
+        };
+        let pat = rhythms[rpat_idx];
+
+        let dur_beats = pat[i % pat.len()] * dur_scale;
+
+        // 5–10% chance of "resting": dropping a note to breathe
+        let is_rest = (i + shift).is_multiple_of(17) && (sty.humanize > 0.15);
+        if !is_rest {
+            m.push(pitch, t, t + dur_beats, vel);
+        }
+        t += dur_beats;
+    }
+    // neighbouring tiles of one color hold their note instead of repeating it
+    m.tie_repeats();
+    // a tiny grid may consist of rests only
+    if m.notes.is_empty() {
+        let pitch = degree_to_midi(sty.root_midi, degs[0], sty.scale) as u8;
+        m.push(pitch, 0.0, 0.5, vels[0]);
+    }
+    if sty.chromatic {
+        apply_chromatic_approach(&mut m, shift);
+    }
+
+    Ok(m)
+}
 
-    // 3) Rezolution duration: #tiles ~ area/(300x300) clamped 250..1500
-    let cells_target = ((w as f32 * h as f32) / (380.0 * 380.0)).clamp(180.0, 950.0);
+/// Walks the tile grid in `scan_order` and returns each note's tile (row, col), scale degree and
+/// velocity; a motif turn adds two notes on its tile.
+fn image_tile_walk(
+    img: &DynamicImage,
+    ife: &ImageFeatures,
+    sty: &AutoStyle,
+    opts: &TransformOpts,
+    progress: Progress,
+) -> (Vec<(u32, u32)>, Vec<i32>, Vec<u8>) {
+    use image::GenericImageView;
+    use palette::{Srgb, IntoColor, Hsv};
+
+    let (w, h) = img.dimensions();
+
+    // 3) Rezolution duration: #tiles ~ area/(380x380) clamped 180..950,
+    //    or ~2 notes per second of `target_seconds`; then scaled by the density
+    let base_cells = match opts.target_seconds {
+        Some(secs) if secs > 0.0 => secs * 2.0,
+        _ => ((w as f32 * h as f32) / (380.0 * 380.0)).clamp(180.0, 950.0),
+    };
+    let density = opts.image_note_density.unwrap_or(1.0).clamp(0.05, 8.0);
+    let (min_notes, max_notes) = image_note_bounds(opts);
+    let cells_target = (base_cells * density).clamp(min_notes as f32, max_notes as f32);
+    // the grid clamps follow the density so they don't undo a sparse/dense request
+    let k = density.sqrt();
     let aspect = w as f32 / h.max(1) as f32;
-    let cols = (cells_target.sqrt() * aspect.sqrt()).round().clamp(16.0, 96.0) as u32;
-    let rows = ((cells_target / cols as f32).round()).clamp(12.0, 96.0) as u32;
+    // never more tiles than pixels along an axis (a thin strip gets a single row/column)
+    let cols = ((cells_target.sqrt() * aspect.sqrt()).round().clamp((16.0 * k).max(1.0), 96.0 * k) as u32).min(w);
+    let mut rows = ((cells_target / cols as f32).round()).clamp((12.0 * k).max(1.0), 96.0 * k) as u32;
+    // explicit bounds win over the grid clamps
+    if ((cols * rows) as usize) < min_notes {
+        rows = (min_notes as u32).div_ceil(cols);
+    }
+    if ((cols * rows) as usize) > max_notes {
+        rows = ((max_notes as u32) / cols).max(1);
+    }
+    let rows = rows.min(h);
     let tile_w = (w as f32 / cols as f32).ceil().max(1.0) as u32;
     let tile_h = (h as f32 / rows as f32).ceil().max(1.0) as u32;
 
-    // 4) Parsing without loop (boustrophedon) + local mapping HSV -> note
-    let rgb = img.to_rgb8();
+    // 4) Parsing without loop (`scan_order`, boustrophedon by default) + local mapping HSV -> note
+    let rgb = to_rgb8_over(img, opts.image_background.unwrap_or(DEFAULT_BACKGROUND));
     let total_notes = (cols * rows) as usize;
+    let mut tiles = Vec::with_capacity(total_notes);
     let mut degs = Vec::with_capacity(total_notes);
     let mut vels = Vec::with_capacity(total_notes);
 
@@ -318,111 +1542,830 @@ fn image_to_audio(img_bytes: &[u8], _opts: &TransformOpts) -> Result<(Vec<u8>, V
     let base_s = ife.hsv_mean_s;
     let base_v = ife.hsv_mean_v;
     let span = (1.0 + 6.0 * sty.jumpiness).round() as i32;
+    let range = sty.degree_range;
 
     let mut cur_degree = 0i32;
+    let mut corners = 0usize;
 
-    for r in 0..rows {
+    let path = opts.scan_order.path(rows, cols);
+    for (i, &(r, cc)) in path.iter().enumerate() {
         let y0 = (r * tile_h).min(h.saturating_sub(1));
         let y1 = ((r + 1) * tile_h).min(h);
-
-        let (start_c, end_c, step_col) = if r % 2 == 0 {
-            (0u32, cols, 1i32)
-        } else {
-            (cols - 1, u32::MAX, -1i32)
-        };
-
-        let mut c = start_c as i32;
-        while c != end_c as i32 {
-            let cc = c as u32;
-            let x0 = (cc * tile_w).min(w.saturating_sub(1));
-            let x1 = ((cc + 1) * tile_w).min(w);
-
-            // subsampling 4x4 px - HSV average
-            let mut sh=0.0; let mut ss=0.0; let mut sv=0.0; let mut cnt=0.0;
-            let mut yy=y0; while yy<y1 {
-                let mut xx=x0; while xx<x1 {
-                    let p = rgb.get_pixel(xx, yy);
-                    let (r8,g8,b8) = (p[0], p[1], p[2]);
-                    let (r,g,b) = (r8 as f32/255.0, g8 as f32/255.0, b8 as f32/255.0);
-                    let hsv: Hsv = Srgb::new(r,g,b).into_color();
-                    sh+=hsv.hue.into_degrees(); ss+=hsv.saturation; sv+=hsv.value; cnt+=1.0;
-                    xx = xx.saturating_add(4);
+        let x0 = (cc * tile_w).min(w.saturating_sub(1));
+        let x1 = ((cc + 1) * tile_w).min(w);
+
+        // subsampling 4x4 px - HSV (and luma) average
+        let mut sh=0.0; let mut ss=0.0; let mut sv=0.0; let mut sl=0.0; let mut cnt=0.0;
+        let mut yy=y0; while yy<y1 {
+            let mut xx=x0; while xx<x1 {
+                let p = rgb.get_pixel(xx, yy);
+                let (r8,g8,b8) = (p[0], p[1], p[2]);
+                let (r,g,b) = (r8 as f32/255.0, g8 as f32/255.0, b8 as f32/255.0);
+                let hsv: Hsv = Srgb::new(r,g,b).into_color();
+                sh+=hsv.hue.into_degrees(); ss+=hsv.saturation; sv+=hsv.value; cnt+=1.0;
+                sl += 0.2126*r + 0.7152*g + 0.0722*b;
+                xx = xx.saturating_add(4);
+            }
+            yy = yy.saturating_add(4);
+        }
+        let (mh, ms, mv) = if cnt>0.0 { (sh/cnt, ss/cnt, sv/cnt) } else { (base_h, base_s, base_v) };
+
+        match opts.image_map {
+            ImageMap::HueSteps => {
+                // mapping: hue diff -> step size, saturation -> extra salt, value -> velocity
+                let dh = (mh - base_h).abs();
+                let hue_push = ((dh / 180.0) * span as f32).round() as i32;
+                let salt = if ms < 0.2 { 0 } else if ms < 0.5 { 1 } else { 2 };
+                let step_deg = (hue_push.min(span) + salt).max(0);
+
+                let dir = if (r + cc).is_multiple_of(2) { 1 } else { -1 };
+                cur_degree = (cur_degree + dir * step_deg).clamp(-range, range);
+
+                // small occasional transposition for relief (without exiting the ±12 range)
+                if (cc + r).is_multiple_of(37) && sty.humanize > 0.1 {
+                    cur_degree = (cur_degree + if base_v > 0.5 { 12 } else { -12 }).clamp(-range, range);
                 }
-                yy = yy.saturating_add(4);
             }
-            let (mh, ms, mv) = if cnt>0.0 { (sh/cnt, ss/cnt, sv/cnt) } else { (base_h, base_s, base_v) };
+            ImageMap::LumaContour => {
+                // luma 0..1 spans the whole range: black = -range, white = +range
+                let ml = if cnt > 0.0 { sl / cnt } else { base_v };
+                cur_degree = ((2.0 * ml - 1.0) * range as f32).round() as i32;
+            }
+        }
 
-            // mapping: hue diff -> step size, saturation -> extra salt, value -> velocity
-            let dh = (mh - base_h).abs();
-            let hue_push = ((dh / 180.0) * span as f32).round() as i32;
-            let salt = if ms < 0.2 { 0 } else if ms < 0.5 { 1 } else { 2 };
-            let step_deg = (hue_push.min(span) + salt).max(0);
+        // "motivic turn" on every fourth corner of the path, counting its start: every other
+        // row (or column) of a line scan, once per ring of a spiral (hue walk only: the
+        // contour stays literal)
+        let corner = i == 0 || (i + 1 < path.len() && scan_step(path[i - 1], (r, cc)) != scan_step((r, cc), path[i + 1]));
+        let turn = corner && corners.is_multiple_of(4);
+        if corner { corners += 1; }
+        if turn && sty.jumpiness > 0.4 && opts.image_map == ImageMap::HueSteps {
+            // insert 2 short bonus notes (used later for variable rhythms)
+            tiles.extend([(r, cc); 2]);
+            degs.push((cur_degree - 2).clamp(-range, range));
+            vels.push((vels.last().copied().unwrap_or(80) as i32 + 6).clamp(30, 127) as u8);
+            degs.push((cur_degree).clamp(-range, range));
+            vels.push((vels.last().copied().unwrap_or(80) as i32 - 4).clamp(30, 127) as u8);
+        }
 
-            let dir = if (r + cc) % 2 == 0 { 1 } else { -1 };
-            cur_degree = (cur_degree + dir * step_deg).clamp(-12, 12);
+        let vel = (50.0 + 70.0 * mv).clamp(30.0, 127.0) as u8;
 
-            // small occasional transposition for relief (without exiting the ±12 range)
-            if (cc + r) % 37 == 0 && sty.humanize > 0.1 {
-                cur_degree = (cur_degree + if base_v > 0.5 { 12 } else { -12 }).clamp(-12, 12);
-            }
+        tiles.push((r, cc));
+        degs.push(cur_degree);
+        vels.push(vel);
 
-            // "motivic turn" every corner passing on even rows
-            if cc == 0 && (r % 2 == 0) && sty.jumpiness > 0.4 {
-                // insert 2 short bonus notes (used later for variable rhythms)
-                degs.push((cur_degree - 2).clamp(-12, 12));
-                vels.push((vels.last().copied().unwrap_or(80) as i32 + 6).clamp(30, 127) as u8);
-                degs.push((cur_degree).clamp(-12, 12));
-                vels.push((vels.last().copied().unwrap_or(80) as i32 - 4).clamp(30, 127) as u8);
-            }
+        if (i + 1) % cols as usize == 0 {
+            progress.report((i + 1) as f32 / total_notes as f32);
+        }
+    }
+
+    // motif turns add a few notes on top of the grid; keep the hard bound
+    tiles.truncate(max_notes);
+    degs.truncate(max_notes);
+    vels.truncate(max_notes);
+
+    // symmetric images answer each phrase with (part of) itself; asymmetric ones stay
+    // through-composed (a luma contour is never rewritten)
+    if opts.image_map == ImageMap::HueSteps {
+        apply_phrase_symmetry(&mut degs, ife.horizontal_symmetry.max(ife.vertical_symmetry));
+    }
 
-            let vel = (50.0 + 70.0 * mv).clamp(30.0, 127.0) as u8;
+    // overall contour follows the vertical brightness: a brighter bottom drifts the lower
+    // tiles upward (and vice versa), whatever order the scan visits them in
+    for (d, &(r, _)) in degs.iter_mut().zip(&tiles) {
+        let drift = (ife.luma_gradient_y * 6.0 * r as f32 / rows as f32).round() as i32;
+        *d = (*d + drift).clamp(-range, range);
+    }
 
-            degs.push(cur_degree);
-            vels.push(vel);
+    (tiles, degs, vels)
+}
 
-            c += step_col;
+/// Grid step between two neighbouring tiles of a scan path.
+fn scan_step(a: (u32, u32), b: (u32, u32)) -> (i64, i64) {
+    (b.0 as i64 - a.0 as i64, b.1 as i64 - a.1 as i64)
+}
+
+/// With `chromatic` on, one note in this many may end on a chromatic approach tone.
+const CHROMATIC_EVERY: usize = 7;
+
+/// Every `CHROMATIC_EVERY`th note (a half beat or longer) gives its second half to a tone a
+/// semitone off the next note: below it when the line rises, above when it falls, a lower
+/// neighbour when it repeats.
+fn apply_chromatic_approach(m: &mut MonophonicMidi, shift: usize) {
+    let mut out = Vec::with_capacity(m.notes.len() + m.notes.len() / CHROMATIC_EVERY + 1);
+    for (i, n) in m.notes.iter().enumerate() {
+        let dur = n.end - n.start;
+        let next = m.notes.get(i + 1).filter(|_| (i + shift) % CHROMATIC_EVERY == CHROMATIC_EVERY - 1 && dur >= 0.5);
+        let Some(next) = next else {
+            out.push(n.clone());
+            continue;
+        };
+        let pitch = match next.pitch.cmp(&n.pitch) {
+            std::cmp::Ordering::Greater => next.pitch - 1,
+            std::cmp::Ordering::Less => next.pitch + 1,
+            std::cmp::Ordering::Equal => n.pitch.saturating_sub(1),
+        };
+        if pitch == n.pitch {
+            out.push(n.clone());
+            continue;
         }
+        let mid = n.start + dur * 0.5;
+        out.push(Note { end: mid, ..n.clone() });
+        out.push(Note { pitch, start: mid, velocity: n.velocity.saturating_sub(10).max(1), glide: false, ..n.clone() });
     }
+    m.notes = out;
+}
 
-    // 5) Building MIDI: note per tile, without pattern loop. Duration per note = 0.5 beat (eighth).
-    let rhythms: &[&[f32]] = &[
-        &[0.5, 0.5, 0.5, 0.5],          // "straight" eighths
-        &[0.25, 0.75, 0.5, 0.5],        // light syncope
-        &[0.75, 0.25, 0.5, 0.25, 0.25], // "push-pull"
-    ];
+/// Notes per phrase for the symmetry-driven repetition in the image route.
+const PHRASE_NOTES: usize = 8;
+
+/// Every second phrase repeats the head of the one before it; the repeated part grows
+/// from nothing at symmetry 0.7 to the whole phrase at 1.0.
+fn apply_phrase_symmetry(degs: &mut [i32], symmetry: f32) {
+    let repeat = ((symmetry - 0.7) / 0.3 * PHRASE_NOTES as f32).round().clamp(0.0, PHRASE_NOTES as f32) as usize;
+    if repeat == 0 { return; }
+    let mut start = PHRASE_NOTES;
+    while start < degs.len() {
+        let end = (start + repeat).min(degs.len());
+        degs.copy_within(start - PHRASE_NOTES..end - PHRASE_NOTES, start);
+        start += 2 * PHRASE_NOTES;
+    }
+}
+
+/// Images with fewer pixels than this get `tiny_image_phrase` instead of a tile grid.
+const TINY_IMAGE_PIXELS: u64 = 64;
+
+/// A short phrase for images too small to scan: up and back down the scale from the
+/// root, loudness from the brightness.
+fn tiny_image_phrase(ife: &ImageFeatures, sty: &AutoStyle, opts: &TransformOpts) -> MonophonicMidi {
+    const PHRASE: [i32; PHRASE_NOTES] = [0, 2, 4, 3, 2, 1, -1, 0];
+    let (_, max_notes) = image_note_bounds(opts);
+    let vel = (50.0 + 70.0 * ife.hsv_mean_v).clamp(30.0, 127.0) as u8;
     let mut m = MonophonicMidi::new(sty.tempo);
-    let mut t = 0.0f32;
-    let mut rpat_idx;
-    let mut rstep_idx = 0usize;
+    for (i, &d) in PHRASE.iter().take(max_notes).enumerate() {
+        let pitch = degree_to_midi(sty.root_midi, d, sty.scale) as u8;
+        let t = i as f32 * 0.5;
+        m.push(pitch, t, t + if i + 1 == PHRASE.len() { 1.0 } else { 0.5 }, vel);
+    }
+    m
+}
 
-    for (i, d) in degs.iter().enumerate() {
-        let pitch = degree_to_midi(sty.root_midi, *d, sty.scale).clamp(0, 127) as u8;
-        let vel = vels[i];
+/// (min, max) notes for the image route: caller bounds inside 1..`IMAGE_NOTES_CEIL`.
+fn image_note_bounds(opts: &TransformOpts) -> (usize, usize) {
+    let max = opts.max_notes.unwrap_or(IMAGE_NOTES_CEIL).clamp(1, IMAGE_NOTES_CEIL);
+    let min = opts.min_notes.unwrap_or(1).clamp(1, max);
+    (min, max)
+}
 
-        // choose pattern by image "agitation" (edge_density) + progress
-        rpat_idx = ((sty.swing * 10.0) as usize + (i / 32)) % rhythms.len();
-        let pat = rhythms[rpat_idx];
+/* ------------------------------------
+   Text + Image -> Audio (cross-modal)
+-------------------------------------*/
 
-        let dur_beats = pat[rstep_idx % pat.len()];
-        rstep_idx += 1;
+/// Length of the alternating text/image windows in the multi route.
+const MULTI_SECTION_BEATS: f32 = 16.0;
+
+/// Blending rules for the multi route (predictable on purpose):
+/// - tempo: average of text and image tempo
+/// - root: from the image (hue); scale: from the text (sentiment)
+/// - polyphony: the richer of the two, and layering comes from that same side
+/// - swing / humanize / jumpiness: averaged
+/// - percussion: on if either side wants it; drum pattern, drum noise and portamento from the image
+/// - tremolo, velocity curve and drum meter: from the text
+/// - ambient only when both sides are; constant tempo
+fn blend_styles(t: &AutoStyle, i: &AutoStyle) -> AutoStyle {
+    let richer = if t.polyphony > i.polyphony { t } else { i };
+    AutoStyle {
+        tempo: (t.tempo + i.tempo) / 2,
+        tempo_end: None,
+        root_midi: i.root_midi,
+        scale: t.scale,
+        layering: richer.layering.clone(),
+        polyphony: richer.polyphony,
+        swing: (t.swing + i.swing) * 0.5,
+        humanize: (t.humanize + i.humanize) * 0.5,
+        percussion: t.percussion || i.percussion,
+        jumpiness: (t.jumpiness + i.jumpiness) * 0.5,
+        tremolo_depth: t.tremolo_depth,
+        drum_pattern: i.drum_pattern.clone(),
+        noise_color: i.noise_color,
+        scale_arc: None,
+        layering_changes: Vec::new(),
+        velocity_curve: t.velocity_curve,
+        portamento_sec: i.portamento_sec,
+        unison_cents: i.unison_cents,
+        seed: t.seed,
+        degree_range: t.degree_range,
+        chromatic: t.chromatic,
+        count_in_bars: t.count_in_bars,
+        time_sig: t.time_sig,
+        mode: if t.mode == i.mode { t.mode } else { StyleMode::Melodic },
+    }
+}
 
-        // 5–10% chance of "resting": dropping a note to breathe
-        let is_rest = (i % 17 == 0) && (sty.humanize > 0.15);
-        if !is_rest {
-            m.push(pitch, t, t + dur_beats, vel);
+/// Alternate `section_beats`-long windows of two melodies on a shared timeline:
+/// even windows come from `a`, odd windows from `b`; once one side runs out,
+/// the other one keeps playing.
+fn interleave_sections(a: &MonophonicMidi, b: &MonophonicMidi, section_beats: f32) -> MonophonicMidi {
+    let pick = |src: &MonophonicMidi, parity: usize, other_end: f32| {
+        let mut out = MonophonicMidi::new(a.tempo_bpm);
+        for n in &src.notes {
+            let sec = (n.start / section_beats).floor() as usize;
+            let sec_start = sec as f32 * section_beats;
+            let sec_end = sec_start + section_beats;
+            if sec % 2 != parity && sec_start < other_end { continue; }
+            // cut at the window edge while the other side is still playing
+            let end = if sec_end < other_end { n.end.min(sec_end) } else { n.end };
+            if end > n.start { out.push(n.pitch, n.start, end, n.velocity); }
         }
-        t += dur_beats;
+        out
+    };
+    let mut m = pick(a, 0, b.end_beats());
+    m.overlay(&pick(b, 1, a.end_beats()));
+    m
+}
+
+/// Returns (midi, wav, {features, style} json, meta)
+fn multi_to_audio(text: &str, img_bytes: &[u8], opts: &TransformOpts, progress: Progress) -> Result<(MidiExport, Vec<u8>, serde_json::Value, ConvertMeta)> {
+    let tf = analyze_text(text)?;
+    let img = image::load_from_memory(img_bytes)?;
+    let bg = opts.image_background.unwrap_or(DEFAULT_BACKGROUND);
+    let ife = analyze_image_on(&img, bg)?;
+
+    let mut sty = blend_styles(&style_from_text(&tf), &style_from_image(&ife));
+    apply_overrides(&mut sty, opts)?;
+    let features = serde_json::json!({ "features": { "text": tf, "image": ife }, "style": sty });
+    if opts.dry_run {
+        return Ok(style_preview(features, &sty));
     }
 
-    // 6) Serious rendering with everything
-    let wav = render_wav_bytes_styled(&m, 44_100, &StyleParams {
-        layering: sty.layering,
+    // both lines share the blended key/tempo so the sections fit together
+    let text_m = text_melody(text, &tf, &sty, text_duration(&tf, opts));
+    let image_m = image_melody(&img, &ife, &sty, opts, progress.melody())?;
+    let mut m = interleave_sections(&text_m, &image_m, MULTI_SECTION_BEATS);
+
+    shape_timeline(&mut m, opts)?;
+
+    let sr = output_sample_rate(opts)?;
+    let (wav, secs) = render_auto(&mut m, &sty, sr, opts, progress.render())?;
+    let midi = midi_export(&m, &sty, opts)?;
+    let meta = convert_meta(&m, &sty, secs);
+    Ok((midi, wav, features, meta))
+}
+
+/* ------------------------------------
+   Rendering (shared)
+-------------------------------------*/
+
+/// Voices per layer when the auto style asks for a unison spread.
+const AUTO_UNISON_VOICES: usize = 3;
+
+/// Pieces longer than this (beats) get a one-bar drum intro.
+const DRUM_INTRO_MIN_BEATS: f32 = 30.0;
+
+/// Offset a seed adds to the deterministic index patterns of the melody builders.
+fn seed_shift(seed: u64) -> usize {
+    (seed % 0x1_0000) as usize
+}
+
+/// The caller's timeline options: `quantize`, `min_note_beats`, then `bound_duration`.
+fn shape_timeline(m: &mut MonophonicMidi, opts: &TransformOpts) -> Result<()> {
+    if let Some(grid) = opts.quantize {
+        m.quantize(grid, 1.0, false);
+    }
+    if let Some(min) = opts.min_note_beats {
+        if !(min > 0.0 && min <= MAX_MIN_NOTE_BEATS) {
+            return Err(anyhow!("min_note_beats must be > 0 and at most {MAX_MIN_NOTE_BEATS} (got {min})"));
+        }
+        m.thin(min);
+    }
+    bound_duration(m, opts)
+}
+
+/// Enforce `max_output_sec` (and the hard ceiling) by truncating the timeline.
+fn bound_duration(m: &mut MonophonicMidi, opts: &TransformOpts) -> Result<()> {
+    let limit = match opts.max_output_sec {
+        Some(s) if s.is_nan() || s <= 0.0 => return Err(anyhow!("max_output_sec must be > 0 (got {s})")),
+        Some(s) if s > HARD_MAX_OUTPUT_SEC => {
+            return Err(anyhow!("max_output_sec {s} exceeds the hard ceiling of {HARD_MAX_OUTPUT_SEC}s"));
+        }
+        Some(s) => s,
+        None => HARD_MAX_OUTPUT_SEC,
+    };
+    m.truncate(limit);
+    if m.notes.is_empty() { return Err(anyhow!("no notes left within max_output_sec = {limit}s")); }
+    Ok(())
+}
+
+/// Validated output sample rate of the request.
+fn output_sample_rate(opts: &TransformOpts) -> Result<u32> {
+    match opts.sample_rate {
+        None => Ok(DEFAULT_SAMPLE_RATE),
+        Some(sr) if (MIN_SAMPLE_RATE..=MAX_SAMPLE_RATE).contains(&sr) => Ok(sr),
+        Some(sr) => Err(anyhow!("sample_rate must be within {MIN_SAMPLE_RATE}..={MAX_SAMPLE_RATE} Hz (got {sr})")),
+    }
+}
+
+/// `duration_sec`: length of the rendered audio.
+fn convert_meta(m: &MonophonicMidi, sty: &AutoStyle, duration_sec: f32) -> ConvertMeta {
+    ConvertMeta {
+        music_start_sec: music_start_sec(m, &auto_style_params(m, sty)).unwrap_or(0.0),
+        tempo_bpm: sty.tempo,
+        tempo_end_bpm: m.tempo_changes.last().map(|c| c.1),
+        root_midi: sty.root_midi,
+        scale: sty.scale,
+        polyphony: sty.polyphony,
+        duration_sec,
+    }
+}
+
+/// The rendered note timeline: MIDI JSON and, with `TransformOpts.midi_file`, a
+/// multi-track SMF (`multi_track_midi`) of what `render_auto` played; with
+/// `TransformOpts.piano_roll`, its piano roll.
+struct MidiExport {
+    json: Vec<u8>,
+    smf: Option<Vec<u8>>,
+    png: Option<Vec<u8>>,
+}
+
+/// `render_piano_roll` of `m` when `TransformOpts.piano_roll` asks for it.
+fn piano_roll_png(m: &MonophonicMidi, opts: &TransformOpts) -> Result<Option<Vec<u8>>> {
+    opts.piano_roll.then(|| render_piano_roll(m)).transpose()
+}
+
+fn midi_export(m: &MonophonicMidi, sty: &AutoStyle, opts: &TransformOpts) -> Result<MidiExport> {
+    let smf = if opts.midi_file {
+        Some(multi_track_midi(m, &auto_style_params(m, sty))?.to_mid_bytes()?)
+    } else {
+        None
+    };
+    Ok(MidiExport { json: serde_json::to_vec(m)?, smf, png: piano_roll_png(m, opts)? })
+}
+
+/// Dry run result of the analysed routes: no MIDI, no audio, the style's meta.
+fn style_preview(features: serde_json::Value, sty: &AutoStyle) -> (MidiExport, Vec<u8>, serde_json::Value, ConvertMeta) {
+    let meta = ConvertMeta {
+        tempo_bpm: sty.tempo,
+        tempo_end_bpm: sty.tempo_end,
+        root_midi: sty.root_midi,
+        scale: sty.scale,
+        polyphony: sty.polyphony,
+        duration_sec: 0.0,
+        music_start_sec: 0.0,
+    };
+    (MidiExport { json: Vec::new(), smf: None, png: None }, Vec::new(), features, meta)
+}
+
+/// Render with the auto style; the MIDI export then carries what was rendered
+/// (GM program of the primary layer, key, drum hits on the drum channel).
+fn render_auto(m: &mut MonophonicMidi, sty: &AutoStyle, sr: u32, opts: &TransformOpts, progress: Progress) -> Result<(Vec<u8>, f32)> {
+    let style = finish_melody(m, sty)?;
+    render_wav(m, sr, &style, opts, progress)
+}
+
+/// Synth settings for `sty`; fills in the melody's program, key and drum hits to match.
+fn finish_melody(m: &mut MonophonicMidi, sty: &AutoStyle) -> Result<StyleParams> {
+    let style = auto_style_params(m, sty);
+    m.program = style.layering.first().map(|o| o.gm_program());
+    m.key = Some((sty.root_midi, sty.scale));
+    m.drums = drum_hits(m, &style)?;
+    Ok(style)
+}
+
+/// `render_wav_bytes_styled` reporting to `progress`, leveled to `opts.target_lufs`
+/// before the encoding; returns the WAV and its length in seconds.
+fn render_wav(m: &MonophonicMidi, sr: u32, style: &StyleParams, opts: &TransformOpts, progress: Progress) -> Result<(Vec<u8>, f32)> {
+    if let Some(t) = opts.target_lufs.filter(|t| !(MIN_TARGET_LUFS..=MAX_TARGET_LUFS).contains(t)) {
+        return Err(anyhow!("target_lufs must be within {MIN_TARGET_LUFS}..={MAX_TARGET_LUFS} (got {t})"));
+    }
+    let report = |x: f32| progress.report(x);
+    let cb: Option<&dyn Fn(f32)> = progress.cb.map(|_| &report as &dyn Fn(f32));
+    let mut mono = render_mono_styled_with_progress(m, sr, style, cb)?;
+    if let Some(t) = opts.target_lufs {
+        normalize_loudness(&mut mono, sr, t);
+    }
+    Ok((write_wav_i16(&mono, sr, 1)?, mono.len() as f32 / sr as f32))
+}
+
+fn auto_style_params(m: &MonophonicMidi, sty: &AutoStyle) -> StyleParams {
+    StyleParams {
+        layering: sty.layering.clone(),
+        layering_changes: sty.layering_changes.clone(),
         swing: sty.swing,
         humanize: sty.humanize,
         polyphony: sty.polyphony,
         percussion: sty.percussion,
         scale: sty.scale,
-    })?;
-    let midi_json = serde_json::to_vec(&m)?;
-    Ok((midi_json, wav))
+        tremolo_depth: sty.tremolo_depth,
+        drum_pattern: sty.drum_pattern.clone(),
+        time_sig: sty.time_sig,
+        velocity_curve: sty.velocity_curve,
+        portamento_sec: sty.portamento_sec,
+        unison_detune_cents: sty.unison_cents,
+        unison_voices: if sty.unison_cents > 0.0 { AUTO_UNISON_VOICES } else { 1 },
+        seed: sty.seed,
+        // rhythmic pieces get a low end
+        bass: sty.percussion,
+        // longer pieces hold the drums back for a bar to build up
+        drum_intro_bars: if m.end_beats() > DRUM_INTRO_MIN_BEATS { 1 } else { 0 },
+        count_in_bars: sty.count_in_bars,
+        reverb: if sty.mode == StyleMode::Ambient { AMBIENT_REVERB } else { 0.0 },
+        pad: sty.mode == StyleMode::Ambient,
+        noise_color: sty.noise_color,
+        ..StyleParams::default()
+    }
+}
+
+/* ------------------------------------
+   MIDI -> Audio (bring your own melody)
+-------------------------------------*/
+
+fn midi_to_audio(mid_bytes: &[u8], opts: &TransformOpts, progress: Progress) -> Result<(MidiExport, Vec<u8>)> {
+    if opts.dry_run {
+        return Err(anyhow!("dry_run needs an analysed source (text, image, multi or audio), not midi"));
+    }
+    let mut m = MonophonicMidi::from_mid_bytes(mid_bytes)?;
+    if m.notes.is_empty() { return Err(anyhow!("MIDI file contains no notes")); }
+
+    shape_timeline(&mut m, opts)?;
+
+    let mut style = StyleParams {
+        seed: opts.seed.unwrap_or(0),
+        count_in_bars: opts.count_in_bars.min(MAX_COUNT_IN_BARS),
+        ..StyleParams::default()
+    };
+    if let Some(layering) = layering_override(opts)? { style.layering = layering; }
+    let (wav, _) = render_wav(&m, output_sample_rate(opts)?, &style, opts, progress)?;
+    let midi = MidiExport {
+        json: serde_json::to_vec(&m)?,
+        smf: if opts.midi_file { Some(multi_track_midi(&m, &style)?.to_mid_bytes()?) } else { None },
+        png: piano_roll_png(&m, opts)?,
+    };
+    Ok((midi, wav))
+}
+
+/* ------------------------------------
+   Audio -> Audio (follow the source's pitch)
+-------------------------------------*/
+
+/// Returns (midi, wav, {features, style} json, meta)
+fn audio_to_audio(wav_bytes: &[u8], opts: &TransformOpts, progress: Progress) -> Result<(MidiExport, Vec<u8>, serde_json::Value, ConvertMeta)> {
+    let (mut mono, sr) = audio_features::decode_wav_to_mono_f32(wav_bytes)?;
+    if opts.trim_silence {
+        audio_features::trim_silence(&mut mono, SILENCE_THRESHOLD_DB);
+    }
+    let af = AudioFE::new(sr, 2048, 512).analyze_mono(&mono, sr)?;
+    let mut sty = style_from_audio(&af);
+    apply_overrides(&mut sty, opts)?;
+    let features = serde_json::json!({ "features": af, "style": sty });
+    if opts.dry_run {
+        return Ok(style_preview(features, &sty));
+    }
+
+    let mut m = audio_melody(&af, &sty, mono.len() as f32 / sr as f32)?;
+
+    shape_timeline(&mut m, opts)?;
+
+    let sr = output_sample_rate(opts)?;
+    let (wav, secs) = render_auto(&mut m, &sty, sr, opts, progress.render())?;
+    let midi = midi_export(&m, &sty, opts)?;
+    let meta = convert_meta(&m, &sty, secs);
+    Ok((midi, wav, features, meta))
+}
+
+/// Spectral slope (dB/kHz) under which `style_from_audio` hears a dark timbre.
+const DARK_SLOPE_DB_PER_KHZ: f32 = -1.0;
+
+fn style_from_audio(af: &AudioFeatures) -> AutoStyle {
+    // steeply falling spectrum: a dark, muffled timbre
+    let dark = af.spectral_slope < DARK_SLOPE_DB_PER_KHZ;
+    let tempo = if af.tempo_bpm > 0.0 { af.tempo_bpm.round().clamp(60.0, 180.0) as u32 } else { 100 };
+    // root: pitch class of the mean F0, in the octave above C3
+    let root_midi = if af.f0.mean_hz > 0.0 {
+        48 + (hz_to_midi(af.f0.mean_hz).round() as i32).rem_euclid(12)
+    } else {
+        60
+    };
+    // bright spectrum -> major, dull or dark -> minor
+    let bright = af.spectral_centroid_hz > 2000.0 && !dark;
+    let scale = if bright { ScaleKind::Major } else { ScaleKind::Minor };
+
+    // tonal sources get a harmony voice, noisy ones stay a single line; spectral contrast
+    // catches harmonically rich tones (saws, voices) that flatness alone calls noisy
+    let contrast = af.spectral_contrast.iter().sum::<f32>() / af.spectral_contrast.len() as f32;
+    let tonal = af.spectral_flatness < 0.1 || contrast > 20.0;
+    let polyphony = if tonal { 2 } else { 1 };
+    // dark sources are answered by a soft sine-led sound instead of the bright saw
+    let layering = match (polyphony >= 2, dark) {
+        (true, false) => vec![Osc::Saw, Osc::Sine],
+        (true, true) => vec![Osc::Sine, Osc::Saw],
+        (false, false) => vec![Osc::Saw],
+        (false, true) => vec![Osc::Sine],
+    };
+
+    let swing = 0.0;
+    let humanize = (0.1 + af.amplitude_entropy * 0.2).clamp(0.0, 0.4);
+    // drums only answer a source that has a percussive part of its own (HPSS), not
+    // just many onsets (a fast melody has those too)
+    let percussion = af.onset_rate > 2.0 && af.harmonic_ratio < 0.93;
+    let jumpiness = if af.f0.mean_hz > 0.0 { (af.f0.std_hz / af.f0.mean_hz * 4.0).clamp(0.0, 1.0) } else { 0.3 };
+    let tremolo_depth = 0.0;
+    let drum_pattern = None;
+    let velocity_curve = VelocityCurve::Linear;
+    // mostly-voiced sources (voice, strings) glide between notes
+    let portamento_sec = if af.f0.voiced_ratio > 0.6 { 0.03 } else { 0.0 };
+    let unison_cents = 0.0;
+
+    AutoStyle {
+        tempo, tempo_end: None, root_midi, scale, layering, polyphony, swing, humanize, percussion, jumpiness,
+        tremolo_depth, drum_pattern, velocity_curve, portamento_sec, unison_cents, seed: 0,
+        degree_range: DEFAULT_DEGREE_RANGE, chromatic: false, count_in_bars: 0, time_sig: (4, 4),
+        mode: StyleMode::Melodic,
+        noise_color: NoiseColor::White,
+        scale_arc: None,
+        layering_changes: Vec::new(),
+    }
+}
+
+/// Melody following the source's F0 contour: one slot per eighth at the source tempo,
+/// pitched at the median voiced F0 of the slot (`hz_to_midi`), folded around the root
+/// and snapped to the scale. Unvoiced slots rest, repeated pitches are tied.
+/// Unpitched sources (drums etc.) fall back to root notes on the onsets.
+fn audio_melody(af: &AudioFeatures, sty: &AutoStyle, secs: f32) -> Result<MonophonicMidi> {
+    let mut m = MonophonicMidi::new(sty.tempo);
+    let root = sty.root_midi.clamp(0, 127);
+    // louder sources play louder
+    let vel = (60.0 + (af.integrated_lufs + 40.0) * 1.5).clamp(50.0, 115.0) as u8;
+    // timeline units are rendered as seconds, so slots follow the source clock
+    let slot = 30.0 / sty.tempo.max(1) as f32;
+
+    let contour = &af.f0.contour_hz;
+    let hop = af.f0.contour_hop_sec;
+    if hop > 0.0 && contour.iter().any(|&hz| hz > 0.0) {
+        let n_slots = (secs / slot).ceil() as usize;
+        for k in 0..n_slots {
+            let (t0, t1) = (k as f32 * slot, (k + 1) as f32 * slot);
+            let i0 = ((t0 / hop) as usize).min(contour.len());
+            let i1 = ((t1 / hop) as usize).clamp(i0, contour.len());
+            let mut hz: Vec<f32> = contour[i0..i1].iter().copied().filter(|&h| h > 0.0).collect();
+            if hz.is_empty() { continue; }
+            hz.sort_by(f32::total_cmp);
+            let mut p = hz_to_midi(hz[hz.len() / 2]).round() as i32;
+            while p < root - 12 { p += 12; }
+            while p > root + 24 { p -= 12; }
+            let p = p.clamp(0, 127) as u8;
+            match m.notes.last_mut() {
+                Some(last) if last.pitch == p && (last.end - t0).abs() < 1e-4 => last.end = t1,
+                _ => m.push(p, t0, t1, vel),
+            }
+        }
+        m.snap_to_scale(root, sty.scale);
+    } else {
+        let onsets = &af.onset_times_sec;
+        let mut last_t = f32::NEG_INFINITY;
+        for (i, &t) in onsets.iter().enumerate() {
+            if t - last_t < slot * 0.5 { continue; }
+            let end = onsets.get(i + 1).copied().unwrap_or(t + slot).clamp(t + slot * 0.5, t + slot * 2.0);
+            m.push(root as u8, t, end, vel);
+            last_t = t;
+        }
+    }
+
+    if m.notes.is_empty() { return Err(anyhow!("no pitch or onsets to follow in the audio")); }
+    Ok(m)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn questions_step_up_and_statements_step_down() {
+        let text = "Is it? Really? Yes.";
+        let tf = analyze_text(text).unwrap();
+        assert!((tf.question_ratio - 2.0 / 3.0).abs() < 1e-6, "question_ratio {}", tf.question_ratio);
+
+        // 4 words over 8 notes: the sentences end on notes 3, 5 and 7
+        let mut degs = vec![0; 8];
+        apply_sentence_cadences(&mut degs, text, DEFAULT_DEGREE_RANGE);
+        assert_eq!(degs, [0, 0, 0, 1, 0, 1, 0, -1]);
+    }
+
+    fn image(w: u32, h: u32, f: impl Fn(u32, u32) -> [u8; 3]) -> DynamicImage {
+        DynamicImage::ImageRgb8(image::RgbImage::from_fn(w, h, |x, y| image::Rgb(f(x, y))))
+    }
+
+    /// The style and tile melody `image_to_audio` builds for `img`.
+    fn melody(img: &DynamicImage, opts: &TransformOpts) -> (AutoStyle, MonophonicMidi) {
+        let ife = analyze_image_on(img, DEFAULT_BACKGROUND).unwrap();
+        let sty = style_from_image(&ife);
+        let m = image_melody(img, &ife, &sty, opts, Progress::new(None)).unwrap();
+        (sty, m)
+    }
+
+    fn assert_bounded(m: &MonophonicMidi) {
+        assert!(!m.notes.is_empty());
+        assert!(m.notes.len() <= IMAGE_NOTES_CEIL);
+        assert!(m.notes.iter().all(|n| n.start.is_finite() && n.end > n.start));
+    }
+
+    #[test]
+    fn single_pixel_image_plays_the_tiny_phrase() {
+        let (_, m) = melody(&image(1, 1, |_, _| [200, 40, 40]), &TransformOpts::default());
+        assert_bounded(&m);
+        assert_eq!(m.notes.len(), PHRASE_NOTES);
+    }
+
+    #[test]
+    fn thin_strip_gets_a_grid_within_its_size() {
+        let strip = image(2000, 1, |x, _| [(x % 256) as u8, 128, 64]);
+        let (_, m) = melody(&strip, &TransformOpts::default());
+        assert_bounded(&m);
+        // one row of at most 96 tiles (plus motif turns)
+        assert!(m.notes.len() <= 96 + 2, "{} notes", m.notes.len());
+    }
+
+    fn request(from: &str, to: &str, payload: InputPayload) -> ConvertRequest {
+        ConvertRequest { from: from.into(), to: to.into(), options: TransformOpts::default(), payload }
+    }
+
+    #[test]
+    fn unsupported_route_lists_the_supported_ones() {
+        let err = handle_convert(request("video", "audio", InputPayload::Text { text: "hi".into() })).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ConvertError>(),
+            Some(&ConvertError::Unsupported { from: "video".into(), to: "audio".into() })
+        );
+        let msg = err.to_string();
+        assert!(msg.contains("video -> audio"), "{msg}");
+        for (from, to) in SUPPORTED_ROUTES {
+            assert!(msg.contains(&format!("{from} -> {to}")), "{msg}");
+        }
+    }
+
+    #[test]
+    fn sentence_end_rests_near_its_position() {
+        // "three." is word 3 of 8: over 16 notes the rest falls on note 6
+        let marks = punctuation_marks("One two three. Four five six seven eight", 16);
+        let rests: Vec<usize> = (0..16).filter(|&i| marks[i] == PunctMark::Rest).collect();
+        assert_eq!(rests, [6]);
+    }
+
+    #[test]
+    fn comma_accents_the_next_note() {
+        let marks = punctuation_marks("One, two three four", 8);
+        assert!(marks[2] == PunctMark::Accent);
+        assert!(marks.iter().all(|&m| m != PunctMark::Rest));
+    }
+
+    #[test]
+    fn dense_punctuation_keeps_rests_apart() {
+        let marks = punctuation_marks("A. B. C. D. E. F. G. H.", 8);
+        let rests: Vec<usize> = (0..8).filter(|&i| marks[i] == PunctMark::Rest).collect();
+        assert_eq!(rests, [1, 5]);
+        assert!(marks[0] != PunctMark::Rest);
+    }
+
+    fn payloads() -> Vec<InputPayload> {
+        vec![
+            InputPayload::Text { text: "hi".into() },
+            InputPayload::ImageBase64 { data_b64: String::new() },
+            InputPayload::AudioBase64 { data_b64: String::new() },
+            InputPayload::MidiBase64 { data_b64: String::new() },
+            InputPayload::TextAndImage { text: "hi".into(), image_b64: String::new() },
+        ]
+    }
+
+    #[test]
+    fn every_mismatched_payload_is_reported() {
+        for from in ["text", "image", "audio", "midi", "multi"] {
+            for payload in payloads().into_iter().filter(|p| p.source() != from) {
+                let kind = payload.kind();
+                let err = handle_convert(request(from, "audio", payload)).unwrap_err();
+                assert_eq!(
+                    err.downcast_ref::<ConvertError>(),
+                    Some(&ConvertError::MismatchedPayload { from: from.into(), payload: kind }),
+                    "{from} / {kind}"
+                );
+                assert!(err.to_string().contains(kind), "{err}");
+            }
+        }
+    }
+
+    #[test]
+    fn luma_contour_rises_on_even_rows() {
+        // dark on the left, bright on the right; two pixel rows -> two tile rows
+        let img = image(256, 2, |x, _| [x as u8; 3]);
+        let opts = TransformOpts { image_map: ImageMap::LumaContour, ..Default::default() };
+        let (_, m) = melody(&img, &opts);
+        let p: Vec<u8> = m.notes.iter().map(|n| n.pitch).collect();
+        // snake scan: row 0 runs left to right (up), row 1 right to left (down)
+        let peak = p.iter().enumerate().max_by_key(|&(i, &x)| (x, std::cmp::Reverse(i))).unwrap().0;
+        assert!(p[..=peak].windows(2).all(|w| w[0] <= w[1]), "{p:?}");
+        assert!(p[peak..].windows(2).all(|w| w[0] >= w[1]), "{p:?}");
+        assert!(p[0] < p[peak] && p[p.len() - 1] < p[peak], "{p:?}");
+    }
+
+    #[test]
+    fn gray_image_drones_without_drums() {
+        let (sty, m) = melody(&image(64, 64, |_, _| [128; 3]), &TransformOpts::default());
+        assert!(sty.mode == StyleMode::Ambient);
+        assert!(!sty.percussion);
+        assert!(m.notes.iter().all(|n| n.end - n.start >= AMBIENT_NOTE_BEATS), "{:?}", m.notes);
+    }
+
+    #[test]
+    fn flat_regions_tie_into_long_notes() {
+        // two flat halves: 192 tiles, but only two pitches
+        let img = image(256, 192, |x, _| if x < 128 { [0; 3] } else { [255; 3] });
+        let mut opts = TransformOpts { image_map: ImageMap::LumaContour, ..Default::default() };
+        let (_, mut m) = melody(&img, &opts);
+        assert!(m.notes.len() < 192 / 4, "{} notes", m.notes.len());
+        assert!(m.notes.iter().any(|n| n.end - n.start > 2.0));
+
+        opts.min_note_beats = Some(1.0);
+        shape_timeline(&mut m, &opts).unwrap();
+        assert!(m.notes.iter().all(|n| n.end - n.start >= 1.0));
+        opts.min_note_beats = Some(0.0);
+        assert!(shape_timeline(&mut m, &opts).is_err());
+    }
+
+    #[test]
+    fn every_scan_order_visits_each_tile_once() {
+        for order in [ScanOrder::Snake, ScanOrder::RowMajor, ScanOrder::ColumnMajor, ScanOrder::Spiral] {
+            for (rows, cols) in [(1, 1), (1, 7), (7, 1), (2, 2), (3, 5), (6, 4), (12, 16)] {
+                let mut path = order.path(rows, cols);
+                assert_eq!(path.len(), (rows * cols) as usize, "{order:?} {rows}x{cols}");
+                path.sort_unstable();
+                path.dedup();
+                assert_eq!(path.len(), (rows * cols) as usize, "{order:?} {rows}x{cols}");
+                assert!(path.iter().all(|&(r, c)| r < rows && c < cols));
+            }
+        }
+    }
+
+    /// The (row, col) tile and degree of each note `image_melody` builds for `img`.
+    fn tile_walk(img: &DynamicImage, opts: &TransformOpts) -> (Vec<(u32, u32)>, Vec<i32>) {
+        let ife = analyze_image_on(img, DEFAULT_BACKGROUND).unwrap();
+        let (tiles, degs, _) = image_tile_walk(img, &ife, &style_from_image(&ife), opts, Progress::new(None));
+        (tiles, degs)
+    }
+
+    #[test]
+    fn every_scan_order_keeps_the_image_contour() {
+        let orders = [ScanOrder::Snake, ScanOrder::RowMajor, ScanOrder::ColumnMajor, ScanOrder::Spiral];
+        // brightness along x (no vertical drift) and along y (bright bottom drifts upward)
+        let across = image(400, 300, |x, _| [(x * 255 / 399) as u8; 3]);
+        let down = image(400, 300, |_, y| [(y * 255 / 299) as u8; 3]);
+        for order in orders {
+            let opts = TransformOpts { scan_order: order, image_map: ImageMap::LumaContour, ..Default::default() };
+            for (img, axis) in [(&across, 1), (&down, 0)] {
+                let (tiles, degs) = tile_walk(img, &opts);
+                let mut by_tile: Vec<(u32, i32)> = tiles.iter().zip(&degs).map(|(t, &d)| (if axis == 0 { t.0 } else { t.1 }, d)).collect();
+                by_tile.sort_unstable();
+                // the degree depends on the tile's position only, rising with the brightness
+                assert!(by_tile.windows(2).all(|p| p[0].1 <= p[1].1 && (p[0].0 != p[1].0 || p[0].1 == p[1].1)), "{order:?} axis {axis}");
+                assert!(by_tile[0].1 < by_tile[by_tile.len() - 1].1, "{order:?} axis {axis}");
+            }
+        }
+
+        // motif turns: every other row start of a row scan, every other column top of a column scan
+        let colorful = image(400, 300, |x, y| [(x * 255 / 399) as u8, 40, (y * 255 / 299) as u8]);
+        for order in orders {
+            let (tiles, _) = tile_walk(&colorful, &TransformOpts { scan_order: order, ..Default::default() });
+            let (rows, cols) = (tiles.iter().map(|t| t.0).max().unwrap() + 1, tiles.iter().map(|t| t.1).max().unwrap() + 1);
+            let mut turns: Vec<(u32, u32)> = tiles.windows(2).filter(|p| p[0] == p[1]).map(|p| p[0]).collect();
+            turns.dedup();
+            match order {
+                ScanOrder::Snake | ScanOrder::RowMajor => assert_eq!(turns, (0..rows).step_by(2).map(|r| (r, 0)).collect::<Vec<_>>(), "{order:?}"),
+                ScanOrder::ColumnMajor => assert_eq!(turns, (0..cols).step_by(2).map(|c| (0, c)).collect::<Vec<_>>()),
+                // once per ring, plus the start
+                ScanOrder::Spiral => assert!((2..=rows.min(cols).div_ceil(2) as usize + 1).contains(&turns.len()), "{turns:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn loudness_gain_is_applied_to_the_samples() {
+        let sr = 8000;
+        let mut tone: Vec<f32> = (0..sr * 2).map(|i| 0.5 * (2.0 * std::f32::consts::PI * 440.0 * i as f32 / sr as f32).sin()).collect();
+        normalize_loudness(&mut tone, sr as u32, -30.0);
+        let lufs = audio_features::integrated_lufs(&tone, sr as u32);
+        assert!((lufs + 30.0).abs() < 0.1, "{lufs} LUFS");
+
+        // silence is below the gate and stays silent
+        let mut silence = vec![0.0f32; sr];
+        normalize_loudness(&mut silence, sr as u32, -14.0);
+        assert!(silence.iter().all(|&x| x == 0.0));
+    }
+
+    #[test]
+    fn text_to_json_checks_the_callers_limits() {
+        let opts = TransformOpts { limits: InputLimits { text: 4, ..Default::default() }, ..Default::default() };
+        let err = convert_text_to_json("hello", &opts).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ConvertError>(),
+            Some(&ConvertError::InputTooLarge { input: "text", size: 5, limit: 4 })
+        );
+        assert!(convert_text_to_json("hey", &opts).is_ok());
+    }
 }
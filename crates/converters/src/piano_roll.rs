@@ -0,0 +1,82 @@
+//! Piano-roll PNG of a `MonophonicMidi`, for a quick look at what the generator produced.
+
+use std::io::Cursor;
+
+use anyhow::{Context, Result};
+use image::{ImageFormat, Rgb, RgbImage};
+use melody_core::MonophonicMidi;
+
+/// Horizontal resolution (pixels per beat), lowered for long pieces to stay within `MAX_WIDTH`.
+const PX_PER_BEAT: f32 = 24.0;
+const MAX_WIDTH: u32 = 4096;
+/// Height of one semitone row.
+const ROW_PX: u32 = 8;
+/// Empty rows above the highest and below the lowest note.
+const PITCH_PAD: u8 = 2;
+/// Beats per bar line.
+const BAR_BEATS: u32 = 4;
+
+const BACKGROUND: Rgb<u8> = Rgb([30, 30, 36]);
+/// Rows of the black keys, slightly darker.
+const BLACK_KEY_ROW: Rgb<u8> = Rgb([24, 24, 29]);
+const C_LINE: Rgb<u8> = Rgb([60, 60, 72]);
+const BAR_LINE: Rgb<u8> = Rgb([52, 52, 62]);
+/// Note fill at velocity 0 and 127 (blue -> orange).
+const SOFT_NOTE: [f32; 3] = [60.0, 110.0, 220.0];
+const LOUD_NOTE: [f32; 3] = [250.0, 120.0, 40.0];
+
+/// Draws `midi` as a piano roll and returns it as PNG: x = time in beats, y = pitch
+/// (higher notes on top, a line under every C), color = velocity. An empty melody
+/// gives an empty one-bar roll.
+pub fn render_piano_roll(midi: &MonophonicMidi) -> Result<Vec<u8>> {
+    let lo = midi.notes.iter().map(|n| n.pitch).min().unwrap_or(60).saturating_sub(PITCH_PAD);
+    let hi = midi.notes.iter().map(|n| n.pitch).max().unwrap_or(72).saturating_add(PITCH_PAD);
+    let rows = (hi - lo) as u32 + 1;
+    let beats = midi.end_beats().max(BAR_BEATS as f32);
+    let px_per_beat = PX_PER_BEAT.min(MAX_WIDTH as f32 / beats);
+    let width = ((beats * px_per_beat).ceil() as u32).clamp(1, MAX_WIDTH);
+    let height = rows * ROW_PX;
+    let x_at = |beat: f32| ((beat.max(0.0) * px_per_beat) as u32).min(width - 1);
+    // row 0 (top) is the highest pitch
+    let row_of = |pitch: u8| (hi - pitch) as u32;
+
+    let mut img = RgbImage::from_pixel(width, height, BACKGROUND);
+    for pitch in lo..=hi {
+        let y0 = row_of(pitch) * ROW_PX;
+        if matches!(pitch % 12, 1 | 3 | 6 | 8 | 10) {
+            fill(&mut img, 0, y0, width, y0 + ROW_PX, BLACK_KEY_ROW);
+        }
+        if pitch % 12 == 0 {
+            fill(&mut img, 0, y0 + ROW_PX - 1, width, y0 + ROW_PX, C_LINE);
+        }
+    }
+    for bar in (0..=beats as u32).step_by(BAR_BEATS as usize) {
+        let x = x_at(bar as f32);
+        fill(&mut img, x, 0, x + 1, height, BAR_LINE);
+    }
+
+    for n in &midi.notes {
+        let y0 = row_of(n.pitch) * ROW_PX;
+        let x0 = x_at(n.start);
+        // at least 2px wide so very short notes stay visible
+        let x1 = x_at(n.end).max(x0 + 2).min(width);
+        let v = n.velocity.min(127) as f32 / 127.0;
+        let c = |i: usize| (SOFT_NOTE[i] + (LOUD_NOTE[i] - SOFT_NOTE[i]) * v) as u8;
+        let color = Rgb([c(0), c(1), c(2)]);
+        // 1px gap between adjacent rows and notes
+        fill(&mut img, x0, y0 + 1, x1.saturating_sub(1).max(x0 + 1), y0 + ROW_PX - 1, color);
+    }
+
+    let mut out = Cursor::new(Vec::new());
+    img.write_to(&mut out, ImageFormat::Png).context("encode piano roll PNG")?;
+    Ok(out.into_inner())
+}
+
+/// Fill the rectangle [x0, x1) x [y0, y1), clipped to the image.
+fn fill(img: &mut RgbImage, x0: u32, y0: u32, x1: u32, y1: u32, color: Rgb<u8>) {
+    for y in y0..y1.min(img.height()) {
+        for x in x0..x1.min(img.width()) {
+            img.put_pixel(x, y, color);
+        }
+    }
+}
@@ -0,0 +1,27 @@
+//! wasm - browser entry point (feature `wasm`, target wasm32-unknown-unknown)
+//!
+//! Build with `cargo build -p converters --target wasm32-unknown-unknown
+//! --no-default-features --features wasm`, then run `wasm-bindgen` on the output.
+//!
+//! Supported inputs in the browser build:
+//! - Text (`Text`) -> audio / json
+//! - Images (`ImageBase64`, `TextAndImage`): every format the `image` crate decodes
+//!   (PNG, JPEG, GIF, BMP, WebP, ...)
+//! - Audio (`AudioBase64`): WAV only, same as the native build
+//! - MIDI (`MidiBase64`): standard MIDI files
+//!
+//! Everything runs in-process; no server and no threads are needed.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{handle_convert, ConvertRequest};
+
+/// `handleConvert(reqJson) -> respJson`: same request/response JSON as the HTTP API.
+/// Errors (bad JSON, unsupported route, decode failures) are thrown as JS strings.
+#[wasm_bindgen(js_name = handleConvert)]
+pub fn handle_convert_json(req_json: &str) -> Result<String, JsValue> {
+    let req: ConvertRequest =
+        serde_json::from_str(req_json).map_err(|e| JsValue::from_str(&format!("bad request: {e}")))?;
+    let resp = handle_convert(req).map_err(|e| JsValue::from_str(&format!("{e:#}")))?;
+    serde_json::to_string(&resp).map_err(|e| JsValue::from_str(&e.to_string()))
+}
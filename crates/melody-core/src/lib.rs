@@ -6,97 +6,735 @@ pub fn hz_to_midi(hz: f32) -> f32 { 69.0 + 12.0 * (hz / 440.0).log2() }
 pub fn midi_to_hz(m: f32) -> f32 { 440.0 * 2f32.powf((m - 69.0) / 12.0) }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct Note { pub pitch: u8, pub start: f32, pub end: f32, pub velocity: u8 }
+pub struct Note {
+    pub pitch: u8,
+    pub start: f32,
+    pub end: f32,
+    pub velocity: u8,
+    /// Glide in from the previous note's pitch (portamento); exported as pitch bends.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub glide: bool,
+}
+
+fn is_false(b: &bool) -> bool { !*b }
+
+/// Largest gap (timeline units) between two notes `MonophonicMidi::tie_repeats` still ties.
+pub const TIE_TOLERANCE: f32 = 1e-4;
+/// Length of the pitch-bend ramp `to_mid_bytes` writes for a gliding note (timeline units).
+pub const MIDI_GLIDE_LEN: f32 = 0.05;
+/// Pitch-bend events per glide ramp.
+const MIDI_GLIDE_STEPS: u32 = 8;
+/// Bend range (semitones) set via RPN 0 when the export contains glides.
+const MIDI_BEND_RANGE: u8 = 12;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct MonophonicMidi { pub notes: Vec<Note>, pub tempo_bpm: u32 }
+pub struct MonophonicMidi {
+    pub notes: Vec<Note>,
+    pub tempo_bpm: u32,
+    /// General MIDI program (0-based) for the melody; None = no program change (piano).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub program: Option<u8>,
+    /// Percussion on the GM drum channel (`MIDI_DRUM_CHANNEL`), pitches are GM drum keys.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub drums: Vec<Note>,
+    /// Time signature as (numerator, denominator); None = 4/4.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_signature: Option<(u8, u8)>,
+    /// Key as (root MIDI note, scale) for the key-signature meta event; None = no key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<(i32, ScaleKind)>,
+    /// Tempo changes after the opening `tempo_bpm` as (timeline position, BPM), in order;
+    /// empty = constant tempo. Exported as tempo events; renderers play each stretch
+    /// `bpm / tempo_bpm` times faster (see `time_at`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tempo_changes: Vec<(f32, u32)>,
+}
+
+/// 0-based MIDI channel of the GM drum kit (channel 10).
+pub const MIDI_DRUM_CHANNEL: u8 = 9;
 
 impl MonophonicMidi {
-    pub fn new(tempo_bpm: u32) -> Self { Self { notes: Vec::new(), tempo_bpm } }
+    pub fn new(tempo_bpm: u32) -> Self {
+        Self { notes: Vec::new(), tempo_bpm, program: None, drums: Vec::new(), time_signature: None, key: None, tempo_changes: Vec::new() }
+    }
     pub fn push(&mut self, pitch: u8, start: f32, end: f32, vel: u8) {
-        self.notes.push(Note { pitch, start, end, velocity: vel });
+        self.notes.push(Note { pitch, start, end, velocity: vel, glide: false });
     }
 
-    /// Serialize to SMF bytes (single track), simple delta timing.
-    pub fn to_mid_bytes(&self) -> anyhow::Result<Vec<u8>> {
-        use midly::{
-            Smf, Header, Format, Timing, TrackEvent, TrackEventKind, MetaMessage, MidiMessage,
-            num::{u4, u7}
-        };
-        let ppq: u16 = 480;
-        let micros_per_quarter = (60_000_000u32 / self.tempo_bpm) as u32;
-
-        // sorting events
-        let mut evs: Vec<(f32, bool, &Note)> = Vec::new();
-        for n in &self.notes {
-            evs.push((n.start, true, n));
-            evs.push((n.end, false, n));
-        }
-        evs.sort_by(|a,b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
-
-        let mut track: Vec<TrackEvent> = Vec::new();
-        // tempo (u24)
-        track.push(TrackEvent {
-            delta: 0.into(),
-            kind: TrackEventKind::Meta(MetaMessage::Tempo(micros_per_quarter.into())),
-        });
-
-        // time deltas
-        let mut last_tick: u32 = 0;
-        for (t_sec, is_on, n) in evs {
-            let tick = (t_sec.max(0.0) * ppq as f32) as u32;
-            let delta = tick.saturating_sub(last_tick);
-            last_tick = tick;
-            let kind = if is_on {
-                TrackEventKind::Midi {
-                    channel: u4::new(0),
-                    message: MidiMessage::NoteOn {
-                        key: u7::new(n.pitch.min(127)),
-                        vel: u7::new(n.velocity.min(127)),
-                    },
+    /// End of the last note (0 when empty).
+    pub fn end_beats(&self) -> f32 {
+        self.notes.iter().fold(0.0, |mx, n| mx.max(n.end))
+    }
+
+    /// Playback time of timeline position `pos` under `tempo_changes`: each stretch lasts
+    /// `tempo_bpm / bpm` of its length (unchanged at constant tempo).
+    pub fn time_at(&self, pos: f32) -> f32 {
+        if self.tempo_changes.is_empty() { return pos; }
+        let base = self.tempo_bpm.max(1) as f32;
+        let (mut t, mut last, mut bpm) = (0.0, 0.0, base);
+        for &(at, next) in &self.tempo_changes {
+            if at >= pos { break; }
+            t += (at - last) * base / bpm;
+            last = at;
+            bpm = next.max(1) as f32;
+        }
+        t + (pos - last) * base / bpm
+    }
+
+    /// Timeline position played at time `t`: the inverse of `time_at`.
+    pub fn pos_at(&self, t: f32) -> f32 {
+        if self.tempo_changes.is_empty() { return t; }
+        let base = self.tempo_bpm.max(1) as f32;
+        let (mut at_t, mut last, mut bpm) = (0.0, 0.0, base);
+        for &(at, next) in &self.tempo_changes {
+            let seg_end = at_t + (at - last) * base / bpm;
+            if seg_end >= t { break; }
+            at_t = seg_end;
+            last = at;
+            bpm = next.max(1) as f32;
+        }
+        last + (t - at_t) * bpm / base
+    }
+
+    /// Replace `tempo_changes` with a stepped linear ramp from `tempo_bpm` to `end_bpm`
+    /// over the notes: a change every `step` timeline units, the last stretch at `end_bpm`.
+    /// Clears them when the ramp is flat or the piece shorter than two steps.
+    pub fn set_tempo_ramp(&mut self, end_bpm: u32, step: f32) {
+        self.tempo_changes.clear();
+        let end = self.end_beats();
+        if end_bpm == self.tempo_bpm || step <= 0.0 || end < 2.0 * step { return; }
+        let n = (end / step).ceil() as usize - 1;
+        let (from, to) = (self.tempo_bpm as f32, end_bpm.max(1) as f32);
+        for k in 1..=n {
+            let bpm = from + (to - from) * k as f32 / n as f32;
+            self.tempo_changes.push((k as f32 * step, bpm.round() as u32));
+        }
+    }
+
+    /// Append `other` after the end of this timeline plus `gap_beats` (other's own
+    /// leading offset is preserved). The tempo of `self` is kept.
+    pub fn append(&mut self, other: &MonophonicMidi, gap_beats: f32) {
+        let offset = if self.notes.is_empty() { 0.0 } else { self.end_beats() + gap_beats.max(0.0) };
+        for n in &other.notes {
+            self.notes.push(Note { start: n.start + offset, end: n.end + offset, ..n.clone() });
+        }
+        self.sort_notes();
+    }
+
+    /// Drop notes (and drum hits) starting at/after `end` and cut the ones that ring past it;
+    /// tempo changes at/after `end` go too.
+    pub fn truncate(&mut self, end: f32) {
+        for notes in [&mut self.notes, &mut self.drums] {
+            notes.retain(|n| n.start < end);
+            for n in notes.iter_mut() {
+                n.end = n.end.min(end);
+            }
+        }
+        self.tempo_changes.retain(|&(at, _)| at < end);
+    }
+
+    /// Tie consecutive notes of the same pitch that touch (the next one starts where the
+    /// previous one ends, within `TIE_TOLERANCE`) into one longer note, keeping the first
+    /// one's velocity.
+    pub fn tie_repeats(&mut self) {
+        let mut out: Vec<Note> = Vec::with_capacity(self.notes.len());
+        for n in self.notes.drain(..) {
+            if let Some(last) = out.last_mut() {
+                if last.pitch == n.pitch && (n.start - last.end).abs() <= TIE_TOLERANCE {
+                    last.end = last.end.max(n.end);
+                    continue;
                 }
-            } else {
-                TrackEventKind::Midi {
-                    channel: u4::new(0),
-                    message: MidiMessage::NoteOff {
-                        key: u7::new(n.pitch.min(127)),
-                        vel: u7::new(0),
-                    },
+            }
+            out.push(n);
+        }
+        self.notes = out;
+    }
+
+    /// Thin the line so no note is shorter than `min_beats`: each kept note lasts at least
+    /// that long, and notes starting within `min_beats` of it are absorbed (it then lasts
+    /// to their end). No-op when `min_beats <= 0`.
+    pub fn thin(&mut self, min_beats: f32) {
+        if min_beats <= 0.0 || !min_beats.is_finite() { return; }
+        self.sort_notes();
+        let mut out: Vec<Note> = Vec::with_capacity(self.notes.len());
+        for n in self.notes.drain(..) {
+            if let Some(last) = out.last_mut() {
+                if n.start < last.start + min_beats {
+                    last.end = last.end.max(n.end);
+                    continue;
+                }
+            }
+            out.push(n);
+        }
+        for n in out.iter_mut() {
+            n.end = n.end.max(n.start + min_beats);
+        }
+        self.notes = out;
+    }
+
+    /// Merge `other`'s notes onto the same timeline (no time shift).
+    pub fn overlay(&mut self, other: &MonophonicMidi) {
+        self.notes.extend(other.notes.iter().cloned());
+        self.sort_notes();
+    }
+
+    fn sort_notes(&mut self) {
+        self.notes.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap_or(std::cmp::Ordering::Equal));
+    }
+
+    /// Shift every pitch by `semitones`, clamping to the MIDI range 0..127.
+    pub fn transpose(&mut self, semitones: i32) {
+        for n in &mut self.notes {
+            n.pitch = (n.pitch as i32 + semitones).clamp(0, 127) as u8;
+        }
+    }
+
+    /// Move every pitch to the nearest note of `scale` rooted at `root` (ties resolve downward).
+    pub fn snap_to_scale(&mut self, root: i32, scale: ScaleKind) {
+        let steps = scale_steps(scale);
+        for n in &mut self.notes {
+            let p = n.pitch as i32;
+            let base = p - (p - root).rem_euclid(12);
+            let mut best = p;
+            let mut best_d = i32::MAX;
+            for oct in [-12, 0, 12] {
+                for &st in steps.iter() {
+                    let cand = base + oct + st;
+                    let d = (cand - p).abs();
+                    if d < best_d || (d == best_d && cand < best) {
+                        best = cand;
+                        best_d = d;
+                    }
                 }
+            }
+            n.pitch = best.clamp(0, 127) as u8;
+        }
+    }
+
+    /// Snap note starts to the nearest multiple of `grid_beats`.
+    /// `strength` in [0,1] moves each start part of the way (1 = hard quantize).
+    /// With `snap_lengths`, durations are also rounded to whole grid steps (min one step).
+    /// No-op when `grid_beats <= 0`.
+    pub fn quantize(&mut self, grid_beats: f32, strength: f32, snap_lengths: bool) {
+        if grid_beats <= 0.0 || !grid_beats.is_finite() { return; }
+        let k = strength.clamp(0.0, 1.0);
+        for n in &mut self.notes {
+            let len = (n.end - n.start).max(0.0);
+            let target = (n.start / grid_beats).round() * grid_beats;
+            n.start = (n.start + (target - n.start) * k).max(0.0);
+            let new_len = if snap_lengths {
+                let target_len = ((len / grid_beats).round() * grid_beats).max(grid_beats);
+                len + (target_len - len) * k
+            } else {
+                len
             };
-            track.push(TrackEvent { delta: delta.into(), kind });
+            n.end = n.start + new_len;
         }
+        self.sort_notes();
+    }
+
+    /// Parse an SMF (any format; all tracks are merged) into a monophonic line.
+    /// Ticks are converted to seconds through the tempo map (default 120 BPM);
+    /// overlapping notes collapse to the highest one. `tempo_bpm` is taken from
+    /// the first tempo event.
+    pub fn from_mid_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        use midly::{Smf, Timing, TrackEventKind, MetaMessage, MidiMessage};
+
+        let smf = Smf::parse(bytes).map_err(|e| anyhow::anyhow!(e))?;
+        let ppq = match smf.header.timing {
+            Timing::Metrical(t) => t.as_int().max(1) as f64,
+            Timing::Timecode(..) => anyhow::bail!("SMPTE timecode MIDI files are not supported"),
+        };
 
-        let smf = Smf {
-            header: Header {
-                format: Format::SingleTrack,
-                timing: Timing::Metrical(ppq.into()), // u15
-            },
-            tracks: vec![track],
+        // absolute ticks across all tracks
+        let mut tempos: Vec<(u64, u32)> = Vec::new(); // (tick, micros per quarter)
+        let mut raw: Vec<(u64, bool, u8, u8)> = Vec::new(); // (tick, is_on, key, vel)
+        for track in &smf.tracks {
+            let mut tick = 0u64;
+            for ev in track {
+                tick += ev.delta.as_int() as u64;
+                match ev.kind {
+                    TrackEventKind::Meta(MetaMessage::Tempo(us)) => tempos.push((tick, us.as_int().max(1))),
+                    TrackEventKind::Midi { message: MidiMessage::NoteOn { key, vel }, .. } => {
+                        raw.push((tick, vel.as_int() > 0, key.as_int(), vel.as_int()));
+                    }
+                    TrackEventKind::Midi { message: MidiMessage::NoteOff { key, .. }, .. } => {
+                        raw.push((tick, false, key.as_int(), 0));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        tempos.sort_by_key(|t| t.0);
+        if tempos.first().is_none_or(|t| t.0 > 0) {
+            tempos.insert(0, (0, 500_000));
+        }
+        let tick_to_sec = |tick: u64| -> f32 {
+            let mut sec = 0.0f64;
+            for (i, &(t0, us)) in tempos.iter().enumerate() {
+                if tick <= t0 { break; }
+                let t1 = tempos.get(i + 1).map_or(tick, |n| n.0.min(tick));
+                sec += (t1 - t0) as f64 / ppq * us as f64 / 1e6;
+            }
+            sec as f32
         };
 
-        let mut buf = Vec::new();
-        smf.write(&mut buf).map_err(|e| anyhow::anyhow!(e))?;
-        Ok(buf)
+        // pair note-on/off (offs sort before ons at the same tick)
+        raw.sort_by_key(|e| (e.0, e.1));
+        let mut open: [Option<(u64, u8)>; 128] = [None; 128];
+        let mut notes: Vec<Note> = Vec::new();
+        for (tick, is_on, key, vel) in raw {
+            let k = key as usize;
+            if let Some((t0, v0)) = open[k].take() {
+                if tick > t0 {
+                    notes.push(Note { pitch: key, start: tick_to_sec(t0), end: tick_to_sec(tick), velocity: v0, glide: false });
+                }
+            }
+            if is_on { open[k] = Some((tick, vel)); }
+        }
+        notes.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap_or(std::cmp::Ordering::Equal));
+
+        // collapse to monophonic, keeping the highest of overlapping notes
+        let mut mono: Vec<Note> = Vec::with_capacity(notes.len());
+        for n in notes {
+            if let Some(last) = mono.last_mut() {
+                if n.start < last.end {
+                    if n.pitch <= last.pitch { continue; }
+                    last.end = n.start;
+                    if last.end <= last.start { mono.pop(); }
+                }
+            }
+            mono.push(n);
+        }
+
+        let tempo_bpm = (60_000_000.0 / tempos[0].1 as f64).round().max(1.0) as u32;
+        Ok(Self { notes: mono, tempo_bpm, ..Self::new(tempo_bpm) })
+    }
+
+    /// Serialize to SMF bytes (single track), simple delta timing.
+    /// A note with `glide` starts bent to the previous note's pitch and ramps back over
+    /// `MIDI_GLIDE_LEN` (bend range set to `MIDI_BEND_RANGE` semitones up front);
+    /// without glides the output has no bend/controller events at all.
+    /// `program` becomes a program change at delta 0; `drums` play on `MIDI_DRUM_CHANNEL`.
+    /// The track opens with the time signature (4/4 unless set) and, when `key` is set,
+    /// the matching key signature; `tempo_changes` follow as timed tempo events.
+    pub fn to_mid_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        use midly::{Format, MidiMessage, num::u7};
+
+        let mut evs: Vec<(f32, TrackEv)> = tempo_events(&self.tempo_changes);
+        line_events(&self.notes, 0, &mut evs);
+        line_events(&self.drums, MIDI_DRUM_CHANNEL, &mut evs);
+
+        let mut track = conductor_events(self.tempo_bpm, self.time_signature, self.key)?;
+        if let Some(program) = self.program {
+            track.push(midi_event(0, MidiMessage::ProgramChange { program: u7::new(program.min(127)) }));
+        }
+        // RPN 0 (pitch-bend sensitivity), only when there is something to bend
+        if evs.iter().any(|e| matches!(e.1, TrackEv::Bend(..))) {
+            track.extend(bend_range_events(0));
+        }
+        push_timed(&mut track, evs);
+
+        write_smf(Format::SingleTrack, vec![track])
+    }
+}
+
+/// One part of a `MultiTrackMidi`, written as its own SMF track.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MidiTrack {
+    /// Track name (meta event), e.g. "Melody".
+    pub name: String,
+    /// 0-based MIDI channel (`MIDI_DRUM_CHANNEL` for GM percussion).
+    pub channel: u8,
+    /// General MIDI program (0-based); None = no program change.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub program: Option<u8>,
+    /// Notes on the same timeline as the other tracks; gliding notes bend this channel.
+    pub notes: Vec<Note>,
+}
+
+/// Several parts on one timeline (e.g. melody, harmony, bass, drums), exported as an
+/// SMF format 1 file so each part stays editable on its own track/channel.
+/// `MonophonicMidi::to_mid_bytes` remains the single-line export.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MultiTrackMidi {
+    pub tempo_bpm: u32,
+    /// Time signature as (numerator, denominator); None = 4/4.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_signature: Option<(u8, u8)>,
+    /// Key as (root MIDI note, scale) for the key-signature meta event; None = no key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<(i32, ScaleKind)>,
+    /// Tempo changes after `tempo_bpm`, as in `MonophonicMidi::tempo_changes`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tempo_changes: Vec<(f32, u32)>,
+    pub tracks: Vec<MidiTrack>,
+}
+
+impl MultiTrackMidi {
+    pub fn new(tempo_bpm: u32) -> Self {
+        Self { tempo_bpm, time_signature: None, key: None, tempo_changes: Vec::new(), tracks: Vec::new() }
+    }
+
+    /// SMF format 1: a conductor track (tempo and its changes, time signature, key signature), then one
+    /// track per `MidiTrack` with its name, program change and notes (pitch bends as in
+    /// `MonophonicMidi::to_mid_bytes`). Every track ends with an end-of-track event.
+    pub fn to_mid_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        use midly::{Format, MetaMessage, MidiMessage, num::u7};
+
+        let mut conductor = conductor_events(self.tempo_bpm, self.time_signature, self.key)?;
+        push_timed(&mut conductor, tempo_events(&self.tempo_changes));
+        conductor.push(meta_event(MetaMessage::EndOfTrack));
+        let mut tracks: Vec<Vec<midly::TrackEvent>> = vec![conductor];
+        for part in &self.tracks {
+            if part.channel > 15 {
+                anyhow::bail!("track '{}': MIDI channel {} out of range 0..=15", part.name, part.channel);
+            }
+            let mut evs: Vec<(f32, TrackEv)> = Vec::new();
+            line_events(&part.notes, part.channel, &mut evs);
+
+            let mut track = vec![meta_event(MetaMessage::TrackName(part.name.as_bytes()))];
+            if let Some(program) = part.program {
+                track.push(midi_event(part.channel, MidiMessage::ProgramChange { program: u7::new(program.min(127)) }));
+            }
+            if evs.iter().any(|e| matches!(e.1, TrackEv::Bend(..))) {
+                track.extend(bend_range_events(part.channel));
+            }
+            push_timed(&mut track, evs);
+            track.push(meta_event(MetaMessage::EndOfTrack));
+            tracks.push(track);
+        }
+        write_smf(Format::Parallel, tracks)
+    }
+}
+
+/// Ticks per quarter note of the SMF exports.
+const MIDI_PPQ: u16 = 480;
+
+/// A timed event of an SMF export: channel events, or a tempo change (BPM).
+enum TrackEv<'a> { On(&'a Note, u8), Off(&'a Note, u8), Bend(f32, u8), Tempo(u32) }
+
+fn tempo_events<'a>(changes: &[(f32, u32)]) -> Vec<(f32, TrackEv<'a>)> {
+    changes.iter().map(|&(at, bpm)| (at, TrackEv::Tempo(bpm))).collect()
+}
+
+fn micros_per_quarter(bpm: u32) -> u32 {
+    60_000_000 / bpm.max(1)
+}
+
+/// Note on/off events of `notes` on `channel`. A note with `glide` starts bent to the
+/// previous note's pitch and ramps back over `MIDI_GLIDE_LEN` (stable order: a bend
+/// stays between the previous note-off and its note-on once sorted).
+fn line_events<'a>(notes: &'a [Note], channel: u8, evs: &mut Vec<(f32, TrackEv<'a>)>) {
+    for (i, n) in notes.iter().enumerate() {
+        let from = if n.glide && i > 0 { Some(notes[i - 1].pitch) } else { None };
+        if let Some(prev) = from.filter(|&p| p != n.pitch) {
+            let semis = (prev as f32 - n.pitch as f32).clamp(-(MIDI_BEND_RANGE as f32), MIDI_BEND_RANGE as f32);
+            let len = MIDI_GLIDE_LEN.min(n.end - n.start).max(0.0);
+            for k in 0..=MIDI_GLIDE_STEPS {
+                let rel = k as f32 / MIDI_GLIDE_STEPS as f32;
+                evs.push((n.start + len * rel, TrackEv::Bend(semis * (1.0 - rel) / MIDI_BEND_RANGE as f32, channel)));
+                if k == 0 { evs.push((n.start, TrackEv::On(n, channel))); }
+            }
+        } else {
+            evs.push((n.start, TrackEv::On(n, channel)));
+        }
+        evs.push((n.end, TrackEv::Off(n, channel)));
+    }
+}
+
+fn meta_event(message: midly::MetaMessage<'_>) -> midly::TrackEvent<'_> {
+    midly::TrackEvent { delta: 0.into(), kind: midly::TrackEventKind::Meta(message) }
+}
+
+fn midi_event(channel: u8, message: midly::MidiMessage) -> midly::TrackEvent<'static> {
+    midly::TrackEvent {
+        delta: 0.into(),
+        kind: midly::TrackEventKind::Midi { channel: midly::num::u4::new(channel), message },
     }
 }
 
+/// Tempo, time signature (4/4 unless set) and, with a key, the key signature.
+fn conductor_events(
+    tempo_bpm: u32,
+    time_signature: Option<(u8, u8)>,
+    key: Option<(i32, ScaleKind)>,
+) -> anyhow::Result<Vec<midly::TrackEvent<'static>>> {
+    use midly::MetaMessage;
+    let mut track = vec![meta_event(MetaMessage::Tempo(micros_per_quarter(tempo_bpm).into()))];
+    let (num, den) = time_signature.unwrap_or((4, 4));
+    if num == 0 || !den.is_power_of_two() {
+        anyhow::bail!("invalid time signature {num}/{den}");
+    }
+    // 24 MIDI clocks per metronome click, 8 32nd notes per quarter
+    track.push(meta_event(MetaMessage::TimeSignature(num, den.trailing_zeros() as u8, 24, 8)));
+    if let Some((root, scale)) = key {
+        let (sharps, minor) = key_signature(root, scale);
+        track.push(meta_event(MetaMessage::KeySignature(sharps, minor)));
+    }
+    Ok(track)
+}
+
+/// RPN 0 (pitch-bend sensitivity) set to `MIDI_BEND_RANGE` semitones on `channel`.
+fn bend_range_events<'a>(channel: u8) -> impl Iterator<Item = midly::TrackEvent<'a>> {
+    use midly::{MidiMessage, num::u7};
+    [(101, 0), (100, 0), (6, MIDI_BEND_RANGE), (38, 0)].into_iter().map(move |(controller, value)| {
+        midi_event(channel, MidiMessage::Controller { controller: u7::new(controller), value: u7::new(value) })
+    })
+}
+
+/// Sort `evs` by time (stable) and append them to `track` with tick deltas.
+fn push_timed(track: &mut Vec<midly::TrackEvent<'_>>, mut evs: Vec<(f32, TrackEv)>) {
+    use midly::{MetaMessage, MidiMessage, PitchBend, TrackEvent, num::u7};
+    evs.sort_by(|a,b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+    let mut last_tick: u32 = 0;
+    for (t_sec, ev) in evs {
+        let tick = (t_sec.max(0.0) * MIDI_PPQ as f32) as u32;
+        let delta = tick.saturating_sub(last_tick);
+        last_tick = tick;
+        let event = match ev {
+            TrackEv::On(n, ch) => midi_event(ch, MidiMessage::NoteOn {
+                key: u7::new(n.pitch.min(127)),
+                vel: u7::new(n.velocity.min(127)),
+            }),
+            TrackEv::Off(n, ch) => midi_event(ch, MidiMessage::NoteOff {
+                key: u7::new(n.pitch.min(127)),
+                vel: u7::new(0),
+            }),
+            TrackEv::Bend(amount, ch) => midi_event(ch, MidiMessage::PitchBend { bend: PitchBend::from_f32(amount) }),
+            TrackEv::Tempo(bpm) => meta_event(MetaMessage::Tempo(micros_per_quarter(bpm).into())),
+        };
+        track.push(TrackEvent { delta: delta.into(), ..event });
+    }
+}
+
+fn write_smf(format: midly::Format, tracks: Vec<Vec<midly::TrackEvent<'_>>>) -> anyhow::Result<Vec<u8>> {
+    let smf = midly::Smf {
+        header: midly::Header { format, timing: midly::Timing::Metrical(MIDI_PPQ.into()) },
+        tracks,
+    };
+    let mut buf = Vec::new();
+    smf.write(&mut buf).map_err(|e| anyhow::anyhow!(e))?;
+    Ok(buf)
+}
+
 /// Simple scale machinery
-#[derive(Copy, Clone, Debug)]
-pub enum ScaleKind { Major, Minor }
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScaleKind { Major, Minor, Blues, WholeTone }
+
+impl std::str::FromStr for ScaleKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_ascii_lowercase().replace(['-', ' '], "_").as_str() {
+            "major" => Ok(ScaleKind::Major),
+            "minor" => Ok(ScaleKind::Minor),
+            "blues" => Ok(ScaleKind::Blues),
+            "whole_tone" | "wholetone" => Ok(ScaleKind::WholeTone),
+            other => Err(anyhow::anyhow!("unknown scale '{other}' (expected major|minor|blues|whole-tone)")),
+        }
+    }
+}
+
+/// Return semitone steps (within one octave) for the scale's degrees.
+/// Heptatonic scales have 7 steps; Blues and WholeTone have 6.
+pub fn scale_steps(scale: ScaleKind) -> &'static [i32] {
+    match scale {
+        ScaleKind::Major => &[0,2,4,5,7,9,11],
+        ScaleKind::Minor => &[0,2,3,5,7,8,10], // natural minor
+        ScaleKind::Blues => &[0,3,5,6,7,10],   // minor blues, with the flat-five blue note
+        ScaleKind::WholeTone => &[0,2,4,6,8,10],
+    }
+}
 
-/// Return semitone steps for diatonic degrees 0..6 for the given scale
-pub fn scale_steps(scale: ScaleKind) -> [i32;7] {
+/// Key signature for a root and scale as (sharps > 0 / flats < 0, is_minor).
+/// Blues counts as minor; whole-tone has no key and uses the root's major signature.
+pub fn key_signature(root: i32, scale: ScaleKind) -> (i8, bool) {
+    // major keys by tonic pitch class, C..B (flats preferred for Db, Eb, Ab, Bb; F# over Gb)
+    const MAJOR: [i8; 12] = [0, -5, 2, -3, 4, -1, 6, 1, -4, 3, -2, 5];
+    let pc = root.rem_euclid(12) as usize;
     match scale {
-        ScaleKind::Major => [0,2,4,5,7,9,11],
-        ScaleKind::Minor => [0,2,3,5,7,8,10], // natural minor
+        ScaleKind::Major | ScaleKind::WholeTone => (MAJOR[pc], false),
+        ScaleKind::Minor | ScaleKind::Blues => (MAJOR[(pc + 3) % 12], true), // relative major
     }
 }
 
-/// Map (root MIDI, diatonic degree index possibly >6) to absolute MIDI pitch, across octaves
+/// Map (root MIDI, scale degree index possibly beyond one octave) to absolute MIDI pitch, across octaves.
+/// The result is clamped to 0..=127, so extreme degrees pin to the range ends.
 pub fn degree_to_midi(root: i32, degree: i32, scale: ScaleKind) -> i32 {
     let steps = scale_steps(scale);
-    let octave = degree.div_euclid(7);
-    let idx = degree.rem_euclid(7) as usize;
-    root + steps[idx] + 12 * octave
+    debug_assert!(!steps.is_empty(), "{scale:?} has no scale steps");
+    if steps.is_empty() { return root.clamp(0, 127); }
+    let len = steps.len() as i32;
+    let octave = degree.div_euclid(len);
+    let idx = degree.rem_euclid(len) as usize;
+    root.saturating_add(steps[idx]).saturating_add(octave.saturating_mul(12)).clamp(0, 127)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(pitches: &[u8]) -> MonophonicMidi {
+        let mut m = MonophonicMidi::new(120);
+        for (i, &p) in pitches.iter().enumerate() {
+            m.push(p, i as f32 * 0.5, (i + 1) as f32 * 0.5, 100);
+        }
+        m
+    }
+
+    fn pitches(m: &MonophonicMidi) -> Vec<u8> {
+        m.notes.iter().map(|n| n.pitch).collect()
+    }
+
+    #[test]
+    fn transpose_by_an_octave_round_trips() {
+        let mut m = line(&[48, 60, 67, 72]);
+        m.transpose(12);
+        assert_eq!(pitches(&m), [60, 72, 79, 84]);
+        m.transpose(-12);
+        assert_eq!(pitches(&m), [48, 60, 67, 72]);
+    }
+
+    #[test]
+    fn transpose_clamps_to_the_midi_range() {
+        let mut m = line(&[5, 122]);
+        m.transpose(-12);
+        assert_eq!(pitches(&m), [0, 110]);
+        m.transpose(24);
+        assert_eq!(pitches(&m), [24, 127]);
+    }
+
+    #[test]
+    fn snap_to_scale_moves_to_the_nearest_degree() {
+        // C major: C#, F# and Bb sit between two degrees and resolve downward
+        let mut m = line(&[60, 61, 66, 70, 71]);
+        m.snap_to_scale(60, ScaleKind::Major);
+        assert_eq!(pitches(&m), [60, 60, 65, 69, 71]);
+        // in A minor the same pitches land on the natural-minor degrees
+        let mut m = line(&[61, 66, 68]);
+        m.snap_to_scale(57, ScaleKind::Minor);
+        assert_eq!(pitches(&m), [60, 65, 67]);
+    }
+
+    #[test]
+    fn six_note_scales_wrap_octaves_on_degree_six() {
+        for scale in [ScaleKind::Blues, ScaleKind::WholeTone] {
+            let steps = scale_steps(scale);
+            assert_eq!(steps.len(), 6, "{scale:?}");
+            for (i, &st) in steps.iter().enumerate() {
+                let d = i as i32;
+                assert_eq!(degree_to_midi(60, d, scale), 60 + st, "{scale:?} degree {d}");
+                assert_eq!(degree_to_midi(60, d + 6, scale), 72 + st, "{scale:?} degree {}", d + 6);
+                assert_eq!(degree_to_midi(60, d - 6, scale), 48 + st, "{scale:?} degree {}", d - 6);
+            }
+            // one step below the root is the previous octave's top step
+            assert_eq!(degree_to_midi(60, -1, scale), 48 + steps[5], "{scale:?}");
+        }
+        assert_eq!(degree_to_midi(60, 3, ScaleKind::Blues), 66, "the blue note is the flat five");
+        assert_eq!(degree_to_midi(60, -1, ScaleKind::WholeTone), 58);
+    }
+
+    fn midi_messages(bytes: &[u8]) -> Vec<midly::MidiMessage> {
+        let smf = midly::Smf::parse(bytes).unwrap();
+        smf.tracks.iter().flatten().filter_map(|e| match e.kind {
+            midly::TrackEventKind::Midi { message, .. } => Some(message),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn glide_adds_pitch_bends_only_to_the_gliding_export() {
+        let plain = line(&[60, 64]);
+        let mut glided = plain.clone();
+        glided.notes[1].glide = true;
+        let (a, b) = (plain.to_mid_bytes().unwrap(), glided.to_mid_bytes().unwrap());
+        assert_ne!(a, b);
+
+        let plain_msgs = midi_messages(&a);
+        assert_eq!(plain_msgs.len(), 4, "two note-ons and two note-offs: {plain_msgs:?}");
+        assert!(!plain_msgs.iter().any(|m| matches!(m, midly::MidiMessage::PitchBend { .. } | midly::MidiMessage::Controller { .. })));
+
+        let bends: Vec<f32> = midi_messages(&b).iter().filter_map(|m| match m {
+            midly::MidiMessage::PitchBend { bend } => Some(bend.as_f32()),
+            _ => None,
+        }).collect();
+        assert_eq!(bends.len(), MIDI_GLIDE_STEPS as usize + 1);
+        // starts bent down to the previous pitch (-4 of 12 semitones) and returns to centre
+        assert!((bends[0] + 4.0 / MIDI_BEND_RANGE as f32).abs() < 1e-3, "{bends:?}");
+        assert!(bends.last().unwrap().abs() < 1e-3, "{bends:?}");
+        // same notes either way
+        let ons = |bytes: &[u8]| midi_messages(bytes).into_iter().filter_map(|m| match m {
+            midly::MidiMessage::NoteOn { key, .. } => Some(key.as_int()),
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert_eq!(ons(&a), ons(&b));
+    }
+
+    #[test]
+    fn gliding_into_the_same_pitch_writes_no_bends() {
+        let plain = line(&[60, 60]);
+        let mut glided = plain.clone();
+        glided.notes[1].glide = true;
+        assert_eq!(plain.to_mid_bytes().unwrap(), glided.to_mid_bytes().unwrap());
+    }
+
+    fn meta_messages(bytes: &[u8]) -> Vec<midly::MetaMessage<'_>> {
+        let smf = midly::Smf::parse(bytes).unwrap();
+        smf.tracks.into_iter().flatten().filter_map(|e| match e.kind {
+            midly::TrackEventKind::Meta(message) => Some(message),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn key_signature_tells_c_major_from_a_minor() {
+        assert_eq!(key_signature(60, ScaleKind::Major), (0, false));
+        assert_eq!(key_signature(57, ScaleKind::Minor), (0, true));
+        assert_eq!(key_signature(67, ScaleKind::Major), (1, false));
+        assert_eq!(key_signature(62, ScaleKind::Minor), (-1, true));
+
+        for (key, minor) in [((60, ScaleKind::Major), false), ((57, ScaleKind::Minor), true)] {
+            let mut m = line(&[60]);
+            m.key = Some(key);
+            let bytes = m.to_mid_bytes().unwrap();
+            let metas = meta_messages(&bytes);
+            assert!(metas.contains(&midly::MetaMessage::KeySignature(0, minor)), "{metas:?}");
+        }
+    }
+
+    #[test]
+    fn conductor_track_defaults_to_four_four_without_a_key() {
+        let bytes = line(&[60]).to_mid_bytes().unwrap();
+        let metas = meta_messages(&bytes);
+        assert!(metas.contains(&midly::MetaMessage::TimeSignature(4, 2, 24, 8)), "{metas:?}");
+        assert!(!metas.iter().any(|m| matches!(m, midly::MetaMessage::KeySignature(..))));
+
+        let mut m = line(&[60]);
+        m.time_signature = Some((3, 4));
+        let bytes = m.to_mid_bytes().unwrap();
+        assert!(meta_messages(&bytes).contains(&midly::MetaMessage::TimeSignature(3, 2, 24, 8)));
+    }
+
+    #[test]
+    fn extreme_degrees_stay_in_the_midi_range() {
+        for scale in [ScaleKind::Major, ScaleKind::Minor, ScaleKind::Blues, ScaleKind::WholeTone] {
+            for root in [0, 60, 127] {
+                assert_eq!(degree_to_midi(root, 100, scale), 127, "{scale:?} root {root}");
+                assert_eq!(degree_to_midi(root, -100, scale), 0, "{scale:?} root {root}");
+            }
+            assert_eq!(degree_to_midi(60, i32::MAX, scale), 127, "{scale:?}");
+            assert_eq!(degree_to_midi(60, i32::MIN, scale), 0, "{scale:?}");
+        }
+    }
+
+    #[test]
+    fn truncate_cuts_notes_and_tempo_changes_at_the_end() {
+        let mut m = line(&[60, 62, 64, 65, 67]);
+        m.tempo_changes = vec![(0.5, 130), (1.25, 140), (2.0, 150)];
+        m.truncate(1.25);
+        assert_eq!(pitches(&m), [60, 62, 64]);
+        assert_eq!(m.notes.last().unwrap().end, 1.25);
+        assert_eq!(m.tempo_changes, [(0.5, 130)]);
+    }
 }
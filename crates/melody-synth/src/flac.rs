@@ -0,0 +1,400 @@
+//! flac - minimal lossless FLAC encoder for 16-bit PCM.
+//!
+//! Fixed blocks of `FLAC_BLOCK_SIZE` samples; per channel and block the best fixed
+//! predictor (order 0..=4, or a constant subframe for silence) with partitioned Rice
+//! coding. No MD5 in STREAMINFO (allowed: all zeros = unknown).
+
+use anyhow::{anyhow, Result};
+use hound::{SampleFormat, WavReader};
+use std::io::Cursor;
+
+/// Samples per channel in every frame but the last.
+pub const FLAC_BLOCK_SIZE: usize = 4096;
+/// Highest Rice partition order tried.
+const MAX_PARTITION_ORDER: u32 = 6;
+/// Highest 4-bit Rice parameter (15 is the escape code).
+const MAX_RICE_PARAM: u32 = 14;
+
+/// Encode interleaved 16-bit samples (`channels` 1..=8) as a FLAC stream.
+pub fn encode_flac(samples: &[i16], sr: u32, channels: u16) -> Result<Vec<u8>> {
+    if !(1..=8).contains(&channels) {
+        return Err(anyhow!("FLAC supports 1..=8 channels (got {channels})"));
+    }
+    if sr == 0 || sr >= 1 << 20 {
+        return Err(anyhow!("FLAC sample rate out of range: {sr}"));
+    }
+    let ch = channels as usize;
+    let n = samples.len() / ch;
+
+    let mut w = BitWriter::default();
+    w.bytes.extend_from_slice(b"fLaC");
+    // STREAMINFO, the only (last) metadata block
+    w.put(1, 1);
+    w.put(0, 7);
+    w.put(34, 24);
+    let block = FLAC_BLOCK_SIZE.min(n.max(16)) as u64;
+    w.put(block, 16); // min block size (the last frame may be shorter, as the format allows)
+    w.put(block, 16);
+    w.put(0, 24); // min frame size: unknown
+    w.put(0, 24); // max frame size: unknown
+    w.put(sr as u64, 20);
+    w.put(ch as u64 - 1, 3);
+    w.put(15, 5); // 16 bits per sample
+    w.put(n as u64, 36);
+    // MD5: unknown
+    for _ in 0..16 {
+        w.put(0, 8);
+    }
+
+    let mut chan = vec![0i32; FLAC_BLOCK_SIZE];
+    for (frame_no, start) in (0..n).step_by(FLAC_BLOCK_SIZE).enumerate() {
+        let len = FLAC_BLOCK_SIZE.min(n - start);
+        let frame_start = w.bytes.len();
+        write_frame_header(&mut w, frame_no as u64, len, sr, ch);
+        for c in 0..ch {
+            for i in 0..len {
+                chan[i] = samples[(start + i) * ch + c] as i32;
+            }
+            write_subframe(&mut w, &chan[..len]);
+        }
+        w.align();
+        let crc = crc16(&w.bytes[frame_start..]);
+        w.put(crc as u64, 16);
+    }
+    Ok(w.bytes)
+}
+
+/// Transcode a 16-bit PCM WAV (as written by this crate) to FLAC.
+pub fn wav_to_flac(wav: &[u8]) -> Result<Vec<u8>> {
+    let reader = WavReader::new(Cursor::new(wav))?;
+    let spec = reader.spec();
+    if spec.sample_format != SampleFormat::Int || spec.bits_per_sample != 16 {
+        return Err(anyhow!("wav_to_flac expects 16-bit integer PCM"));
+    }
+    let samples = reader
+        .into_samples::<i16>()
+        .collect::<Result<Vec<_>, _>>()?;
+    encode_flac(&samples, spec.sample_rate, spec.channels)
+}
+
+fn write_frame_header(w: &mut BitWriter, frame_no: u64, len: usize, sr: u32, ch: usize) {
+    let start = w.bytes.len();
+    w.put(0b11_1111_1111_1110, 14); // sync
+    w.put(0, 1);
+    w.put(0, 1); // fixed block size
+    let bs_code = if len == FLAC_BLOCK_SIZE { 12 } else { 7 }; // 12: 4096, 7: 16-bit value at the end
+    w.put(bs_code, 4);
+    let sr_code = match sr {
+        88_200 => 1,
+        176_400 => 2,
+        192_000 => 3,
+        8_000 => 4,
+        16_000 => 5,
+        22_050 => 6,
+        24_000 => 7,
+        32_000 => 8,
+        44_100 => 9,
+        48_000 => 10,
+        96_000 => 11,
+        _ => 0, // from STREAMINFO
+    };
+    w.put(sr_code, 4);
+    w.put(ch as u64 - 1, 4); // independent channels
+    w.put(0b100, 3); // 16 bits per sample
+    w.put(0, 1);
+    put_utf8(w, frame_no);
+    if bs_code == 7 {
+        w.put(len as u64 - 1, 16);
+    }
+    let crc = crc8(&w.bytes[start..]);
+    w.put(crc as u64, 8);
+}
+
+/// Constant subframe for a flat block, else the fixed predictor with the cheapest residual.
+fn write_subframe(w: &mut BitWriter, x: &[i32]) {
+    if x.iter().all(|&s| s == x[0]) {
+        w.put(0, 1);
+        w.put(0, 6);
+        w.put(0, 1);
+        w.put_signed(x[0] as i64, 16);
+        return;
+    }
+    let max_order = 4.min(x.len() - 1);
+    let (order, residual) = (0..=max_order)
+        .map(|o| (o, fixed_residual(x, o)))
+        .min_by_key(|(_, r)| r.iter().map(|v| v.unsigned_abs() as u64).sum::<u64>())
+        .expect("at least order 0");
+
+    w.put(0, 1);
+    w.put(0b001000 | order as u64, 6);
+    w.put(0, 1);
+    for &s in &x[..order] {
+        w.put_signed(s as i64, 16);
+    }
+    write_residual(w, &residual, x.len(), order);
+}
+
+fn fixed_residual(x: &[i32], order: usize) -> Vec<i32> {
+    (order..x.len())
+        .map(|i| {
+            let s = |k: usize| x[i - k] as i64;
+            let r = match order {
+                0 => s(0),
+                1 => s(0) - s(1),
+                2 => s(0) - 2 * s(1) + s(2),
+                3 => s(0) - 3 * s(1) + 3 * s(2) - s(3),
+                _ => s(0) - 4 * s(1) + 6 * s(2) - 4 * s(3) + s(4),
+            };
+            r as i32
+        })
+        .collect()
+}
+
+/// Partitioned Rice residual: the partition order with the fewest bits, each partition
+/// with its best parameter.
+fn write_residual(w: &mut BitWriter, res: &[i32], block: usize, order: usize) {
+    let zig: Vec<u32> = res.iter().map(|&r| ((r << 1) ^ (r >> 31)) as u32).collect();
+    let partitions = |po: u32| {
+        let plen = block >> po;
+        (0..1usize << po).map(move |p| {
+            let a = if p == 0 { 0 } else { p * plen - order };
+            let b = (p + 1) * plen - order;
+            (a, b)
+        })
+    };
+    let mut best: Option<(u64, u32, Vec<u32>)> = None;
+    for po in 0..=MAX_PARTITION_ORDER {
+        if !block.is_multiple_of(1 << po) || (block >> po) <= order {
+            break;
+        }
+        let mut bits = 0u64;
+        let mut params = Vec::with_capacity(1 << po);
+        for (a, b) in partitions(po) {
+            let (k, cost) = best_rice_param(&zig[a..b]);
+            bits += 4 + cost;
+            params.push(k);
+        }
+        if best.as_ref().is_none_or(|(bb, _, _)| bits < *bb) {
+            best = Some((bits, po, params));
+        }
+    }
+    let (_, po, params) = best.expect("partition order 0 always fits");
+    w.put(0, 2); // Rice, 4-bit parameters
+    w.put(po as u64, 4);
+    for ((a, b), k) in partitions(po).zip(params) {
+        w.put(k as u64, 4);
+        for &u in &zig[a..b] {
+            w.put_unary(u >> k);
+            if k > 0 {
+                w.put((u & ((1 << k) - 1)) as u64, k);
+            }
+        }
+    }
+}
+
+/// (parameter, bits) of the cheapest Rice code for `zig`.
+fn best_rice_param(zig: &[u32]) -> (u32, u64) {
+    (0..=MAX_RICE_PARAM)
+        .map(|k| {
+            let bits = zig
+                .iter()
+                .map(|&u| (u >> k) as u64 + 1 + k as u64)
+                .sum::<u64>();
+            (k, bits)
+        })
+        .min_by_key(|&(_, bits)| bits)
+        .expect("non-empty range")
+}
+
+/// Frame number as in UTF-8 (up to 36 bits).
+fn put_utf8(w: &mut BitWriter, v: u64) {
+    if v < 0x80 {
+        w.put(v, 8);
+        return;
+    }
+    let mut extra = 1;
+    while v >> (6 * extra + (6 - extra)) != 0 {
+        extra += 1;
+    }
+    let lead = (0xFF00u64 >> (extra + 1)) & 0xFF;
+    w.put(lead | (v >> (6 * extra)), 8);
+    for i in (0..extra).rev() {
+        w.put(0x80 | ((v >> (6 * i)) & 0x3F), 8);
+    }
+}
+
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &b in data {
+        crc ^= b;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x8005
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// MSB-first bit packer.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    acc: u64,
+    n: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, v: u64, bits: u32) {
+        let mut left = bits;
+        while left > 0 {
+            let take = left.min(32);
+            left -= take;
+            self.acc = (self.acc << take) | ((v >> left) & ((1u64 << take) - 1));
+            self.n += take;
+            while self.n >= 8 {
+                self.n -= 8;
+                self.bytes.push((self.acc >> self.n) as u8);
+            }
+            self.acc &= (1u64 << self.n) - 1;
+        }
+    }
+
+    fn put_signed(&mut self, v: i64, bits: u32) {
+        self.put((v as u64) & ((1u64 << bits) - 1), bits);
+    }
+
+    fn put_unary(&mut self, mut q: u32) {
+        while q >= 32 {
+            self.put(0, 32);
+            q -= 32;
+        }
+        self.put(1, q + 1);
+    }
+
+    fn align(&mut self) {
+        if self.n > 0 {
+            self.put(0, 8 - self.n);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::codecs::DecoderOptions;
+    use symphonia::core::errors::Error;
+    use symphonia::core::formats::FormatOptions;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::meta::MetadataOptions;
+    use symphonia::core::probe::Hint;
+
+    /// (sample rate, channels, interleaved samples) of a FLAC stream, decoded by symphonia.
+    fn decode(flac: Vec<u8>) -> (u32, u16, Vec<i16>) {
+        let mss = MediaSourceStream::new(Box::new(Cursor::new(flac)), Default::default());
+        let mut hint = Hint::new();
+        hint.with_extension("flac");
+        let probed = symphonia::default::get_probe()
+            .format(&hint, mss, &FormatOptions::default(), &MetadataOptions::default())
+            .unwrap();
+        let mut format = probed.format;
+        let params = format.default_track().unwrap().codec_params.clone();
+        let mut decoder = symphonia::default::get_codecs()
+            .make(&params, &DecoderOptions { verify: true })
+            .unwrap();
+        let mut out = Vec::new();
+        loop {
+            let packet = match format.next_packet() {
+                Ok(p) => p,
+                Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+                Err(e) => panic!("{e}"),
+            };
+            let buf = decoder.decode(&packet).unwrap();
+            let mut samples = SampleBuffer::<i16>::new(buf.capacity() as u64, *buf.spec());
+            samples.copy_interleaved_ref(buf);
+            out.extend_from_slice(samples.samples());
+        }
+        let channels = params.channels.unwrap().count() as u16;
+        (params.sample_rate.unwrap(), channels, out)
+    }
+
+    fn round_trip(samples: &[i16], sr: u32, channels: u16) {
+        let flac = encode_flac(samples, sr, channels).unwrap();
+        assert_eq!(decode(flac), (sr, channels, samples.to_vec()), "{} samples", samples.len());
+    }
+
+    /// Full-scale noise plus a slow sine (xorshift, reproducible).
+    fn signal(n: usize, seed: u64) -> Vec<i16> {
+        let mut x = seed;
+        (0..n)
+            .map(|i| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                let tone = 12000.0 * (i as f32 * 0.01).sin();
+                (tone as i32 + (x >> 52) as i32 - 2048) as i16
+            })
+            .collect()
+    }
+
+    #[test]
+    fn odd_and_short_lengths_round_trip() {
+        for n in [1, 2, 5, 15, 16, 17, 1001, 4095, 4096, 4097, 10_001] {
+            round_trip(&signal(n, n as u64 + 1), 44_100, 1);
+        }
+        round_trip(&[i16::MIN, i16::MAX, i16::MIN, 0, i16::MAX], 44_100, 1);
+    }
+
+    #[test]
+    fn constant_and_silent_blocks_round_trip() {
+        round_trip(&vec![0; 5000], 8000, 1);
+        round_trip(&[-1234; 9], 8000, 1);
+        // a flat second block between two busy ones
+        let mut x = signal(3 * FLAC_BLOCK_SIZE, 7);
+        x[FLAC_BLOCK_SIZE..2 * FLAC_BLOCK_SIZE].fill(321);
+        round_trip(&x, 8000, 1);
+    }
+
+    #[test]
+    fn stereo_round_trips() {
+        let (l, r) = (signal(5003, 3), signal(5003, 4));
+        let inter: Vec<i16> = l.iter().zip(&r).flat_map(|(&a, &b)| [a, b]).collect();
+        round_trip(&inter, 48_000, 2);
+        // one silent channel
+        let inter: Vec<i16> = l.iter().flat_map(|&a| [a, 0]).collect();
+        round_trip(&inter, 48_000, 2);
+    }
+
+    #[test]
+    fn sample_rate_outside_the_header_table_round_trips() {
+        // no frame header code for these: the rate comes from STREAMINFO
+        for sr in [11_025, 12_345, 100_000] {
+            round_trip(&signal(6000, sr as u64), sr, 1);
+        }
+    }
+
+    #[test]
+    fn bad_channel_counts_and_rates_are_rejected() {
+        assert!(encode_flac(&[0; 9], 44_100, 9).is_err());
+        assert!(encode_flac(&[0; 4], 0, 1).is_err());
+        assert!(encode_flac(&[0; 4], 1 << 20, 1).is_err());
+    }
+}
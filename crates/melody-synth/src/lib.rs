@@ -1,21 +1,233 @@
-
+pub mod flac;
+pub use flac::{encode_flac, wav_to_flac};
 
 use anyhow::{anyhow, Result};
 use hound::{SampleFormat, WavSpec, WavWriter};
-use melody_core::{MonophonicMidi, ScaleKind};
+use melody_core::{MidiTrack, MonophonicMidi, MultiTrackMidi, Note, ScaleKind, MIDI_DRUM_CHANNEL};
+use serde::{Deserialize, Serialize};
 
 use std::f32::consts::PI;
-use std::io::Cursor;
+use std::io::{Cursor, Write};
 
 /* =========================
    Public types & API
    ========================= */
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Osc {
     Sine,
     Saw,
     Square,
+    /// Test oscillator (unit tests and the `testing` feature): a single unit sample at
+    /// each note's first sample, no envelope or gain, so note placement can be checked
+    /// sample-exactly.
+    #[cfg(any(test, feature = "testing"))]
+    Impulse,
+}
+
+impl std::str::FromStr for Osc {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "sine" => Ok(Osc::Sine),
+            "saw" => Ok(Osc::Saw),
+            "square" => Ok(Osc::Square),
+            #[cfg(any(test, feature = "testing"))]
+            "impulse" => Ok(Osc::Impulse),
+            other => Err(anyhow!("unknown oscillator '{other}' (expected sine|saw|square)")),
+        }
+    }
+}
+
+impl Osc {
+    /// Closest General MIDI program (0-based) for exports.
+    pub fn gm_program(self) -> u8 {
+        match self {
+            Osc::Sine => 89,   // Pad 2 (warm)
+            Osc::Saw => 81,    // Lead 2 (sawtooth)
+            Osc::Square => 80, // Lead 1 (square)
+            #[cfg(any(test, feature = "testing"))]
+            Osc::Impulse => 115, // Woodblock
+        }
+    }
+}
+
+/// One bar of drums on a sixteenth-note grid; `kick`, `snare` and `hat`
+/// must have the same length (16 steps for 4/4).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DrumPattern {
+    pub kick: Vec<bool>,
+    pub snare: Vec<bool>,
+    pub hat: Vec<bool>,
+    /// Snare/tom fill on the last bar of every N-bar phrase (0 = no fills).
+    pub fill_every_bars: u32,
+}
+
+impl DrumPattern {
+    /// Parse one bar from a tiny DSL: one char per sixteenth, `x` = hit, anything else = rest.
+    /// Ex: `DrumPattern::from_strs("x.......x.......", "....x.......x...", "x.x.x.x.x.x.x.x.", 4)`
+    pub fn from_strs(kick: &str, snare: &str, hat: &str, fill_every_bars: u32) -> Result<Self> {
+        let parse = |s: &str| s.chars().map(|c| c == 'x' || c == 'X').collect::<Vec<bool>>();
+        let (kick, snare, hat) = (parse(kick), parse(snare), parse(hat));
+        if kick.is_empty() || kick.len() != snare.len() || kick.len() != hat.len() {
+            return Err(anyhow!("DrumPattern rows must be non-empty and of equal length"));
+        }
+        Ok(Self { kick, snare, hat, fill_every_bars })
+    }
+
+    /// Kick on 1 & 3, snare on 2 & 4, hats on every eighth (4/4).
+    pub fn standard() -> Self {
+        Self::from_strs(
+            "x.......x.......",
+            "....x.......x...",
+            "x.x.x.x.x.x.x.x.",
+            4,
+        ).expect("valid builtin pattern")
+    }
+
+    /// Busier groove: syncopated kicks, ghost snare, sixteenth hats.
+    pub fn busy() -> Self {
+        Self::from_strs(
+            "x.....x.x.....x.",
+            "....x..x....x...",
+            "xxxxxxxxxxxxxxxx",
+            4,
+        ).expect("valid builtin pattern")
+    }
+
+    /// Builtin groove for a `num/den` time signature (`den` 4 or 8), hats on every eighth.
+    /// Quarter-note meters group beats in twos (a trailing three for odd meters) with the
+    /// kick on each group's first beat and the snare on its second: 4/4 is `standard()`,
+    /// 3/4 puts the snare on beat 2 only. Eighth-note meters group in threes (dotted
+    /// quarters) alternating kick and snare: 6/8 is kick on 1, snare on 4.
+    pub fn for_time_sig(time_sig: (u8, u8)) -> Result<Self> {
+        let unit = time_sig_unit_steps(time_sig)?;
+        let (num, den) = (time_sig.0 as usize, time_sig.1);
+        let steps = num * unit;
+        let (mut kick, mut snare) = (vec![false; steps], vec![false; steps]);
+        let hat = (0..steps).map(|i| i.is_multiple_of(2)).collect();
+        let mut beat = 0;
+        let mut group = 0;
+        while beat < num {
+            let rest = num - beat;
+            if den == 8 {
+                let len = rest.min(3);
+                if group % 2 == 0 { kick[beat * unit] = true; } else { snare[beat * unit] = true; }
+                beat += len;
+            } else {
+                let len = if rest == 3 { 3 } else { rest.min(2) };
+                kick[beat * unit] = true;
+                if len > 1 { snare[(beat + 1) * unit] = true; }
+                beat += len;
+            }
+            group += 1;
+        }
+        Ok(Self { kick, snare, hat, fill_every_bars: 4 })
+    }
+
+    pub fn steps_per_bar(&self) -> usize {
+        self.kick.len().min(self.snare.len()).min(self.hat.len())
+    }
+}
+
+impl Default for DrumPattern {
+    fn default() -> Self { Self::standard() }
+}
+
+/// Sixteenths per notated beat of `time_sig`; only quarter (4) and eighth (8) beats are supported.
+fn time_sig_unit_steps((num, den): (u8, u8)) -> Result<usize> {
+    if !(1..=16).contains(&num) || (den != 4 && den != 8) {
+        return Err(anyhow!("unsupported time signature {num}/{den} (expected 1..=16 over 4 or 8)"));
+    }
+    Ok(16 / den as usize)
+}
+
+/// Sixteenths per felt pulse of `time_sig`: the beat, or the dotted quarter in compound
+/// eighth-note meters (6/8, 9/8, 12/8). Fills start on a pulse.
+fn time_sig_pulse_steps(time_sig: (u8, u8)) -> usize {
+    let unit = time_sig_unit_steps(time_sig).unwrap_or(4);
+    if time_sig.1 == 8 && time_sig.0 > 3 && time_sig.0.is_multiple_of(3) { unit * 3 } else { unit }
+}
+
+/// Drum levels: per-drum gains inside the drum bus (toms follow the kick), and the
+/// bus gain applied when it is summed with the melody bus.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DrumMix {
+    pub kick: f32,
+    pub snare: f32,
+    pub hat: f32,
+    pub bus: f32,
+}
+
+impl Default for DrumMix {
+    fn default() -> Self {
+        Self { kick: 0.7, snare: 0.6, hat: 0.2, bus: 0.7 }
+    }
+}
+
+/// Spectrum of the drum noise (snare body, hats).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoiseColor {
+    /// Flat spectrum: the bright, classic kit.
+    #[default]
+    White,
+    /// 1/f, -3 dB per octave: a softer kit.
+    Pink,
+    /// 1/f², -6 dB per octave (integrated white): dull, rumbly hits.
+    Brown,
+}
+
+impl std::str::FromStr for NoiseColor {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "white" => Ok(NoiseColor::White),
+            "pink" => Ok(NoiseColor::Pink),
+            "brown" => Ok(NoiseColor::Brown),
+            other => Err(anyhow!("unknown noise color '{other}' (expected white|pink|brown)")),
+        }
+    }
+}
+
+/// Velocity (0..127) -> note gain mapping.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VelocityCurve {
+    /// gain = v / 127
+    #[default]
+    Linear,
+    /// gain = (v / 127)^k; k < 1 evens dynamics out, k > 1 exaggerates them.
+    Exponential(f32),
+    /// Smoothstep: soft notes a bit softer, loud notes a bit louder, middle unchanged.
+    SCurve,
+}
+
+impl VelocityCurve {
+    pub fn gain(self, velocity: u8) -> f32 {
+        let v = (velocity as f32 / 127.0).clamp(0.0, 1.0);
+        match self {
+            VelocityCurve::Linear => v,
+            VelocityCurve::Exponential(k) => v.powf(k.max(0.01)),
+            VelocityCurve::SCurve => v * v * (3.0 - 2.0 * v),
+        }
+    }
+}
+
+/// Master level normalization (applied before the fades).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NormalizeMode {
+    /// Scale down only when the peak exceeds 0.99; quiet material stays quiet.
+    #[default]
+    Soft,
+    /// Leave the mix as rendered (may clip).
+    Off,
+    /// Scale the peak to this level (0..1), up or down.
+    Peak(f32),
+    /// Scale to this RMS level (0..1), then a brick-wall limiter keeps peaks under `LIMITER_CEILING`.
+    Rms(f32),
 }
 
 /// High-level style for rendering.
@@ -23,6 +235,10 @@ pub enum Osc {
 pub struct StyleParams {
     /// Timbre layers; first is primary. Ex: [Saw, Sine, Square]
     pub layering: Vec<Osc>,
+    /// Layer stacks taking over from `layering` at timeline positions (as in
+    /// `MonophonicMidi::tempo_changes`), in order: notes starting from a change on use its
+    /// stack. Empty = `layering` throughout.
+    pub layering_changes: Vec<(f32, Vec<Osc>)>,
     /// Delay on even notes (0..0.35). 0 = no swing.
     pub swing: f32,
     /// Small timing/velocity jitter (0..0.4).
@@ -33,17 +249,115 @@ pub struct StyleParams {
     pub percussion: bool,
     /// Scale kind for choosing the third (major/minor) when polyphony > 1
     pub scale: ScaleKind,
+    /// Tremolo depth (0..1), LFO synced to eighth notes. 0 = off.
+    pub tremolo_depth: f32,
+    /// Drum groove used when `percussion` is on; None = `DrumPattern::for_time_sig(time_sig)`.
+    pub drum_pattern: Option<DrumPattern>,
+    /// Meter of the drums as (beats, beat unit), 4 or 8 below; picks the default groove
+    /// and where fills start. A custom `drum_pattern` keeps its own bar length.
+    pub time_sig: (u8, u8),
+    /// Bars without drums at the start (build-up); drums then ramp in over one bar
+    /// and always ramp out over the last bar.
+    pub drum_intro_bars: u32,
+    /// Per-drum and drum bus levels.
+    pub drum_mix: DrumMix,
+    /// Bars of quiet metronome clicks (`COUNT_IN_BEATS_PER_BAR` beats, accented downbeat)
+    /// before the music; everything else starts `music_start_sec` later. 0 = off.
+    pub count_in_bars: u32,
+    /// Add a root-note bassline (one note per beat, roots sampled per bar).
+    pub bass: bool,
+    /// Master fade-in length in seconds (cosine ramp, applied after normalization).
+    pub fade_in_sec: f32,
+    /// Master fade-out length in seconds; covers the default 0.5s tail.
+    pub fade_out_sec: f32,
+    /// How note velocity maps to gain.
+    pub velocity_curve: VelocityCurve,
+    /// Length of a timbre "section" in seconds (8s by default); the layer order
+    /// rotates once per section and is stable inside it.
+    pub section_len_sec: f32,
+    /// Pitch glide into legato notes, in seconds (0 = off).
+    pub portamento_sec: f32,
+    /// Unison spread in cents: detuned layers move this much further out (half each way),
+    /// and with `unison_voices` > 1 each layer's voices spread over it. 0 = off.
+    pub unison_detune_cents: f32,
+    /// Voices per layer (1..=`UNISON_MAX_VOICES`), gain-compensated; 1 = off.
+    pub unison_voices: usize,
+    /// Haas widening for `render_wav_bytes_styled_stereo` (0..1): 0 = dual mono,
+    /// 1 = 20ms delay on the right channel. Ignored by the mono renderers.
+    pub stereo_width: f32,
+    /// Seed for the random jitter (humanize); 0 reproduces the unseeded output.
+    pub seed: u64,
+    /// Master level: soft peak protection by default, or a fixed peak/RMS target.
+    pub normalize_mode: NormalizeMode,
+    /// Soft-knee compressor on the master before the normalization (`COMP_*` settings).
+    pub compress: bool,
+    /// Cut the silent end: the output stops `fade_out_sec` (at least `TRIM_TAIL_KEEP_SEC`,
+    /// plus `REVERB_TAIL_SEC` with reverb) after the last sample within `TRIM_TAIL_DB` of
+    /// the peak (release tails above that are kept).
+    pub trim_tail: bool,
+    /// Reverb mix (0..1) on the whole mix, before the compressor; 0 = off.
+    pub reverb: f32,
+    /// Slow swell (`SLOW_PAD_ENV`) on every layer instead of the pluck/pad roles.
+    pub pad: bool,
+    /// Noise of the snare and hats; pink or brown give a softer kit.
+    pub noise_color: NoiseColor,
+    /// Silence rendered after the last note-off (0..=`MAX_TAIL_SEC`), for the effects to
+    /// ring out. None = `DEFAULT_TAIL_SEC`, longer with reverb (`REVERB_TAIL_SEC`).
+    pub tail_sec: Option<f32>,
+}
+
+/// Tail after the last note without reverb; also lets the last drum hits finish.
+pub const DEFAULT_TAIL_SEC: f32 = 0.5;
+/// Tail with reverb: about the reverb's decay to -60 dB (slowest comb,
+/// 3 * 0.0307s / -log10(`REVERB_FEEDBACK`)).
+pub const REVERB_TAIL_SEC: f32 = 1.2;
+/// Longest `StyleParams::tail_sec`.
+pub const MAX_TAIL_SEC: f32 = 10.0;
+
+impl StyleParams {
+    /// The tail `tail_sec` asks for, or the automatic one.
+    pub fn effective_tail_sec(&self) -> f32 {
+        match self.tail_sec {
+            Some(t) if t.is_finite() => t.clamp(0.0, MAX_TAIL_SEC),
+            _ if self.reverb > 0.0 => REVERB_TAIL_SEC,
+            _ => DEFAULT_TAIL_SEC,
+        }
+    }
 }
 
 impl Default for StyleParams {
     fn default() -> Self {
         Self {
             layering: vec![Osc::Saw, Osc::Sine],
+            layering_changes: Vec::new(),
             swing: 0.0,
             humanize: 0.1,
             polyphony: 1,
             percussion: false,
             scale: ScaleKind::Major,
+            tremolo_depth: 0.0,
+            drum_pattern: None,
+            time_sig: (4, 4),
+            drum_intro_bars: 0,
+            drum_mix: DrumMix::default(),
+            count_in_bars: 0,
+            bass: false,
+            fade_in_sec: 0.05,
+            fade_out_sec: 0.5,
+            velocity_curve: VelocityCurve::Linear,
+            section_len_sec: 8.0,
+            portamento_sec: 0.0,
+            unison_detune_cents: 0.0,
+            unison_voices: 1,
+            stereo_width: 0.5,
+            seed: 0,
+            normalize_mode: NormalizeMode::Soft,
+            compress: false,
+            trim_tail: true,
+            reverb: 0.0,
+            pad: false,
+            noise_color: NoiseColor::White,
+            tail_sec: None,
         }
     }
 }
@@ -53,20 +367,241 @@ impl Default for StyleParams {
 pub fn render_wav_bytes(midi: &MonophonicMidi, sr: u32, primary: Osc) -> Result<Vec<u8>> {
     let style = StyleParams {
         layering: vec![primary],
-        swing: 0.0,
         humanize: 0.0,
-        polyphony: 1,
-        percussion: false,
-        scale: ScaleKind::Major,
+        trim_tail: false,
+        ..StyleParams::default()
     };
     render_wav_bytes_styled(midi, sr, &style)
 }
 
 /// New API: full serious rendering with layering/polyphony/swing/humanize/percussion.
 pub fn render_wav_bytes_styled(midi: &MonophonicMidi, sr: u32, style: &StyleParams) -> Result<Vec<u8>> {
-    if style.layering.is_empty() {
+    render_wav_bytes_styled_with_progress(midi, sr, style, None)
+}
+
+/// `render_wav_bytes_styled` reporting the finished fraction (0..1) to `progress` after
+/// every `STREAM_BLOCK_SEC` block.
+pub fn render_wav_bytes_styled_with_progress(midi: &MonophonicMidi, sr: u32, style: &StyleParams, progress: Option<&dyn Fn(f32)>) -> Result<Vec<u8>> {
+    let mono = render_mono(midi, sr, style, progress)?;
+    write_wav_i16(&mono, sr, 1)
+}
+
+/// `render_wav_bytes_styled_with_progress` before the encoding: the finished mono
+/// samples, for a caller that processes them further (then `write_wav_i16`).
+pub fn render_mono_styled_with_progress(midi: &MonophonicMidi, sr: u32, style: &StyleParams, progress: Option<&dyn Fn(f32)>) -> Result<Vec<f32>> {
+    render_mono(midi, sr, style, progress)
+}
+
+/// Render block length for `render_wav_stream` and the progress reports (seconds).
+const STREAM_BLOCK_SEC: f32 = 1.0;
+
+/// Streaming variant of `render_wav_bytes_styled` (same bytes): renders `STREAM_BLOCK_SEC`
+/// blocks and writes 16-bit PCM as it goes, so memory stays O(block) instead of O(piece).
+/// Normalization needs the global peak/RMS, so the blocks are rendered twice: once to
+/// measure, once to write; the in-memory API renders once. The WAV header is written up
+/// front (the length is known), which is why `writer` doesn't need `Seek`.
+pub fn render_wav_stream(midi: &MonophonicMidi, sr: u32, style: &StyleParams, writer: &mut dyn Write) -> Result<()> {
+    render_wav_stream_with_progress(midi, sr, style, writer, None)
+}
+
+/// `render_wav_stream` reporting the finished fraction (0..1) to `progress` after every
+/// block; the measuring pass covers 0..0.5, the writing pass 0.5..1.
+pub fn render_wav_stream_with_progress(
+    midi: &MonophonicMidi,
+    sr: u32,
+    style: &StyleParams,
+    writer: &mut dyn Write,
+    progress: Option<&dyn Fn(f32)>,
+) -> Result<()> {
+    let prep = prepare(midi, sr, style)?;
+    let total = prep.total_samples;
+    let block = ((STREAM_BLOCK_SEC * sr as f32) as usize).max(1);
+    let mut buf = vec![0.0f32; block];
+
+    // pass 1: level for the normalization (step 7) and where the audible part ends
+    let mut level = Level::default();
+    let mut tail = TailMeter::new(sr, total);
+    let mut master = Master::new(&prep, sr, style);
+    for off in (0..total).step_by(block) {
+        let len = block.min(total - off);
+        let mut span = Span::new(&mut buf[..len], off, total);
+        master.render(&mut span);
+        level.measure(span.buf);
+        tail.measure(off, span.buf);
+        if let Some(report) = progress { report(0.5 * (off + len) as f32 / total as f32); }
+    }
+    let mut finish = Finisher::new(style, &level, &tail, sr, total);
+    let out_len = finish.out_len;
+
+    // pass 2: render again (up to the trimmed end), normalize, fade (step 7b), encode
+    write_wav_header(writer, sr, 1, out_len)?;
+    let mut pcm = Vec::with_capacity(block * 2);
+    let mut master = Master::new(&prep, sr, style);
+    for off in (0..out_len).step_by(block) {
+        let len = block.min(out_len - off);
+        let mut span = Span::new(&mut buf[..len], off, total);
+        master.render(&mut span);
+        finish.process(span.buf, off);
+
+        pcm.clear();
+        for &s in span.buf.iter() {
+            pcm.extend_from_slice(&f32_to_i16(s).to_le_bytes());
+        }
+        writer.write_all(&pcm)?;
+        if let Some(report) = progress { report(0.5 + 0.5 * (off + len) as f32 / out_len as f32); }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Same rendering as `render_wav_bytes_styled`, written as a 2-channel WAV.
+/// The right channel is a delayed (5..20ms, Haas effect) and slightly quieter copy
+/// of the left one, scaled by `style.stereo_width`.
+pub fn render_wav_bytes_styled_stereo(midi: &MonophonicMidi, sr: u32, style: &StyleParams) -> Result<Vec<u8>> {
+    let mono = render_mono(midi, sr, style, None)?;
+    let width = style.stereo_width.clamp(0.0, 1.0);
+    let delay = if width > 0.0 { ((0.005 + 0.015 * width) * sr as f32) as usize } else { 0 };
+    let gain_r = 1.0 - 0.1 * width;
+
+    let mut inter = Vec::with_capacity(mono.len() * 2);
+    for i in 0..mono.len() {
+        let r = if i >= delay { mono[i - delay] * gain_r } else { 0.0 };
+        inter.push(mono[i]);
+        inter.push(r);
+    }
+    write_wav_i16(&inter, sr, 2)
+}
+
+/// Full pipeline up to (and including) the master fades, in memory: blocks are rendered
+/// into one buffer (reporting to `progress`), then normalized and faded as a whole.
+fn render_mono(midi: &MonophonicMidi, sr: u32, style: &StyleParams, progress: Option<&dyn Fn(f32)>) -> Result<Vec<f32>> {
+    let prep = prepare(midi, sr, style)?;
+    let mut out = vec![0.0f32; prep.total_samples];
+    let total = out.len();
+    let block = ((STREAM_BLOCK_SEC * sr as f32) as usize).max(1);
+    let mut master = Master::new(&prep, sr, style);
+    for (k, chunk) in out.chunks_mut(block).enumerate() {
+        let off = k * block;
+        master.render(&mut Span::new(chunk, off, total));
+        if let Some(report) = progress { report((off + chunk.len()) as f32 / total as f32); }
+    }
+
+    let mut level = Level::default();
+    level.measure(&out);
+    let mut tail = TailMeter::new(sr, total);
+    tail.measure(0, &out);
+    let mut finish = Finisher::new(style, &level, &tail, sr, total);
+    out.truncate(finish.out_len);
+    finish.process(&mut out, 0);
+    Ok(out)
+}
+
+/// Steps 7-7b for a measured master, shared by the in-memory and the streaming renderers:
+/// where the output ends (`trim_tail`), then per window the normalization (softly by
+/// default, to avoid clipping) and the master fades (never start/stop on a hard edge).
+struct Finisher {
+    norm: Normalizer,
+    out_len: usize,
+    sr: u32,
+    fade_in: f32,
+    fade_out: f32,
+}
+
+impl Finisher {
+    fn new(style: &StyleParams, level: &Level, tail: &TailMeter, sr: u32, total: usize) -> Self {
+        let (out_len, fade_out) = output_len(style, tail, sr, total);
+        let norm = Normalizer::new(style.normalize_mode, level, sr);
+        Finisher { norm, out_len, sr, fade_in: fade_in_len(style), fade_out }
+    }
+
+    /// Finish the window starting at sample `off` (windows must come in order).
+    fn process(&mut self, buf: &mut [f32], off: usize) {
+        self.norm.process(buf);
+        apply_fades_at(buf, off, self.out_len, self.sr, self.fade_in, self.fade_out);
+    }
+}
+
+/// Steps 5-6b, one window after the other: `render_block` (picking up where the last
+/// window left off), then reverb (6a, optional) and master compression (6b, optional).
+struct Master<'a> {
+    prep: &'a Prepared,
+    sr: u32,
+    style: &'a StyleParams,
+    cursor: BlockCursor,
+    verb: Option<Reverb>,
+    comp: Option<Compressor>,
+}
+
+impl<'a> Master<'a> {
+    fn new(prep: &'a Prepared, sr: u32, style: &'a StyleParams) -> Self {
+        let verb = Reverb::new(sr, style.reverb);
+        let comp = style.compress.then(|| Compressor::new(sr));
+        Self { prep, sr, style, cursor: BlockCursor::default(), verb, comp }
+    }
+
+    /// Render the window after the previous one (windows must come in order).
+    fn render(&mut self, span: &mut Span) {
+        render_block(self.prep, self.sr, self.style, span, &mut self.cursor);
+        if let Some(v) = self.verb.as_mut() { v.process(span.buf); }
+        if let Some(c) = self.comp.as_mut() { c.process(span.buf); }
+    }
+}
+
+/// How far before a layering change a note may start and still take it (humanize jitter).
+const LAYERING_CHANGE_SLACK_SEC: f32 = 0.05;
+
+/// Note events and everything derived from them, computed once per piece.
+struct Prepared {
+    events: Vec<NoteEv>,
+    bpm: f32,
+    total_samples: usize,
+    /// One bass root per bar (empty when `style.bass` is off)
+    bass_roots: Vec<u8>,
+    bass_total_len: f32,
+    drum_pattern: Option<DrumPattern>,
+    /// Where the drums stop: the first bar line at or after the melody's end (always within
+    /// the buffer), so they neither stop before the last note-off nor play on into the
+    /// silent tail. Music timeline (see `tempo_map`)
+    drum_end: f32,
+    /// Count-in length: where the music (notes, bass, drums) starts
+    music_start: f32,
+    /// The MIDI's tempo and tempo changes (no notes). Tempo, bass and drum grids are laid
+    /// out on the music timeline; `warp` turns their times into audio time.
+    tempo_map: MonophonicMidi,
+    /// `style.layering_changes` at their audio times
+    layering_changes: Vec<(f32, Vec<Osc>)>,
+}
+
+impl Prepared {
+    /// Audio time of music-timeline time `t` (absolute: tempo changes count from the music start).
+    fn warp(&self, t: f32) -> f32 {
+        if self.tempo_map.tempo_changes.is_empty() { return t; }
+        self.music_start + self.tempo_map.time_at(t - self.music_start)
+    }
+
+    /// Layer stack of a note starting at audio time `t`: the last layering change
+    /// reached (notes humanized to just before a change count as after it), else `layering`.
+    fn layering_at<'a>(&'a self, t: f32, layering: &'a [Osc]) -> &'a [Osc] {
+        self.layering_changes
+            .iter()
+            .rev()
+            .find(|(at, _)| t >= at - LAYERING_CHANGE_SLACK_SEC)
+            .map_or(layering, |(_, l)| l)
+    }
+
+    /// Music-timeline time of audio time `t`.
+    fn unwarp(&self, t: f32) -> f32 {
+        if self.tempo_map.tempo_changes.is_empty() { return t; }
+        self.music_start + self.tempo_map.pos_at(t - self.music_start)
+    }
+}
+
+/// Steps 1-4: events, tempo, swing/humanize, glides, polyphony, output length.
+fn prepare(midi: &MonophonicMidi, sr: u32, style: &StyleParams) -> Result<Prepared> {
+    if style.layering.is_empty() || style.layering_changes.iter().any(|(_, l)| l.is_empty()) {
         return Err(anyhow!("StyleParams.layering must contain at least one oscillator"));
     }
+    time_sig_unit_steps(style.time_sig)?;
 
     // 1) Collect note events from MIDI
     let mut events = collect_events(midi)?;
@@ -75,54 +610,269 @@ pub fn render_wav_bytes_styled(midi: &MonophonicMidi, sr: u32, style: &StylePara
     let bpm = estimate_bpm(&events).unwrap_or(120.0);
 
     // 3) Apply swing & humanize
-    apply_swing_and_humanize(&mut events, style.swing, style.humanize, bpm);
+    apply_swing_and_humanize(&mut events, style.swing, style.humanize, bpm, style.seed);
+
+    // 3b) Portamento: legato notes remember where to glide from (before chords are stacked)
+    if style.portamento_sec > 0.0 {
+        mark_glides(&mut events);
+    }
+
+    // bass roots come from the single melody line
+    let (bass_roots, bass_total_len) = if style.bass {
+        let bar_len = 60.0 / bpm * BASS_BEATS_PER_BAR as f32;
+        let total_len = calc_total_len(&events);
+        let n_bars = (total_len / bar_len).ceil() as usize;
+        (bass_roots(&events, bar_len, n_bars), total_len)
+    } else {
+        (Vec::new(), 0.0)
+    };
+
+    // 3c) Tempo changes speed up / slow down the notes; tempo, bass roots and the drum
+    // grid above stay on the music timeline (the opening tempo)
+    let tempo_map = MonophonicMidi { tempo_changes: midi.tempo_changes.clone(), ..MonophonicMidi::new(midi.tempo_bpm) };
+    if !tempo_map.tempo_changes.is_empty() {
+        for e in events.iter_mut() {
+            e.t_on = tempo_map.time_at(e.t_on);
+            e.t_off = tempo_map.time_at(e.t_off);
+        }
+    }
+
+    // 3d) Count-in: the music moves back by whole count-in bars (bass roots above stay
+    // on the music's own bar grid)
+    let music_start = count_in_sec(bpm, style.count_in_bars);
+    if music_start > 0.0 {
+        for e in events.iter_mut() {
+            e.t_on += music_start;
+            e.t_off += music_start;
+        }
+    }
 
     // 4) Expand polyphony (triads/dyads) by cloning events and transposing by scale intervals
     if style.polyphony > 1 {
         expand_polyphony(&mut events, style.polyphony, style.scale);
     }
 
-    // 5) Render note layers into a mono buffer
+    // windows pick the notes up in onset order (swing and humanize can swap neighbours)
+    events.sort_by(|a, b| a.t_on.total_cmp(&b.t_on));
+
     let total_len = calc_total_len(&events);
-    let total_samples = (total_len * sr as f32).ceil() as usize + (sr as usize / 2); // tail 0.5s
-    let mut out = vec![0.0f32; total_samples];
+    let total_samples = (total_len * sr as f32).ceil() as usize + (style.effective_tail_sec() * sr as f32) as usize;
+    let drum_pattern = if style.percussion {
+        Some(match &style.drum_pattern {
+            Some(p) => p.clone(),
+            None => DrumPattern::for_time_sig(style.time_sig)?,
+        })
+    } else {
+        None
+    };
+    let layering_changes = style.layering_changes.iter().map(|(at, l)| (music_start + tempo_map.time_at(*at), l.clone())).collect();
+    let mut prep = Prepared { events, bpm, total_samples, bass_roots, bass_total_len, drum_pattern, drum_end: 0.0, music_start, tempo_map, layering_changes };
+    // on the music timeline, like the drum grid
+    if let Some(p) = &prep.drum_pattern {
+        let total_len = prep.unwarp(total_len);
+        let bar_len = 60.0 / bpm / 4.0 * p.steps_per_bar() as f32;
+        // (a melody ending on a bar line up to float error doesn't get an extra bar)
+        let bars = ((total_len - music_start) / bar_len - 1e-3).ceil();
+        let end = if bars >= 1.0 { music_start + bars * bar_len } else { total_len };
+        prep.drum_end = end.min(prep.unwarp(total_samples as f32 / sr as f32));
+    }
+
+    Ok(prep)
+}
+
+/// Beats per count-in bar.
+pub const COUNT_IN_BEATS_PER_BAR: u32 = 4;
+/// Count-in click levels (downbeat, other beats) and length in seconds.
+const COUNT_IN_GAIN: (f32, f32) = (0.3, 0.2);
+const COUNT_IN_CLICK_SEC: f32 = 0.04;
+
+fn count_in_sec(bpm: f32, bars: u32) -> f32 {
+    (bars * COUNT_IN_BEATS_PER_BAR) as f32 * 60.0 / bpm
+}
+
+/// Where the music starts in the rendered audio: 0, or the length of the
+/// `style.count_in_bars` count-in at the tempo the renderer derives from `midi`.
+pub fn music_start_sec(midi: &MonophonicMidi, style: &StyleParams) -> Result<f32> {
+    let events = collect_events(midi)?;
+    Ok(count_in_sec(estimate_bpm(&events).unwrap_or(120.0), style.count_in_bars))
+}
+
+/// `trim_tail` threshold relative to the peak, and the least that is kept after the last
+/// sample above it.
+pub const TRIM_TAIL_DB: f32 = -60.0;
+pub const TRIM_TAIL_KEEP_SEC: f32 = 0.1;
+/// Resolution of the tail search (seconds).
+const TAIL_CHUNK_SEC: f32 = 0.01;
+
+/// Peak per `TAIL_CHUNK_SEC` chunk of the (pre-normalization) master, to find where
+/// the audible part ends without keeping the samples.
+struct TailMeter {
+    chunk: usize,
+    peaks: Vec<f32>,
+}
+
+impl TailMeter {
+    fn new(sr: u32, total: usize) -> Self {
+        let chunk = ((TAIL_CHUNK_SEC * sr as f32) as usize).max(1);
+        Self { chunk, peaks: vec![0.0; total.div_ceil(chunk)] }
+    }
+
+    fn measure(&mut self, off: usize, buf: &[f32]) {
+        for (j, &x) in buf.iter().enumerate() {
+            let p = &mut self.peaks[(off + j) / self.chunk];
+            *p = p.max(x.abs());
+        }
+    }
+
+    /// End of the last chunk within `TRIM_TAIL_DB` of the loudest one (0 for silence).
+    fn audible_end(&self) -> usize {
+        let peak = self.peaks.iter().copied().fold(0.0f32, f32::max);
+        let thr = peak * 10f32.powf(TRIM_TAIL_DB / 20.0);
+        self.peaks.iter().rposition(|&p| p > thr).map_or(0, |k| (k + 1) * self.chunk)
+    }
+}
+
+/// Output length and fade-out length: the full piece, or with `trim_tail` the audible
+/// part plus enough to fade out over the whole `fade_out_sec` (at least
+/// `TRIM_TAIL_KEEP_SEC`), plus the reverb's decay (`REVERB_TAIL_SEC`) when it is on.
+fn output_len(style: &StyleParams, tail: &TailMeter, sr: u32, total: usize) -> (usize, f32) {
+    if !style.trim_tail {
+        return (total, style.fade_out_sec);
+    }
+    let verb = if style.reverb > 0.0 { REVERB_TAIL_SEC } else { 0.0 };
+    let keep_sec = style.fade_out_sec.max(TRIM_TAIL_KEEP_SEC) + verb;
+    let len = (tail.audible_end() + (keep_sec * sr as f32) as usize).clamp(1, total);
+    (len, style.fade_out_sec)
+}
+
+/// Master fade-in; none under a count-in (its first click is beat 1 and has its own attack).
+fn fade_in_len(style: &StyleParams) -> f32 {
+    if style.count_in_bars > 0 { 0.0 } else { style.fade_in_sec }
+}
+
+/// Metronome: a short sine beep per beat, an octave higher on each downbeat.
+fn render_count_in(out: &mut Span, sr: u32, bpm: f32, bars: u32) {
+    let spb = 60.0 / bpm;
+    for beat in 0..bars * COUNT_IN_BEATS_PER_BAR {
+        let t = beat as f32 * spb;
+        let (hz, gain) = if beat % COUNT_IN_BEATS_PER_BAR == 0 { (1760.0, COUNT_IN_GAIN.0) } else { (880.0, COUNT_IN_GAIN.1) };
+        render_note(out, sr, hz, t, t + COUNT_IN_CLICK_SEC, gain, Osc::Sine);
+    }
+}
+
+/// Window `[off, off + buf.len())` of a `total`-sample piece. Renderers work in absolute
+/// sample indices; only what falls inside the window is written.
+struct Span<'a> {
+    buf: &'a mut [f32],
+    off: usize,
+    total: usize,
+}
+
+impl<'a> Span<'a> {
+    fn new(buf: &'a mut [f32], off: usize, total: usize) -> Self {
+        Self { buf, off, total }
+    }
+
+    fn end(&self) -> usize { self.off + self.buf.len() }
+
+    fn add(&mut self, i: usize, x: f32) {
+        if i >= self.off && i < self.end() {
+            self.buf[i - self.off] += x;
+        }
+    }
+}
 
-    // Layer detune/gain recipe (depends on chosen layering)
-    let layer_specs = layering_specs(&style.layering);
+/// Where the next window picks up, so a window only looks at what can sound in it.
+#[derive(Default)]
+struct BlockCursor {
+    /// Next event (in onset order) not yet reached
+    next_ev: usize,
+    /// Events started before the window end and not over before its start, in onset order
+    active: Vec<usize>,
+    /// First sixteenth of the drum grid whose hits can still reach the window
+    drum_step: usize,
+    /// Tremolo LFO phase at the window start
+    trem_phase: f32,
+}
+
+/// Steps 5-6 for one window: note layers, tremolo, bass, drums (before normalization).
+/// Windows are rendered in order; `cur` is advanced past this one.
+fn render_block(p: &Prepared, sr: u32, style: &StyleParams, span: &mut Span, cur: &mut BlockCursor) {
+    span.buf.fill(0.0);
+    let sr_f = sr as f32;
+    let on_sample = |ev: &NoteEv| (ev.t_on * sr_f).max(0.0) as usize;
+    while cur.next_ev < p.events.len() && on_sample(&p.events[cur.next_ev]) < span.end() {
+        cur.active.push(cur.next_ev);
+        cur.next_ev += 1;
+    }
+    cur.active.retain(|&i| (p.events[i].t_off * sr_f) as usize > span.off);
+
+    // 5) Note layers
+    // variation: rotate the oscillators through the detune/gain slots once per section,
+    // so the primary timbre changes every `section_len_sec` and stays put within a section
+    let section_len = if style.section_len_sec > 0.0 { style.section_len_sec } else { 8.0 };
+    let section_specs = |layering: &[Osc], sec_idx: usize| {
+        let mut oscs = layering.to_vec();
+        let r = sec_idx % oscs.len();
+        oscs.rotate_left(r);
+        unison_specs(layering_specs(&oscs), style.unison_detune_cents, style.unison_voices)
+    };
+
+    for &i in &cur.active {
+        let ev = &p.events[i];
 
-    // variation: rotate layers gradually into "sections" ~ 8 sec
-    let section_len = 8.0_f32;
-    for (idx, ev) in events.iter().enumerate() {
         let sec_idx = (ev.t_on / section_len).floor() as usize;
-        // rotating layer order by de section + index
-        let mut rotated = layer_specs.clone();
-        if !rotated.is_empty() {
-            let r = (sec_idx + idx / 32) % rotated.len();
-            rotated.rotate_left(r);
+        // notes starting just after a boundary blend in from the previous section's layers
+        let into_sec = ev.t_on - sec_idx as f32 * section_len;
+        let xfade = SECTION_XFADE_SEC.min(section_len * 0.5);
+        let w = if sec_idx > 0 && into_sec < xfade { into_sec / xfade } else { 1.0 };
+
+        let vel_gain = style.velocity_curve.gain(ev.velocity);
+        // small gain variation in time (subtile pulsation)
+        let g_time = 0.9 + 0.1 * ((ev.t_on * 1.3).sin()).abs();
+        let layering = p.layering_at(ev.t_on, &style.layering);
+        let mut layers: Vec<(LayerSpec, f32)> = section_specs(layering, sec_idx).into_iter().map(|sp| (sp, w)).collect();
+        if w < 1.0 {
+            layers.extend(section_specs(layering, sec_idx - 1).into_iter().map(|sp| (sp, 1.0 - w)));
         }
 
-        for spec in &rotated {
-            let f0 = midi_pitch_to_hz(ev.pitch) * cents_to_ratio(spec.detune_cents);
-            // small gain variation in time (subtile pulsation)
-            let g_time = 0.9 + 0.1 * ((ev.t_on * 1.3).sin()).abs();
-            render_note(
-                &mut out, sr, f0, ev.t_on, ev.t_off,
-                (ev.velocity as f32 / 127.0) * spec.gain * g_time as f32,
-                spec.osc
-            );
+        for (spec, xg) in &layers {
+            let detune = cents_to_ratio(spec.detune_cents);
+            let f0 = midi_pitch_to_hz(ev.pitch) * detune;
+            let gain = vel_gain * spec.gain * g_time * xg;
+            let (f_from, glide_sec) = match ev.glide_from {
+                Some(gp) => (midi_pitch_to_hz(gp) * detune, style.portamento_sec),
+                None => (f0, 0.0),
+            };
+            let env = if style.pad { Some(SLOW_PAD_ENV) } else { spec.env };
+            render_note_glide(span, sr, f_from, f0, glide_sec, ev.t_on, ev.t_off, gain, spec.osc, env);
         }
     }
 
-    // 6) Drums channel (optional)
-    if style.percussion {
-        render_drums(&mut out, sr, bpm);
+    // 5b) Tremolo on the melodic layers (post-pass, independent of note envelopes)
+    if style.tremolo_depth > 0.0 {
+        apply_tremolo(span.buf, sr, p.bpm, style.tremolo_depth, &mut cur.trem_phase);
+    }
+
+    // 5c) Bassline (optional), rendered from the pre-polyphony melody roots
+    if style.bass {
+        render_bass(span, sr, p);
     }
 
-    // 7) Normalize softly to avoid clipping
-    normalize_soft(&mut out, 0.99);
+    // 5d) Count-in clicks (optional)
+    if style.count_in_bars > 0 {
+        render_count_in(span, sr, p.bpm, style.count_in_bars);
+    }
 
-    // 8) Encode to WAV 16-bit PCM in-memory
-    write_wav_i16(&out, sr)
+    // 6) Drums channel (optional), on its own bus
+    if let Some(pattern) = &p.drum_pattern {
+        let mut bus = vec![0.0f32; span.buf.len()];
+        let mut drums = Span::new(&mut bus, span.off, span.total);
+        render_drums(&mut drums, sr, p, pattern, time_sig_pulse_steps(style.time_sig), style.drum_intro_bars, &style.drum_mix, style.seed, style.noise_color, &mut cur.drum_step);
+        for (y, d) in span.buf.iter_mut().zip(&bus) {
+            *y += style.drum_mix.bus * d;
+        }
+    }
 }
 
 /* =========================
@@ -135,6 +885,8 @@ struct NoteEv {
     t_on: f32,
     t_off: f32,
     velocity: u8,
+    /// Previous pitch to glide from (portamento), if this note is played legato.
+    glide_from: Option<u8>,
 }
 
 fn collect_events(midi: &MonophonicMidi) -> Result<Vec<NoteEv>> {
@@ -144,12 +896,12 @@ fn collect_events(midi: &MonophonicMidi) -> Result<Vec<NoteEv>> {
     for n in midi.notes.iter() {
         let pitch: u8 = n.pitch;
         // converting to f32
-        let t_on: f32  = n.start as f32;
-        let t_off: f32 = n.end as f32;
+        let t_on: f32  = n.start;
+        let t_off: f32 = n.end;
         let velocity: u8 = n.velocity;
 
         if t_off > t_on {
-            evs.push(NoteEv { pitch, t_on, t_off, velocity });
+            evs.push(NoteEv { pitch, t_on, t_off, velocity, glide_from: None });
         }
     }
 
@@ -180,8 +932,13 @@ fn calc_total_len(evs: &[NoteEv]) -> f32 {
    Swing & Humanize
    ========================= */
 
-fn apply_swing_and_humanize(evs: &mut [NoteEv], swing: f32, human: f32, bpm: f32) {
+/// Largest relative note-length change at `human = 1` (humanize is capped at 0.4,
+/// so at most ±10%).
+const HUMANIZE_LENGTH_JITTER: f32 = 0.25;
+
+fn apply_swing_and_humanize(evs: &mut [NoteEv], swing: f32, human: f32, bpm: f32, seed: u64) {
     if evs.is_empty() { return; }
+    let mix = seed_mix(seed);
     let swing = swing.clamp(0.0, 0.35);
     let human = human.clamp(0.0, 0.4);
 
@@ -198,43 +955,73 @@ fn apply_swing_and_humanize(evs: &mut [NoteEv], swing: f32, human: f32, bpm: f32
         if human > 0.0 {
             // Timing jitter ±2% of note length scaled by human
             let dur = (e.t_off - e.t_on).max(1e-4);
-            let jt = (rand_hash(i as u64) * 2.0 - 1.0) * 0.02 * human * dur;
+            let jt = (rand_hash(i as u64 ^ mix) * 2.0 - 1.0) * 0.02 * human * dur;
             e.t_on = (e.t_on + jt).max(0.0);
             e.t_off = (e.t_off + jt).max(e.t_on + 1e-4);
 
+            // Length jitter, independent of the timing: ±`HUMANIZE_LENGTH_JITTER` of the
+            // length scaled by human (notes only ever get shorter or longer, never inverted)
+            let jl = (rand_hash((i as u64) ^ 0xC2B2AE3D27D4EB4F ^ mix) * 2.0 - 1.0) * HUMANIZE_LENGTH_JITTER * human;
+            e.t_off = (e.t_on + dur * (1.0 + jl)).max(e.t_on + 1e-4);
+
             // Velocity jitter ±12% scaled by human
-            let jv = 1.0 + (rand_hash((i as u64) ^ 0x9E3779B97F4A7C15) * 2.0 - 1.0) * 0.12 * human;
+            let jv = 1.0 + (rand_hash((i as u64) ^ 0x9E3779B97F4A7C15 ^ mix) * 2.0 - 1.0) * 0.12 * human;
             let vv = (e.velocity as f32 * jv).clamp(1.0, 127.0);
             e.velocity = vv as u8;
         }
     }
 }
 
+/* =========================
+   Portamento
+   ========================= */
+
+/// Gaps shorter than this (seconds) count as legato and get a glide.
+const LEGATO_GAP_SEC: f32 = 0.05;
+
+fn mark_glides(evs: &mut [NoteEv]) {
+    for i in 1..evs.len() {
+        let prev = evs[i - 1];
+        let gap = evs[i].t_on - prev.t_off;
+        if gap < LEGATO_GAP_SEC && evs[i].t_on > prev.t_on && prev.pitch != evs[i].pitch {
+            evs[i].glide_from = Some(prev.pitch);
+        }
+    }
+}
+
 /* =========================
    Polyphony expansion
    ========================= */
 
+/// Semitones of the third and fifth stacked on each melody note.
+fn chord_intervals(scale: ScaleKind) -> (i32, i32) {
+    match scale {
+        ScaleKind::Major => (4, 7),
+        ScaleKind::Minor | ScaleKind::Blues => (3, 7),
+        ScaleKind::WholeTone => (4, 8), // augmented triad
+    }
+}
+
 fn expand_polyphony(evs: &mut Vec<NoteEv>, voices: usize, scale: ScaleKind) {
-    let voices = voices.min(3).max(1);
+    let voices = voices.clamp(1, 3);
     if voices == 1 { return; }
 
     // Copy original events
     let base = evs.clone();
-    let (third_semi, fifth_semi) = match scale {
-        ScaleKind::Major => (4i32, 7i32),
-        ScaleKind::Minor => (3i32, 7i32),
-    };
+    let (third_semi, fifth_semi) = chord_intervals(scale);
 
     if voices >= 2 {
         for e in &base {
             let p = (e.pitch as i32 + third_semi).clamp(0, 127) as u8;
-            evs.push(NoteEv { pitch: p, ..*e });
+            let g = e.glide_from.map(|g| (g as i32 + third_semi).clamp(0, 127) as u8);
+            evs.push(NoteEv { pitch: p, glide_from: g, ..*e });
         }
     }
     if voices >= 3 {
         for e in &base {
             let p = (e.pitch as i32 + fifth_semi).clamp(0, 127) as u8;
-            evs.push(NoteEv { pitch: p, ..*e });
+            let g = e.glide_from.map(|g| (g as i32 + fifth_semi).clamp(0, 127) as u8);
+            evs.push(NoteEv { pitch: p, glide_from: g, ..*e });
         }
     }
 
@@ -246,13 +1033,49 @@ fn expand_polyphony(evs: &mut Vec<NoteEv>, voices: usize, scale: ScaleKind) {
    Layering (detune & gain)
    ========================= */
 
+/// Crossfade between the layer sets of two adjacent sections (seconds of note onsets).
+const SECTION_XFADE_SEC: f32 = 0.5;
+
+/// Per-layer envelope in seconds; attack, decay and release are squeezed to fit short notes.
+/// The release ends at the note-off (notes never ring past it).
 #[derive(Clone, Copy)]
-struct LayerSpec { osc: Osc, detune_cents: f32, gain: f32 }
+struct Adsr { attack: f32, decay: f32, sustain: f32, release: f32 }
+
+impl Adsr {
+    /// Envelope at `t` seconds into a note lasting `dur` seconds.
+    fn at(&self, t: f32, dur: f32) -> f32 {
+        let squeeze = ((self.attack + self.decay + self.release) / dur.max(1e-6)).max(1.0);
+        let (a, d, r) = (self.attack / squeeze, self.decay / squeeze, self.release / squeeze);
+        let ad = if t < a {
+            t / a
+        } else if t < a + d {
+            1.0 - (1.0 - self.sustain) * (t - a) / d
+        } else {
+            self.sustain
+        };
+        let rel = if r > 0.0 { ((dur - t) / r).clamp(0.0, 1.0) } else { 1.0 };
+        ad * rel
+    }
+}
+
+/// Fast pluck for a leading saw/square.
+const PLUCK_ENV: Adsr = Adsr { attack: 0.005, decay: 0.25, sustain: 0.35, release: 0.05 };
+/// Slow swell for a supporting sine.
+const PAD_ENV: Adsr = Adsr { attack: 0.12, decay: 0.2, sustain: 0.8, release: 0.15 };
+
+/// Long swell and fade for every layer with `StyleParams::pad`.
+const SLOW_PAD_ENV: Adsr = Adsr { attack: 0.8, decay: 0.6, sustain: 0.85, release: 0.8 };
+
+/// One oscillator layer; `env` None = the shared `ad_env`.
+#[derive(Clone, Copy)]
+struct LayerSpec { osc: Osc, detune_cents: f32, gain: f32, env: Option<Adsr> }
 
 fn layering_specs(list: &[Osc]) -> Vec<LayerSpec> {
     if list.is_empty() {
-        return vec![LayerSpec { osc: Osc::Saw, detune_cents: 0.0, gain: 1.0 }];
+        return vec![LayerSpec { osc: Osc::Saw, detune_cents: 0.0, gain: 1.0, env: None }];
     }
+    // a mixed stack gets role envelopes: plucked lead over a swelling sine pad
+    let mixed = list.contains(&Osc::Sine) && list.iter().any(|&o| o != Osc::Sine);
     let mut specs = Vec::new();
     for (i, &osc) in list.iter().enumerate() {
         let (det, g) = match (osc, i) {
@@ -265,12 +1088,44 @@ fn layering_specs(list: &[Osc]) -> Vec<LayerSpec> {
             (Osc::Sine, 0)   => (  0.0, 0.80),
             (Osc::Sine, 1)   => ( 12.0, 0.15), // octave up hint
             (Osc::Sine, _)   => (  4.0, 0.05),
+            #[cfg(any(test, feature = "testing"))]
+            (Osc::Impulse, _) => (0.0, 1.0),
         };
-        specs.push(LayerSpec { osc, detune_cents: det, gain: g });
+        let env = match (osc, i) {
+            _ if !mixed => None,
+            (Osc::Saw | Osc::Square, 0) => Some(PLUCK_ENV),
+            (Osc::Sine, i) if i > 0 => Some(PAD_ENV),
+            _ => None,
+        };
+        specs.push(LayerSpec { osc, detune_cents: det, gain: g, env });
     }
     specs
 }
 
+/// Upper bound for `StyleParams::unison_voices`.
+pub const UNISON_MAX_VOICES: usize = 7;
+
+/// Widen the layer stack by `spread` cents (detuned layers move outward by half of it)
+/// and split every layer into `voices` voices evenly spread over `spread` around its
+/// detune. Voice gains scale by 1/sqrt(voices), keeping the layer's power.
+fn unison_specs(specs: Vec<LayerSpec>, spread: f32, voices: usize) -> Vec<LayerSpec> {
+    let voices = voices.clamp(1, UNISON_MAX_VOICES);
+    let spread = spread.max(0.0);
+    if spread == 0.0 && voices == 1 {
+        return specs;
+    }
+    let g = 1.0 / (voices as f32).sqrt();
+    let mut out = Vec::with_capacity(specs.len() * voices);
+    for sp in specs {
+        let center = sp.detune_cents + sp.detune_cents.signum() * spread * 0.5;
+        for v in 0..voices {
+            let off = if voices == 1 { 0.0 } else { spread * (v as f32 / (voices - 1) as f32 - 0.5) };
+            out.push(LayerSpec { detune_cents: center + off, gain: sp.gain * g, ..sp });
+        }
+    }
+    out
+}
+
 /* =========================
    Rendering: oscillators & notes
    ========================= */
@@ -288,6 +1143,8 @@ fn osc_sample(osc: Osc, phase: f32) -> f32 {
         Osc::Sine => (2.0 * PI * phase).sin(),
         Osc::Saw => 2.0 * (phase.fract()) - 1.0,
         Osc::Square => if (phase.fract()) < 0.5 { 1.0 } else { -1.0 },
+        #[cfg(any(test, feature = "testing"))]
+        Osc::Impulse => 0.0, // written by `render_note_glide` directly
     }
 }
 
@@ -300,24 +1157,127 @@ fn ad_env(rel: f32) -> f32 {
     a * d
 }
 
-fn render_note(out: &mut [f32], sr: u32, f0: f32, t_on: f32, t_off: f32, gain: f32, osc: Osc) {
+fn render_note(out: &mut Span, sr: u32, f0: f32, t_on: f32, t_off: f32, gain: f32, osc: Osc) {
+    render_note_glide(out, sr, f0, f0, 0.0, t_on, t_off, gain, osc, None);
+}
+
+/// Like `render_note`, but the pitch slides linearly from `f_from` to `f0` over `glide_sec`,
+/// shaped by `env` (None = `ad_env`).
+#[allow(clippy::too_many_arguments)]
+fn render_note_glide(out: &mut Span, sr: u32, f_from: f32, f0: f32, glide_sec: f32, t_on: f32, t_off: f32, gain: f32, osc: Osc, env: Option<Adsr>) {
     if t_off <= t_on { return; }
     let sr_f = sr as f32;
     let start = (t_on * sr_f).max(0.0) as usize;
-    let end = ((t_off * sr_f) as usize).min(out.len());
-    if end <= start { return; }
+    let end = ((t_off * sr_f) as usize).min(out.total);
+    if end <= start || start >= out.end() { return; }
+    #[cfg(any(test, feature = "testing"))]
+    if osc == Osc::Impulse {
+        out.add(start, 1.0);
+        return;
+    }
 
-    let mut phase = 0.0f32;
-    let inc = f0 / sr_f;
+    // phase locked to the timeline: every note at a given frequency continues one running
+    // cycle, so back-to-back repeats (and layer switches at the same pitch) join seamlessly;
+    // computed per sample, so a window starts mid-note at the right phase
+    let phase0 = f_from as f64 * start as f64 / sr as f64;
+    let glide_len = (glide_sec.max(0.0) * sr_f) as usize;
 
     let dur = (end - start).max(1) as f32;
-    for i in start..end {
+    for i in start.max(out.off)..end.min(out.end()) {
         let rel = (i - start) as f32 / dur;
-        let env = ad_env(rel);
+        let env = match env {
+            Some(adsr) => adsr.at((i - start) as f32 / sr_f, dur / sr_f),
+            None => ad_env(rel),
+        };
+        let phase = (phase0 + glide_cycles(i - start, f_from, f0, glide_len) / sr as f64).fract() as f32;
         let s = osc_sample(osc, phase) * env * gain;
-        out[i] += s;
-        phase += inc;
-        if phase >= 1.0 { phase -= 1.0; }
+        out.add(i, s);
+    }
+}
+
+/// Cycles (times the sample rate) played in the first `n` samples of a note whose frequency
+/// moves linearly from `f_from` to `f0` over `glide_len` samples, then stays at `f0`.
+fn glide_cycles(n: usize, f_from: f32, f0: f32, glide_len: usize) -> f64 {
+    let (f_from, f0) = (f_from as f64, f0 as f64);
+    if glide_len == 0 {
+        return n as f64 * f0;
+    }
+    let ramp = |k: usize| {
+        let k = k as f64;
+        k * f_from + (f0 - f_from) * k * (k - 1.0) / (2.0 * glide_len as f64)
+    };
+    if n <= glide_len {
+        ramp(n)
+    } else {
+        ramp(glide_len) + (n - glide_len) as f64 * f0
+    }
+}
+
+/* =========================
+   Tremolo (amplitude LFO)
+   ========================= */
+
+/// Modulates gain between (1 - depth) and 1 with a raised-cosine LFO,
+/// one cycle per eighth note at the given BPM. `phase` carries over between calls.
+fn apply_tremolo(buf: &mut [f32], sr: u32, bpm: f32, depth: f32, phase: &mut f32) {
+    let depth = depth.clamp(0.0, 1.0);
+    if depth <= 0.0 { return; }
+    let rate_hz = bpm / 60.0 * 2.0;
+    let inc = rate_hz / sr as f32;
+    for x in buf.iter_mut() {
+        let lfo = 0.5 - 0.5 * (2.0 * PI * *phase).cos(); // 0..1
+        *x *= 1.0 - depth * lfo;
+        *phase += inc;
+        if *phase >= 1.0 { *phase -= 1.0; }
+    }
+}
+
+/* =========================
+   Bassline
+   ========================= */
+
+const BASS_BEATS_PER_BAR: usize = 4;
+
+/// Root for each bar: the melody note sounding at the bar start (or the next one to start),
+/// dropped into the bass register (MIDI 31..43).
+fn bass_roots(evs: &[NoteEv], bar_len: f32, n_bars: usize) -> Vec<u8> {
+    let mut roots = Vec::with_capacity(n_bars);
+    let mut last = evs.first().map(|e| e.pitch).unwrap_or(36);
+    for b in 0..n_bars {
+        let t = b as f32 * bar_len;
+        let sounding = evs.iter().find(|e| e.t_on <= t && e.t_off > t)
+            .or_else(|| evs.iter().filter(|e| e.t_on >= t).min_by(|a, c| a.t_on.total_cmp(&c.t_on)));
+        if let Some(e) = sounding { last = e.pitch; }
+        let mut p = last as i32;
+        while p > 43 { p -= 12; }
+        while p < 31 { p += 12; }
+        roots.push(p as u8);
+    }
+    roots
+}
+
+/// One sine note per beat on each bar's root, up to the end of the melody, the whole
+/// line starting at the music start (following the tempo changes).
+fn render_bass(out: &mut Span, sr: u32, p: &Prepared) {
+    bass_schedule(&p.bass_roots, p.bass_total_len, p.bpm, p.music_start, |root, t_on, t_off, gain| {
+        render_note(out, sr, midi_pitch_to_hz(root), p.warp(t_on), p.warp(t_off), gain, Osc::Sine)
+    });
+}
+
+/// Report every bass note (root, on, off, gain) of `render_bass`.
+fn bass_schedule(roots: &[u8], total_len: f32, bpm: f32, start: f32, mut note: impl FnMut(u8, f32, f32, f32)) {
+    let spb = 60.0 / bpm;
+    let bar_len = spb * BASS_BEATS_PER_BAR as f32;
+
+    for (b, &root) in roots.iter().enumerate() {
+        for k in 0..BASS_BEATS_PER_BAR {
+            let t_on = b as f32 * bar_len + k as f32 * spb;
+            if t_on >= total_len { break; }
+            let t_on = start + t_on;
+            // downbeat a little stronger
+            let gain = if k == 0 { 0.45 } else { 0.35 };
+            note(root, t_on, t_on + spb * 0.9, gain);
+        }
     }
 }
 
@@ -325,83 +1285,257 @@ fn render_note(out: &mut [f32], sr: u32, f0: f32, t_on: f32, t_off: f32, gain: f
    Drums: kick/snare/hat
    ========================= */
 
-fn render_drums(out: &mut [f32], sr: u32, bpm: f32) {
-    let sr_f = sr as f32;
+/// Drum bus level at time `t`: silent for `intro_bars`, linear ramp in over the next bar,
+/// linear ramp out over the last bar before `end_secs`.
+fn drum_level(t: f32, bar_len: f32, intro_bars: u32, end_secs: f32) -> f32 {
+    let fade_in = ((t - intro_bars as f32 * bar_len) / bar_len).clamp(0.0, 1.0);
+    let fade_out = ((end_secs - t) / bar_len).clamp(0.0, 1.0);
+    fade_in * fade_out
+}
+
+/// One scheduled drum hit (times in seconds, gain includes the drum level and `DrumMix` gain).
+#[derive(Clone, Copy)]
+enum DrumHit {
+    Kick { t: f32, dur: f32, start_hz: f32, end_hz: f32, gain: f32 },
+    /// Pitched kick used by the fills
+    Tom { t: f32, dur: f32, start_hz: f32, end_hz: f32, gain: f32 },
+    Snare { t: f32, dur: f32, tone: f32, gain: f32 },
+    Hat { t: f32, dur: f32, gain: f32 },
+}
+
+/// Longest a drum hit lasts from its grid step (kick length; the snare's offset included).
+const DRUM_HIT_MAX_SEC: f32 = 0.2;
+
+/// Walk the sixteenth grid from step `first_step` after `start_secs` (the music start) to
+/// `end_secs` (the drums' end, faded out over the bar before it), stopping early at
+/// `until_secs`, and report every hit.
+#[allow(clippy::too_many_arguments)]
+fn drum_schedule(
+    bpm: f32,
+    pattern: &DrumPattern,
+    pulse: usize,
+    start_secs: f32,
+    intro_bars: u32,
+    mix: &DrumMix,
+    end_secs: f32,
+    first_step: usize,
+    until_secs: f32,
+    mut hit: impl FnMut(DrumHit),
+) {
+    let steps = pattern.steps_per_bar();
+    if steps == 0 { return; }
     let spb = 60.0 / bpm; // seconds per beat
-    let eighth = spb / 2.0;
+    let sixteenth = spb / 4.0;
+    let bar_len = sixteenth * steps as f32;
+    // Fills cover the second half of the bar, from the first pulse at or after the middle
+    let pulse = pulse.max(1);
+    let fill_from = (steps / 2).div_ceil(pulse) * pulse;
+    let fill_from = if fill_from >= steps { steps / 2 } else { fill_from };
 
-    // We'll lay hats on every eighth, kick on beats 1 & 3, snare on 2 & 4 (4/4)
-    let total_secs = out.len() as f32 / sr_f;
-    let mut t = 0.0;
-    let mut idx = 0usize;
-    while t < total_secs {
-        let beat_num = (t / spb).floor() as i32;
-        let in_bar = beat_num % 4;
-        let is_beat = (t % spb) < 1e-6;
+    // Integer step counter on the sixteenth grid (no float modulo drift)
+    let mut idx = first_step;
+    loop {
+        let t = start_secs + idx as f32 * sixteenth;
+        if t >= end_secs || t >= until_secs { break; }
+        let level = drum_level(t - start_secs, bar_len, intro_bars, end_secs - start_secs);
+        if level <= 0.0 { idx += 1; continue; }
+        let bar = (idx / steps) as u32;
+        let pos = idx % steps;
+        let every = pattern.fill_every_bars;
+        let in_fill = every > 0 && bar % every == every - 1 && pos >= fill_from;
 
-        // Kick on 1 & 3
-        if is_beat && (in_bar == 0 || in_bar == 2) {
-            render_kick(out, sr, t, 0.18, 75.0, 45.0);
+        if in_fill {
+            // Second half of the phrase's last bar: snare roll with toms falling in pitch
+            let rel = (pos - fill_from) as f32 / (steps - fill_from).max(1) as f32;
+            if pos.is_multiple_of(2) {
+                hit(DrumHit::Snare { t: t + 0.005, dur: 0.10, tone: 0.5, gain: (0.5 + 0.5 * rel) * mix.snare * level });
+            } else {
+                let hz = 180.0 - 80.0 * rel;
+                hit(DrumHit::Tom { t, dur: 0.12, start_hz: hz, end_hz: hz * 0.7, gain: 0.67 * mix.kick * level });
+            }
+        } else {
+            if pattern.kick[pos] {
+                hit(DrumHit::Kick { t, dur: 0.18, start_hz: 75.0, end_hz: 45.0, gain: mix.kick * level });
+            }
+            if pattern.snare[pos] {
+                hit(DrumHit::Snare { t: t + 0.005, dur: 0.14, tone: 0.6, gain: mix.snare * level });
+            }
+            if pattern.hat[pos] {
+                hit(DrumHit::Hat { t, dur: 0.05, gain: mix.hat * level });
+            }
         }
-        // Snare on 2 & 4
-        if is_beat && (in_bar == 1 || in_bar == 3) {
-            render_snare(out, sr, t + 0.005, 0.14, 0.6);
+        idx += 1;
+    }
+}
+
+/// The drums from the music start to `p.drum_end`, hits following the tempo changes.
+/// `step` is the first grid step whose hits can reach this window or a later one; it is
+/// moved up to this window's start.
+#[allow(clippy::too_many_arguments)]
+fn render_drums(out: &mut Span, sr: u32, p: &Prepared, pattern: &DrumPattern, pulse: usize, intro_bars: u32, mix: &DrumMix, seed: u64, color: NoiseColor, step: &mut usize) {
+    let noise = NoiseSource { rng: NoiseRng::new(seed), color };
+    let reach = out.off as f32 / sr as f32 - DRUM_HIT_MAX_SEC;
+    if reach > p.music_start {
+        let reach = p.unwarp(reach);
+        let sixteenth = 60.0 / p.bpm / 4.0;
+        while p.music_start + *step as f32 * sixteenth < reach {
+            *step += 1;
         }
-        // Hats every eighth
-        render_hat(out, sr, t, 0.05, 0.25);
+    }
+    let window_end_secs = p.unwarp(out.end() as f32 / sr as f32);
+    drum_schedule(p.bpm, pattern, pulse, p.music_start, intro_bars, mix, p.drum_end, *step, window_end_secs, |h| match h {
+        DrumHit::Kick { t, dur, start_hz, end_hz, gain } | DrumHit::Tom { t, dur, start_hz, end_hz, gain } => {
+            render_kick(out, sr, p.warp(t), dur, start_hz, end_hz, gain)
+        }
+        DrumHit::Snare { t, dur, tone, gain } => render_snare(out, sr, &noise, p.warp(t), dur, tone, gain),
+        DrumHit::Hat { t, dur, gain } => render_hat(out, sr, &noise, p.warp(t), dur, gain),
+    });
+}
 
-        idx += 1;
-        t = idx as f32 * eighth;
+/// General MIDI percussion keys (channel 10) used by `drum_hits`.
+pub const GM_KICK: u8 = 36;
+pub const GM_SNARE: u8 = 38;
+pub const GM_CLOSED_HAT: u8 = 42;
+/// Low floor tom .. high tom; fills pick by pitch.
+const GM_TOMS: [u8; 4] = [41, 45, 48, 50];
+
+/// The drum hits `render_wav_bytes_styled` would play for `midi` (empty when
+/// `style.percussion` is off), as GM percussion notes on the same timeline, e.g. for
+/// `MonophonicMidi::drums`. Velocity follows the hit gain (`DrumMix` levels, without the bus gain).
+/// A count-in is not part of that timeline: hits are reported relative to the music start.
+pub fn drum_hits(midi: &MonophonicMidi, style: &StyleParams) -> Result<Vec<Note>> {
+    const SR: u32 = 44_100;
+    let prep = prepare(midi, SR, style)?;
+    let Some(pattern) = prep.drum_pattern.as_ref() else { return Ok(Vec::new()) };
+    let total_secs = prep.total_samples as f32 / SR as f32;
+    let mut notes = Vec::new();
+    let mut push = |pitch: u8, t: f32, dur: f32, gain: f32| {
+        if prep.warp(t) + dur <= total_secs {
+            let t = t - prep.music_start;
+            let velocity = (gain * 127.0).round().clamp(1.0, 127.0) as u8;
+            notes.push(Note { pitch, start: t, end: t + dur, velocity, glide: false });
+        }
+    };
+    drum_schedule(prep.bpm, pattern, time_sig_pulse_steps(style.time_sig), prep.music_start, style.drum_intro_bars, &style.drum_mix, prep.drum_end, 0, f32::INFINITY, |h| match h {
+        DrumHit::Kick { t, dur, gain, .. } => push(GM_KICK, t, dur, gain),
+        DrumHit::Tom { t, dur, start_hz, gain, .. } => {
+            let k = (((start_hz - 100.0) / 80.0).clamp(0.0, 1.0) * (GM_TOMS.len() - 1) as f32).round() as usize;
+            push(GM_TOMS[k], t, dur, gain)
+        }
+        DrumHit::Snare { t, dur, gain, .. } => push(GM_SNARE, t, dur, gain),
+        DrumHit::Hat { t, dur, gain } => push(GM_CLOSED_HAT, t, dur, gain),
+    });
+    Ok(notes)
+}
+
+/// GM program (0-based) of the bass track in `multi_track_midi` (Synth Bass 1).
+pub const GM_SYNTH_BASS: u8 = 38;
+
+/// The parts `render_wav_bytes_styled` plays for `midi`, one track each: "Melody"
+/// (channel 0, the primary layer's program unless `midi.program` is set), "Harmony"
+/// (channel 1: the thirds/fifths of `style.polyphony`), "Bass" (channel 2) and "Drums"
+/// (`MIDI_DRUM_CHANNEL`, as `drum_hits`). Parts the style leaves out have no track.
+/// Bass and drums are relative to the music start (no count-in), velocities follow their gain.
+pub fn multi_track_midi(midi: &MonophonicMidi, style: &StyleParams) -> Result<MultiTrackMidi> {
+    const SR: u32 = 44_100;
+    let prep = prepare(midi, SR, style)?;
+    let program = midi.program.or_else(|| style.layering.first().map(|o| o.gm_program()));
+    let velocity = |gain: f32| (gain * 127.0).round().clamp(1.0, 127.0) as u8;
+
+    let mut out = MultiTrackMidi::new(midi.tempo_bpm);
+    out.time_signature = midi.time_signature.or((style.time_sig != (4, 4)).then_some(style.time_sig));
+    out.key = midi.key;
+    out.tempo_changes = midi.tempo_changes.clone();
+    out.tracks.push(MidiTrack { name: "Melody".into(), channel: 0, program, notes: midi.notes.clone() });
+
+    let voices = style.polyphony.clamp(1, 3);
+    if voices > 1 {
+        let (third, fifth) = chord_intervals(style.scale);
+        let mut notes = Vec::new();
+        for interval in [third, fifth].into_iter().take(voices - 1) {
+            // each voice glides along with the melody, but not in from the previous voice's end
+            notes.extend(midi.notes.iter().enumerate().map(|(i, n)| Note {
+                pitch: (n.pitch as i32 + interval).clamp(0, 127) as u8,
+                glide: n.glide && i > 0,
+                ..n.clone()
+            }));
+        }
+        out.tracks.push(MidiTrack { name: "Harmony".into(), channel: 1, program, notes });
+    }
+
+    if style.bass {
+        let mut notes = Vec::new();
+        bass_schedule(&prep.bass_roots, prep.bass_total_len, prep.bpm, 0.0, |root, t_on, t_off, gain| {
+            notes.push(Note { pitch: root, start: t_on, end: t_off, velocity: velocity(gain), glide: false });
+        });
+        out.tracks.push(MidiTrack { name: "Bass".into(), channel: 2, program: Some(GM_SYNTH_BASS), notes });
     }
+
+    let drums = drum_hits(midi, style)?;
+    if !drums.is_empty() {
+        out.tracks.push(MidiTrack { name: "Drums".into(), channel: MIDI_DRUM_CHANNEL, program: None, notes: drums });
+    }
+    Ok(out)
 }
 
-fn render_kick(out: &mut [f32], sr: u32, t_on: f32, dur: f32, start_hz: f32, end_hz: f32) {
+/// Sample range of a drum hit, or None when it is cut by the piece end or misses the window.
+fn hit_range(out: &Span, sr: u32, t_on: f32, dur: f32) -> Option<(usize, usize)> {
     let start = (t_on * sr as f32) as usize;
     let end = ((t_on + dur) * sr as f32) as usize;
-    if end <= start || end > out.len() { return; }
+    if end <= start || end > out.total || end <= out.off || start >= out.end() { return None; }
+    Some((start, end))
+}
+
+fn render_kick(out: &mut Span, sr: u32, t_on: f32, dur: f32, start_hz: f32, end_hz: f32, gain: f32) {
+    let Some((start, end)) = hit_range(out, sr, t_on, dur) else { return };
     let mut phase = 0.0f32;
-    for i in start..end {
+    for i in start..end.min(out.end()) {
         let rel = (i - start) as f32 / ((end - start) as f32);
         let freq = start_hz + (end_hz - start_hz) * rel;
         let inc = freq / sr as f32;
         let env = (1.0 - rel).powf(4.0); // sharp decay
-        let s = (2.0 * PI * phase).sin() * env * 0.9;
-        out[i] += s;
+        let s = (2.0 * PI * phase).sin() * env * gain;
+        out.add(i, s);
         phase = (phase + inc) % 1.0;
     }
 }
 
-fn render_snare(out: &mut [f32], sr: u32, t_on: f32, dur: f32, tone: f32) {
+fn render_snare(out: &mut Span, sr: u32, noise: &NoiseSource, t_on: f32, dur: f32, tone: f32, gain: f32) {
     // noise + short tone
-    let start = (t_on * sr as f32) as usize;
-    let end = ((t_on + dur) * sr as f32) as usize;
-    if end <= start || end > out.len() { return; }
+    let Some((start, end)) = hit_range(out, sr, t_on, dur) else { return };
     let mut phase = 0.0f32;
     let inc = 220.0 / sr as f32;
-    for i in start..end {
+    let mut rng = noise.at(NOISE_STREAM_SNARE, start);
+    for i in start..end.min(out.end()) {
         let rel = (i - start) as f32 / ((end - start) as f32);
         let env = (1.0 - rel).powf(3.0);
         // tone
         let t = (2.0 * PI * phase).sin() * tone * env * 0.4;
         phase = (phase + inc) % 1.0;
         // noise
-        let n = (rand_hash(i as u64) * 2.0 - 1.0) * env * 0.6;
-        out[i] += t + n;
+        let n = rng.next_bipolar() * env * 0.6;
+        out.add(i, (t + n) * gain);
     }
 }
 
-fn render_hat(out: &mut [f32], sr: u32, t_on: f32, dur: f32, gain: f32) {
-    let start = (t_on * sr as f32) as usize;
-    let end = ((t_on + dur) * sr as f32) as usize;
-    if end <= start || end > out.len() { return; }
-    // bright noise with HP-ish response
-    for i in start..end {
+/// Cutoff of the hi-hat high-pass (keeps the hats out of the kick's range).
+const HAT_HPF_HZ: f32 = 7000.0;
+
+fn render_hat(out: &mut Span, sr: u32, noise: &NoiseSource, t_on: f32, dur: f32, gain: f32) {
+    let Some((start, end)) = hit_range(out, sr, t_on, dur) else { return };
+    // noise through a first-order high-pass: y[n] = a * (y[n-1] + x[n] - x[n-1]);
+    // the filter runs from the hit start so a window boundary doesn't reset its state
+    let rc = 1.0 / (2.0 * PI * HAT_HPF_HZ.min(0.45 * sr as f32));
+    let a = rc / (rc + 1.0 / sr as f32);
+    let mut rng = noise.at(NOISE_STREAM_HAT, start);
+    let (mut x_prev, mut y_prev) = (0.0f32, 0.0f32);
+    for i in start..end.min(out.end()) {
         let rel = (i - start) as f32 / ((end - start) as f32);
         let env = (1.0 - rel).powf(4.0);
-        let n = rand_hash((i * 13) as u64) * 2.0 - 1.0;
-        // crude "HPF": subtract a smoothed version
-        let bright = n - 0.5 * (rand_hash((i * 11) as u64) * 2.0 - 1.0);
-        out[i] += bright * env * gain;
+        let x = rng.next_bipolar();
+        let y = a * (y_prev + x - x_prev);
+        (x_prev, y_prev) = (x, y);
+        out.add(i, y * env * gain);
     }
 }
 
@@ -409,18 +1543,216 @@ fn render_hat(out: &mut [f32], sr: u32, t_on: f32, dur: f32, gain: f32) {
    Utils: normalize & WAV writer
    ========================= */
 
-fn normalize_soft(buf: &mut [f32], target_peak: f32) {
-    let mut peak = 0.0f32;
-    for &x in buf.iter() { peak = peak.max(x.abs()); }
-    if peak > target_peak && peak > 1e-9 {
-        let k = target_peak / peak;
-        for x in buf.iter_mut() { *x *= k; }
+/// Peak used by `NormalizeMode::Soft`.
+const SOFT_TARGET_PEAK: f32 = 0.99;
+/// Brick-wall limiter ceiling for `NormalizeMode::Rms` (about -1 dBFS, leaving room for
+/// inter-sample peaks).
+pub const LIMITER_CEILING: f32 = 0.89;
+/// Limiter gain recovery time (seconds).
+const LIMITER_RELEASE_SEC: f32 = 0.05;
+
+/// Master compressor (`StyleParams::compress`): threshold (dBFS), ratio, soft-knee width (dB).
+pub const COMP_THRESHOLD_DB: f32 = -18.0;
+pub const COMP_RATIO: f32 = 4.0;
+pub const COMP_KNEE_DB: f32 = 6.0;
+/// Fixed gain after the compression (dB), so quiet passages come up.
+pub const COMP_MAKEUP_DB: f32 = 6.0;
+/// Envelope follower attack/release times (seconds).
+pub const COMP_ATTACK_SEC: f32 = 0.001;
+pub const COMP_RELEASE_SEC: f32 = 0.15;
+
+/// Feed-forward compressor: a peak envelope follower drives a soft-knee gain curve.
+/// Stateful over consecutive blocks, like `Normalizer`.
+struct Compressor {
+    attack: f32,
+    release: f32,
+    env: f32,
+}
+
+impl Compressor {
+    fn new(sr: u32) -> Self {
+        let coeff = |sec: f32| 1.0 - (-1.0 / (sec * sr as f32)).exp();
+        Compressor { attack: coeff(COMP_ATTACK_SEC), release: coeff(COMP_RELEASE_SEC), env: 0.0 }
+    }
+
+    /// Gain reduction (dB, <= 0) for an envelope level in dBFS.
+    fn gain_db(level_db: f32) -> f32 {
+        let over = level_db - COMP_THRESHOLD_DB;
+        let slope = 1.0 / COMP_RATIO - 1.0;
+        if 2.0 * over <= -COMP_KNEE_DB {
+            0.0
+        } else if 2.0 * over < COMP_KNEE_DB {
+            slope * (over + COMP_KNEE_DB / 2.0).powi(2) / (2.0 * COMP_KNEE_DB)
+        } else {
+            slope * over
+        }
+    }
+
+    fn process(&mut self, buf: &mut [f32]) {
+        for x in buf.iter_mut() {
+            let a = x.abs();
+            let k = if a > self.env { self.attack } else { self.release };
+            self.env += (a - self.env) * k;
+            let level_db = 20.0 * self.env.max(1e-9).log10();
+            *x *= 10f32.powf((Self::gain_db(level_db) + COMP_MAKEUP_DB) / 20.0);
+        }
+    }
+}
+
+/// Reverb comb and all-pass delays (seconds; Freeverb's tunings at 44.1kHz).
+const REVERB_COMB_SEC: [f32; 4] = [0.0253, 0.0269, 0.0290, 0.0307];
+const REVERB_ALLPASS_SEC: [f32; 2] = [0.0126, 0.0100];
+/// Comb feedback (decay time) and damping of the highs in the feedback path.
+const REVERB_FEEDBACK: f32 = 0.84;
+const REVERB_DAMP: f32 = 0.2;
+const REVERB_ALLPASS_FEEDBACK: f32 = 0.5;
+
+/// Delay line with a running write position.
+struct DelayLine {
+    buf: Vec<f32>,
+    pos: usize,
+}
+
+impl DelayLine {
+    fn new(sec: f32, sr: u32) -> Self {
+        DelayLine { buf: vec![0.0; ((sec * sr as f32) as usize).max(1)], pos: 0 }
+    }
+
+    /// Returns the delayed sample and writes `x` in its place.
+    fn swap(&mut self, x: f32) -> f32 {
+        let out = std::mem::replace(&mut self.buf[self.pos], x);
+        self.pos = (self.pos + 1) % self.buf.len();
+        out
+    }
+
+    fn peek(&self) -> f32 { self.buf[self.pos] }
+}
+
+/// Schroeder reverb (`StyleParams::reverb`): damped combs in parallel, then all-passes
+/// in series, added to the dry signal at the `wet` level. Stateful over consecutive
+/// blocks, like `Compressor`.
+struct Reverb {
+    wet: f32,
+    combs: Vec<(DelayLine, f32)>,
+    allpasses: Vec<DelayLine>,
+}
+
+impl Reverb {
+    /// None when `wet` is 0 (off).
+    fn new(sr: u32, wet: f32) -> Option<Self> {
+        let wet = wet.clamp(0.0, 1.0);
+        if wet <= 0.0 { return None; }
+        Some(Reverb {
+            wet,
+            combs: REVERB_COMB_SEC.iter().map(|&d| (DelayLine::new(d, sr), 0.0)).collect(),
+            allpasses: REVERB_ALLPASS_SEC.iter().map(|&d| DelayLine::new(d, sr)).collect(),
+        })
+    }
+
+    fn process(&mut self, buf: &mut [f32]) {
+        let n = self.combs.len() as f32;
+        for x in buf.iter_mut() {
+            let mut y = 0.0;
+            for (line, lp) in self.combs.iter_mut() {
+                let out = line.peek();
+                *lp = out * (1.0 - REVERB_DAMP) + *lp * REVERB_DAMP;
+                line.swap(*x + *lp * REVERB_FEEDBACK);
+                y += out;
+            }
+            y /= n;
+            for line in self.allpasses.iter_mut() {
+                let d = line.peek();
+                line.swap(y + d * REVERB_ALLPASS_FEEDBACK);
+                y = d - y;
+            }
+            *x += self.wet * y;
+        }
+    }
+}
+
+/// Peak and RMS accumulated over one or more blocks.
+#[derive(Default)]
+struct Level {
+    peak: f32,
+    sum_sq: f64,
+    n: usize,
+}
+
+impl Level {
+    fn measure(&mut self, buf: &[f32]) {
+        for &x in buf {
+            self.peak = self.peak.max(x.abs());
+            self.sum_sq += (x as f64) * (x as f64);
+        }
+        self.n += buf.len();
+    }
+
+    fn rms(&self) -> f32 {
+        if self.n == 0 { 0.0 } else { (self.sum_sq / self.n as f64).sqrt() as f32 }
+    }
+}
+
+/// Step 7 as a stateful pass over consecutive blocks: a fixed gain from the measured
+/// level, then (RMS mode) a limiter with instant attack and exponential release.
+struct Normalizer {
+    gain: f32,
+    limit: Option<f32>,
+    release: f32,
+    env: f32,
+}
+
+impl Normalizer {
+    fn new(mode: NormalizeMode, level: &Level, sr: u32) -> Self {
+        let (gain, limit) = match mode {
+            NormalizeMode::Soft if level.peak > SOFT_TARGET_PEAK => (SOFT_TARGET_PEAK / level.peak, None),
+            NormalizeMode::Soft | NormalizeMode::Off => (1.0, None),
+            NormalizeMode::Peak(t) if level.peak > 1e-9 => (t.clamp(0.0, 1.0) / level.peak, None),
+            NormalizeMode::Rms(t) if level.rms() > 1e-9 => (t.clamp(0.0, 1.0) / level.rms(), Some(LIMITER_CEILING)),
+            NormalizeMode::Peak(_) | NormalizeMode::Rms(_) => (1.0, None), // silence
+        };
+        let release = 1.0 - (-1.0 / (LIMITER_RELEASE_SEC * sr as f32)).exp();
+        Normalizer { gain, limit, release, env: 1.0 }
+    }
+
+    fn process(&mut self, buf: &mut [f32]) {
+        if self.gain != 1.0 {
+            for x in buf.iter_mut() { *x *= self.gain; }
+        }
+        let Some(ceiling) = self.limit else { return };
+        for x in buf.iter_mut() {
+            let needed = if x.abs() > ceiling { ceiling / x.abs() } else { 1.0 };
+            self.env = if needed < self.env { needed } else { self.env + (needed - self.env) * self.release };
+            *x = (*x * self.env).clamp(-ceiling, ceiling);
+        }
     }
 }
 
-fn write_wav_i16(buf: &[f32], sr: u32) -> Result<Vec<u8>> {
+/// Raised-cosine fade-in/out of a `total`-sample piece (its first and last sample reach 0),
+/// applied to the window starting at sample `off`.
+fn apply_fades_at(buf: &mut [f32], off: usize, total: usize, sr: u32, fade_in_sec: f32, fade_out_sec: f32) {
+    if total == 0 { return; }
+    let fin = ((fade_in_sec.max(0.0) * sr as f32) as usize).min(total);
+    let fout = ((fade_out_sec.max(0.0) * sr as f32) as usize).min(total);
+    let ramp = |i: usize, len: usize| 0.5 - 0.5 * (PI * i as f32 / len.max(1) as f32).cos();
+    for (j, x) in buf.iter_mut().enumerate() {
+        let i = off + j;
+        if i < fin {
+            *x *= ramp(i, fin);
+        }
+        if i >= total - fout {
+            *x *= ramp(total - 1 - i, fout);
+        }
+    }
+}
+
+fn f32_to_i16(s: f32) -> i16 {
+    (s * i16::MAX as f32).clamp(i16::MIN as f32, i16::MAX as f32) as i16
+}
+
+/// Encode to WAV 16-bit PCM in-memory; `buf` is interleaved when `channels > 1`.
+pub fn write_wav_i16(buf: &[f32], sr: u32, channels: u16) -> Result<Vec<u8>> {
     let spec = WavSpec {
-        channels: 1,
+        channels,
         sample_rate: sr,
         bits_per_sample: 16,
         sample_format: SampleFormat::Int,
@@ -431,14 +1763,35 @@ fn write_wav_i16(buf: &[f32], sr: u32) -> Result<Vec<u8>> {
     {
         let mut writer = WavWriter::new(&mut cursor, spec)?;
         for &s in buf {
-            let v = (s * i16::MAX as f32).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
-            writer.write_sample(v)?;
+            writer.write_sample(f32_to_i16(s))?;
         }
         writer.finalize()?;
     }
     Ok(cursor.into_inner())
 }
 
+/// 44-byte PCM WAV header (same layout hound writes) for `frames` 16-bit frames.
+fn write_wav_header(w: &mut dyn Write, sr: u32, channels: u16, frames: usize) -> Result<()> {
+    let block_align = channels as u32 * 2;
+    let data_len = u32::try_from(frames as u64 * block_align as u64)
+        .map_err(|_| anyhow!("WAV data too long for a RIFF header"))?;
+    let mut h = Vec::with_capacity(44);
+    h.extend_from_slice(b"RIFF");
+    h.extend_from_slice(&(36 + data_len).to_le_bytes());
+    h.extend_from_slice(b"WAVEfmt ");
+    h.extend_from_slice(&16u32.to_le_bytes());
+    h.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    h.extend_from_slice(&channels.to_le_bytes());
+    h.extend_from_slice(&sr.to_le_bytes());
+    h.extend_from_slice(&(sr * block_align).to_le_bytes());
+    h.extend_from_slice(&(block_align as u16).to_le_bytes());
+    h.extend_from_slice(&16u16.to_le_bytes());
+    h.extend_from_slice(b"data");
+    h.extend_from_slice(&data_len.to_le_bytes());
+    w.write_all(&h)?;
+    Ok(())
+}
+
 /* =========================
    Tiny PRNG (deterministic but simple)
    ========================= */
@@ -450,3 +1803,448 @@ fn rand_hash(mut x: u64) -> f32 {
     x ^= x >> 27;
     ((x.wrapping_mul(0x2545F4914F6CDD1D) >> 33) as f32) / (u32::MAX as f32)
 }
+
+/// Independent noise streams of `NoiseRng` (one per drum voice).
+const NOISE_STREAM_SNARE: u64 = 1;
+const NOISE_STREAM_HAT: u64 = 2;
+
+/// Drum noise of one color; `at` gives a generator starting at a hit.
+#[derive(Clone, Copy)]
+struct NoiseSource {
+    rng: NoiseRng,
+    color: NoiseColor,
+}
+
+impl NoiseSource {
+    /// Generator for a hit starting at sample `i` of stream `stream`. Colored noise
+    /// filters from there, so the hit sounds the same however the output is blocked.
+    fn at(&self, stream: u64, i: usize) -> ColoredNoise {
+        ColoredNoise { rng: self.rng.at(stream, i), color: self.color, state: [0.0; 7] }
+    }
+}
+
+/// Pink: Paul Kellet's 1/f filter (tuned for 44.1 kHz, within ~0.5 dB over the audio band);
+/// (pole, input gain) per one-pole section, plus a direct and a one-sample-delayed tap.
+const PINK_POLES: [(f32, f32); 6] =
+    [(0.99886, 0.0555179), (0.99332, 0.0750759), (0.969, 0.153852), (0.8665, 0.3104856), (0.55, 0.5329522), (-0.7616, -0.016898)];
+const PINK_DIRECT: f32 = 0.5362;
+const PINK_DELAYED: f32 = 0.115926;
+/// Brown: leaky integrator y = (y + BROWN_STEP * x) / (1 + BROWN_STEP).
+const BROWN_STEP: f32 = 0.02;
+/// Output gains matching the RMS of uniform white noise (about 0.58).
+const PINK_GAIN: f32 = 0.34;
+const BROWN_GAIN: f32 = 10.2;
+
+/// White noise from `NoiseRng`, optionally filtered to pink or brown.
+struct ColoredNoise {
+    rng: NoiseRng,
+    color: NoiseColor,
+    /// Pink: the six pole sections and the delayed tap; brown: the integrator in [0].
+    state: [f32; 7],
+}
+
+impl ColoredNoise {
+    /// Next sample, about as loud (RMS) as uniform white noise in [-1, 1).
+    fn next_bipolar(&mut self) -> f32 {
+        let x = self.rng.next_bipolar();
+        match self.color {
+            NoiseColor::White => x,
+            NoiseColor::Pink => {
+                let mut sum = x * PINK_DIRECT + self.state[6];
+                for (b, (pole, gain)) in self.state.iter_mut().zip(PINK_POLES) {
+                    *b = pole * *b + x * gain;
+                    sum += *b;
+                }
+                self.state[6] = x * PINK_DELAYED;
+                sum * PINK_GAIN
+            }
+            NoiseColor::Brown => {
+                self.state[0] = (self.state[0] + BROWN_STEP * x) / (1.0 + BROWN_STEP);
+                self.state[0] * BROWN_GAIN
+            }
+        }
+    }
+}
+
+/// SplitMix64 white noise for the drums. The state advances by a fixed step per sample,
+/// so `at` jumps straight to any sample and block rendering yields the same stream.
+#[derive(Clone, Copy)]
+struct NoiseRng {
+    key: u64,
+}
+
+impl NoiseRng {
+    const GAMMA: u64 = 0x9E3779B97F4A7C15;
+
+    fn new(seed: u64) -> Self {
+        NoiseRng { key: seed_mix(seed) }
+    }
+
+    /// Generator positioned at sample `i` of stream `stream`.
+    fn at(&self, stream: u64, i: usize) -> NoiseRng {
+        let stream_key = splitmix64(self.key ^ stream.wrapping_mul(0xBF58476D1CE4E5B9));
+        NoiseRng { key: stream_key.wrapping_add((i as u64).wrapping_mul(Self::GAMMA)) }
+    }
+
+    /// Uniform sample in [-1, 1).
+    fn next_bipolar(&mut self) -> f32 {
+        self.key = self.key.wrapping_add(Self::GAMMA);
+        let x = splitmix64(self.key);
+        (x >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+}
+
+/// SplitMix64 output function.
+fn splitmix64(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+/// Spreads a seed over all 64 bits for XOR-ing into `rand_hash` inputs (0 stays 0).
+fn seed_mix(seed: u64) -> u64 {
+    seed.wrapping_mul(0xD6E8FEB86659FD93).rotate_left(29)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tremolo_cycles_once_per_eighth_note() {
+        // 120 BPM: eighths at 4 Hz, one LFO cycle every 2000 samples at 8 kHz
+        let (sr, depth) = (8000, 0.5);
+        let mut buf = vec![1.0f32; 8000];
+        apply_tremolo(&mut buf, sr, 120.0, depth, &mut 0.0);
+        for k in 0..4 {
+            assert!((buf[k * 2000] - 1.0).abs() < 1e-3, "full gain at the start of cycle {k}");
+            assert!((buf[k * 2000 + 1000] - (1.0 - depth)).abs() < 1e-3, "lowest gain mid-cycle {k}");
+        }
+        let troughs = (1..buf.len() - 1).filter(|&i| buf[i] < buf[i - 1] && buf[i] <= buf[i + 1]).count();
+        assert_eq!(troughs, 4);
+    }
+
+    #[test]
+    fn tremolo_phase_carries_over_between_blocks() {
+        let mut whole = vec![1.0f32; 3000];
+        apply_tremolo(&mut whole, 8000, 100.0, 0.3, &mut 0.0);
+        let mut split = vec![1.0f32; 3000];
+        let mut phase = 0.0;
+        let (a, b) = split.split_at_mut(1234);
+        apply_tremolo(a, 8000, 100.0, 0.3, &mut phase);
+        apply_tremolo(b, 8000, 100.0, 0.3, &mut phase);
+        assert_eq!(whole, split);
+    }
+
+    fn melody(notes: &[(u8, f32, f32)]) -> MonophonicMidi {
+        let mut m = MonophonicMidi::new(120);
+        for &(pitch, start, end) in notes {
+            m.push(pitch, start, end, 100);
+        }
+        m
+    }
+
+    #[test]
+    fn fades_ramp_from_and_to_silence() {
+        let sr = 1000;
+        let mut buf = vec![1.0f32; 2000];
+        apply_fades_at(&mut buf, 0, 2000, sr, 0.1, 0.5);
+        assert_eq!(buf[0], 0.0);
+        assert_eq!(buf[1999], 0.0);
+        assert!(buf[..100].windows(2).all(|w| w[0] <= w[1]), "fade-in rises");
+        assert!(buf[1500..].windows(2).all(|w| w[0] >= w[1]), "fade-out falls");
+        assert!(buf[100..1500].iter().all(|&x| x == 1.0), "no gain change in between");
+    }
+
+    #[test]
+    fn rendered_piece_starts_and_ends_near_zero() {
+        let m = melody(&[(60, 0.0, 1.0), (67, 1.0, 2.0)]);
+        let style = StyleParams { layering: vec![Osc::Square], trim_tail: false, ..StyleParams::default() };
+        let out = render_mono(&m, 8000, &style, None).unwrap();
+        assert!(out[0].abs() < 1e-3 && out[out.len() - 1].abs() < 1e-3);
+        let peak = out.iter().fold(0.0f32, |p, x| p.max(x.abs()));
+        assert!(out[..8].iter().all(|x| x.abs() < 0.05 * peak), "no click at the start");
+    }
+
+    #[test]
+    fn streaming_and_in_memory_renders_are_identical() {
+        // several blocks, with every stateful stage across the block edges
+        let notes: Vec<(u8, f32, f32)> = (0..12).map(|i| (60 + (i % 5) as u8 * 2, i as f32 * 0.5, i as f32 * 0.5 + 0.6)).collect();
+        let style = StyleParams {
+            percussion: true,
+            reverb: 0.3,
+            compress: true,
+            tremolo_depth: 0.2,
+            normalize_mode: NormalizeMode::Rms(0.4),
+            ..StyleParams::default()
+        };
+        let m = melody(&notes);
+        let bytes = render_wav_bytes_styled(&m, 8000, &style).unwrap();
+        let mut streamed = Vec::new();
+        render_wav_stream(&m, 8000, &style, &mut streamed).unwrap();
+        assert!(bytes.len() > 44 + 2 * 8000 * 3);
+        assert!(bytes == streamed);
+    }
+
+    fn noise(color: NoiseColor, seed: u64, n: usize) -> Vec<f32> {
+        let mut g = NoiseSource { rng: NoiseRng::new(seed), color }.at(NOISE_STREAM_HAT, 0);
+        (0..n).map(|_| g.next_bipolar()).collect()
+    }
+
+    #[test]
+    fn drum_noise_is_seeded_and_seekable() {
+        let a = noise(NoiseColor::White, 7, 4096);
+        assert_eq!(a, noise(NoiseColor::White, 7, 4096));
+        assert_ne!(a, noise(NoiseColor::White, 8, 4096));
+        assert!(a.iter().all(|x| (-1.0..1.0).contains(x)));
+        // jumping to a sample continues the same stream
+        let mut g = NoiseRng::new(7).at(NOISE_STREAM_HAT, 1000);
+        assert!((1000..1100).all(|i| g.next_bipolar() == a[i]));
+        // the snare and hat streams differ
+        let mut snare = NoiseRng::new(7).at(NOISE_STREAM_SNARE, 0);
+        assert!(a[..16].iter().any(|&x| x != snare.next_bipolar()));
+    }
+
+    /// Mean power of `x` at `freq` (cycles per sample) over `len`-sample segments, by a plain DFT.
+    fn band_power(x: &[f32], len: usize, freq: f32) -> f32 {
+        let segs = x.len() / len;
+        let mut total = 0.0f64;
+        for seg in x.chunks_exact(len) {
+            let (mut re, mut im) = (0.0f64, 0.0f64);
+            for (i, &s) in seg.iter().enumerate() {
+                let w = 2.0 * std::f64::consts::PI * freq as f64 * i as f64;
+                re += s as f64 * w.cos();
+                im -= s as f64 * w.sin();
+            }
+            total += re * re + im * im;
+        }
+        (total / segs as f64) as f32
+    }
+
+    #[test]
+    fn white_drum_noise_has_a_flat_spectrum() {
+        let x = noise(NoiseColor::White, 3, 1 << 16);
+        let mean = x.iter().sum::<f32>() / x.len() as f32;
+        let var = x.iter().map(|s| (s - mean).powi(2)).sum::<f32>() / x.len() as f32;
+        let lag1 = x.windows(2).map(|w| (w[0] - mean) * (w[1] - mean)).sum::<f32>() / (x.len() - 1) as f32;
+        assert!(mean.abs() < 0.01 && (var - 1.0 / 3.0).abs() < 0.01, "mean {mean}, var {var}");
+        assert!((lag1 / var).abs() < 0.02, "lag-1 correlation {}", lag1 / var);
+        // within 1.5 dB of the average from low to near-Nyquist
+        let powers: Vec<f32> = [0.02, 0.1, 0.25, 0.4, 0.48].iter().map(|&f| band_power(&x, 256, f)).collect();
+        let avg = powers.iter().sum::<f32>() / powers.len() as f32;
+        for p in powers {
+            assert!((10.0 * (p / avg).log10()).abs() < 1.5, "{p} vs {avg}");
+        }
+    }
+
+    #[test]
+    fn hat_high_pass_removes_the_low_band() {
+        let (sr, len) = (44_100, 44_100);
+        let source = NoiseSource { rng: NoiseRng::new(5), color: NoiseColor::White };
+        let mut hat = vec![0.0f32; len];
+        render_hat(&mut Span::new(&mut hat, 0, len), sr, &source, 0.0, 1.0, 1.0);
+        // the same burst without the filter
+        let mut rng = source.at(NOISE_STREAM_HAT, 0);
+        let raw: Vec<f32> = (0..len).map(|i| rng.next_bipolar() * (1.0 - i as f32 / len as f32).powf(4.0)).collect();
+
+        let band = |x: &[f32], hz: &[f32]| hz.iter().map(|&f| band_power(x, 1024, f / sr as f32)).sum::<f32>();
+        let low = [100.0, 300.0, 600.0, 1000.0];
+        let high = [18_000.0, 20_000.0];
+        let low_db = 10.0 * (band(&hat, &low) / band(&raw, &low)).log10();
+        let high_db = 10.0 * (band(&hat, &high) / band(&raw, &high)).log10();
+        assert!(low_db < -15.0, "low band only {low_db} dB down");
+        // the RC-discretized pole loses ~3.5 dB at Nyquist with this cutoff
+        assert!(high_db > -6.0, "high band {high_db} dB down");
+    }
+
+    #[test]
+    fn repeated_note_continues_the_waveform() {
+        // a flat envelope leaves only the oscillator; 300.7 Hz puts the joint mid-cycle
+        let (sr, f0, flat) = (8000, 300.7, Some(Adsr { attack: 0.0, decay: 0.0, sustain: 1.0, release: 0.0 }));
+        let mut two = vec![0.0f32; 8000];
+        let mut span = Span::new(&mut two, 0, 8000);
+        render_note_glide(&mut span, sr, f0, f0, 0.0, 0.0, 0.5, 1.0, Osc::Sine, flat);
+        render_note_glide(&mut span, sr, f0, f0, 0.0, 0.5, 1.0, 1.0, Osc::Sine, flat);
+        let mut one = vec![0.0f32; 8000];
+        render_note_glide(&mut Span::new(&mut one, 0, 8000), sr, f0, f0, 0.0, 0.0, 1.0, 1.0, Osc::Sine, flat);
+
+        let max_step = (2.0 * PI * f0 / sr as f32) * 1.01;
+        assert!((3990..4010).all(|i| (two[i + 1] - two[i]).abs() <= max_step), "jump at the joint");
+        assert!(two.iter().zip(&one).all(|(a, b)| (a - b).abs() < 1e-4), "same as one held note");
+    }
+
+    fn eighths(n: usize) -> Vec<NoteEv> {
+        (0..n).map(|i| NoteEv { pitch: 60, t_on: i as f32 * 0.5, t_off: (i + 1) as f32 * 0.5, velocity: 100, glide_from: None }).collect()
+    }
+
+    #[test]
+    fn humanize_varies_lengths_but_keeps_them_positive() {
+        let mut evs = eighths(64);
+        apply_swing_and_humanize(&mut evs, 0.0, 0.4, 60.0, 11);
+        let lens: Vec<f32> = evs.iter().map(|e| e.t_off - e.t_on).collect();
+        assert!(lens.iter().all(|&l| l > 0.0));
+        // at most ±10% at the humanize cap, and not all the same
+        assert!(lens.iter().all(|&l| (l - 0.5).abs() <= 0.5 * 0.4 * HUMANIZE_LENGTH_JITTER + 1e-4), "{lens:?}");
+        let (lo, hi) = lens.iter().fold((f32::MAX, 0.0f32), |(lo, hi), &l| (lo.min(l), hi.max(l)));
+        assert!(hi - lo > 0.02, "lengths {lo}..{hi}");
+
+        let mut again = eighths(64);
+        apply_swing_and_humanize(&mut again, 0.0, 0.4, 60.0, 11);
+        assert!(evs.iter().zip(&again).all(|(a, b)| a.t_on == b.t_on && a.t_off == b.t_off && a.velocity == b.velocity));
+        let mut other = eighths(64);
+        apply_swing_and_humanize(&mut other, 0.0, 0.4, 60.0, 12);
+        assert!(evs.iter().zip(&other).any(|(a, b)| a.t_off - a.t_on != b.t_off - b.t_on));
+    }
+
+    #[test]
+    fn humanize_never_inverts_very_short_notes() {
+        let mut evs: Vec<NoteEv> = (0..64).map(|i| NoteEv { pitch: 60, t_on: i as f32 * 1e-4, t_off: (i + 1) as f32 * 1e-4, velocity: 100, glide_from: None }).collect();
+        apply_swing_and_humanize(&mut evs, 0.0, 1.0, 120.0, 3);
+        assert!(evs.iter().all(|e| e.t_off > e.t_on && e.t_on >= 0.0));
+    }
+
+    #[test]
+    fn trimmed_output_keeps_the_reverb_tail_but_not_the_padding() {
+        let sr = 8000;
+        let m = melody(&[(60, 0.0, 1.0), (64, 1.0, 2.0)]);
+        let style = StyleParams { reverb: 0.4, tail_sec: Some(8.0), humanize: 0.0, ..StyleParams::default() };
+        let trimmed = render_mono(&m, sr, &style, None).unwrap();
+        let full = render_mono(&m, sr, &StyleParams { trim_tail: false, ..style.clone() }, None).unwrap();
+        let peak = |x: &[f32]| x.iter().fold(0.0f32, |p, s| p.max(s.abs()));
+        let sec = |s: f32| (s * sr as f32) as usize;
+        assert_eq!(full.len(), sec(10.0));
+        assert!(peak(&full[sec(9.0)..]) < 1e-6, "the untrimmed end is padding");
+        assert!(trimmed.len() < sec(5.0), "trimmed to {} samples", trimmed.len());
+        // before the fade-out: the reverb still decaying, quiet but not silent
+        let n = trimmed.len();
+        let tail = peak(&trimmed[n - sec(REVERB_TAIL_SEC + style.fade_out_sec)..n - sec(style.fade_out_sec)]);
+        assert!(tail > 1e-5 && tail < 0.01 * peak(&trimmed), "tail peak {tail}");
+    }
+
+    /// Times of the hits `drum_schedule` reports at 60 BPM (one beat per second), by kind.
+    fn scheduled(pattern: &DrumPattern, time_sig: (u8, u8), end_secs: f32) -> (Vec<f32>, Vec<f32>) {
+        let (mut kicks, mut snares) = (Vec::new(), Vec::new());
+        drum_schedule(60.0, pattern, time_sig_pulse_steps(time_sig), 0.0, 0, &DrumMix::default(), end_secs, 0, f32::INFINITY, |h| match h {
+            DrumHit::Kick { t, .. } => kicks.push(t),
+            DrumHit::Snare { t, .. } => snares.push(t),
+            DrumHit::Tom { .. } | DrumHit::Hat { .. } => {}
+        });
+        (kicks, snares)
+    }
+
+    #[test]
+    fn three_four_puts_the_snare_on_beat_two_only() {
+        let pattern = DrumPattern::for_time_sig((3, 4)).unwrap();
+        assert_eq!(pattern.steps_per_bar(), 12);
+        let (kicks, snares) = scheduled(&pattern, (3, 4), 24.0);
+        let beat_of = |t: f32| ((t % 3.0) + 1e-3).floor() as u32 + 1;
+        let bar_of = |t: f32| (t / 3.0 + 1e-3).floor() as u32;
+        // bar 3 and 7 end a four-bar phrase with a fill over beat 3
+        let plain = |t: &&f32| bar_of(**t) % 4 != 3;
+        assert!(kicks.iter().all(|&t| beat_of(t) == 1), "{kicks:?}");
+        let plain_snares: Vec<f32> = snares.iter().filter(plain).copied().collect();
+        assert_eq!(plain_snares.len(), 6, "{snares:?}");
+        assert!(plain_snares.iter().all(|&t| beat_of(t) == 2 && (t % 3.0 - 1.005).abs() < 1e-3), "{plain_snares:?}");
+        assert!(snares.iter().all(|&t| beat_of(t) != 1), "{snares:?}");
+    }
+
+    #[test]
+    fn drums_stop_within_a_bar_of_the_last_note() {
+        // eighths at 60 BPM (estimated from the note lengths): 4 s bars, melody ends at 5.5 s
+        let sr = 8000;
+        let notes: Vec<(u8, f32, f32)> = (0..11).map(|i| (60, i as f32 * 0.5, (i + 1) as f32 * 0.5)).collect();
+        let m = melody(&notes);
+        let style = StyleParams { percussion: true, humanize: 0.0, tail_sec: Some(8.0), ..StyleParams::default() };
+        let p = prepare(&m, sr, &style).unwrap();
+        assert_eq!(p.bpm, 60.0);
+        assert_eq!(p.drum_end, 8.0, "the bar line after the last note-off");
+
+        let mut drums = vec![0.0f32; p.total_samples];
+        let total = drums.len();
+        let pattern = p.drum_pattern.clone().unwrap();
+        let pulse = time_sig_pulse_steps(style.time_sig);
+        render_drums(&mut Span::new(&mut drums, 0, total), sr, &p, &pattern, pulse, 0, &style.drum_mix, 0, NoiseColor::White, &mut 0);
+        let energy = |from: f32, to: f32| drums[(from * sr as f32) as usize..(to * sr as f32) as usize].iter().map(|x| x * x).sum::<f32>();
+        assert!(total > 12 * sr as usize, "the buffer runs on past the drums");
+        assert!(energy(0.0, 5.5) > 0.0 && energy(5.5, 8.0) > 0.0, "drums play up to the bar line");
+        let bar = 4.0;
+        assert_eq!(energy(5.5 + bar + DRUM_HIT_MAX_SEC, total as f32 / sr as f32), 0.0);
+        assert_eq!(energy(p.drum_end + DRUM_HIT_MAX_SEC, total as f32 / sr as f32), 0.0);
+    }
+
+
+    /// Impulse rendering of `notes` (before reverb, compression and normalization):
+    /// (sample, value) of every non-zero sample.
+    fn impulses(notes: &[(u8, f32, f32)], sr: u32, style: StyleParams) -> Vec<(usize, f32)> {
+        let style = StyleParams { layering: vec![Osc::Impulse], humanize: 0.0, ..style };
+        let p = prepare(&melody(notes), sr, &style).unwrap();
+        let mut buf = vec![0.0f32; p.total_samples];
+        let total = buf.len();
+        render_block(&p, sr, &style, &mut Span::new(&mut buf, 0, total), &mut BlockCursor::default());
+        buf.iter().enumerate().filter(|(_, &x)| x != 0.0).map(|(i, &x)| (i, x)).collect()
+    }
+
+    fn eighth_notes(n: usize) -> Vec<(u8, f32, f32)> {
+        (0..n).map(|i| (60, i as f32 * 0.5, (i + 1) as f32 * 0.5)).collect()
+    }
+
+    #[test]
+    fn golden_swing_delays_every_other_eighth() {
+        // 60 BPM from the note lengths: 0.5 s eighths, swing 0.25 delays the offbeats by
+        // 0.25 * 0.5 * 0.5 s = 500 samples at 8 kHz
+        let hits = impulses(&eighth_notes(4), 8000, StyleParams { swing: 0.25, ..StyleParams::default() });
+        assert_eq!(hits, [(0, 1.0), (4500, 1.0), (8000, 1.0), (12_500, 1.0)]);
+        let straight = impulses(&eighth_notes(4), 8000, StyleParams::default());
+        assert_eq!(straight, [(0, 1.0), (4000, 1.0), (8000, 1.0), (12_000, 1.0)]);
+    }
+
+    #[test]
+    fn golden_polyphony_stacks_voices_on_the_same_onset() {
+        let notes = [(60, 0.25, 0.75), (62, 1.0, 1.5)];
+        let triads = impulses(&notes, 8000, StyleParams { polyphony: 3, ..StyleParams::default() });
+        assert_eq!(triads, [(2000, 3.0), (8000, 3.0)]);
+        let dyads = impulses(&notes, 8000, StyleParams { polyphony: 2, ..StyleParams::default() });
+        assert_eq!(dyads, [(2000, 2.0), (8000, 2.0)]);
+        // overlapping notes each keep their own onset
+        let overlap = impulses(&[(60, 0.0, 1.0), (64, 0.5, 1.0)], 8000, StyleParams { polyphony: 3, ..StyleParams::default() });
+        assert_eq!(overlap, [(0, 3.0), (4000, 3.0)]);
+    }
+
+    #[test]
+    fn golden_drum_grid() {
+        // 60 BPM, two 4 s bars of the standard groove; the drums fade in from silence on
+        // the first downbeat, so the hits at 0 s are dropped
+        let style = StyleParams { layering: vec![Osc::Impulse], percussion: true, humanize: 0.0, ..StyleParams::default() };
+        let hits = drum_hits(&melody(&eighth_notes(16)), &style).unwrap();
+        let at = |pitch: u8| hits.iter().filter(|n| n.pitch == pitch).map(|n| (n.start * 8000.0).round() as usize).collect::<Vec<_>>();
+        assert_eq!(at(GM_KICK), [16_000, 32_000, 48_000]);
+        assert_eq!(at(GM_SNARE), [8040, 24_040, 40_040, 56_040]);
+        assert_eq!(at(GM_CLOSED_HAT), (1..16).map(|k| k * 4000).collect::<Vec<_>>());
+        assert_eq!(hits.len(), 22);
+    }
+
+    /// Slope of `color` noise in dB per octave, from its power at 8..256 cycles per 1024 samples
+    /// (above the brown integrator's leak, about 3 cycles).
+    fn noise_slope_db_per_octave(color: NoiseColor) -> f32 {
+        let x = noise(color, 9, 1 << 17);
+        let freqs = [8.0, 16.0, 32.0, 64.0, 128.0, 256.0];
+        let db: Vec<f32> = freqs.iter().map(|f| 10.0 * band_power(&x, 1024, f / 1024.0).log10()).collect();
+        // least-squares slope against octave number
+        let n = db.len() as f32;
+        let mean_o = (n - 1.0) / 2.0;
+        let mean_db = db.iter().sum::<f32>() / n;
+        let cov: f32 = db.iter().enumerate().map(|(o, d)| (o as f32 - mean_o) * (d - mean_db)).sum();
+        let var: f32 = (0..db.len()).map(|o| (o as f32 - mean_o).powi(2)).sum();
+        cov / var
+    }
+
+    #[test]
+    fn colored_noise_falls_off_per_octave() {
+        let white = noise_slope_db_per_octave(NoiseColor::White);
+        let pink = noise_slope_db_per_octave(NoiseColor::Pink);
+        let brown = noise_slope_db_per_octave(NoiseColor::Brown);
+        assert!(white.abs() < 1.0, "white {white} dB/oct");
+        assert!((pink + 3.0).abs() < 1.0, "pink {pink} dB/oct");
+        assert!((brown + 6.0).abs() < 1.0, "brown {brown} dB/oct");
+    }
+}
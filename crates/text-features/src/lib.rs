@@ -13,14 +13,59 @@ pub struct TextFeatures {
     pub reading_time_minutes: f32, // words / 180
     pub punctuation_ratio: f32,    // punct chars / total chars
     pub sentiment_score: f32,      // [-1,1] heuristic
+    #[serde(default)]
+    pub sentiment_trajectory: Vec<f32>, // `sentiment_score` of each sentence (see `split_sentences`)
     pub char_entropy_bits: f32,    // 0..~log2|alphabet|
     pub word_entropy_bits: f32,    // normalized by log2(vocab)
+    pub keywords: Vec<(String, f32)>, // top terms by frequency (tf = count / n_words), no stopwords
+    pub stopword_ratio: f32,       // words in `STOPWORDS` / n_words
+    pub caps_ratio: f32,           // ALL-CAPS words (2+ letters) / n_words
+    pub question_ratio: f32,       // sentences ending in '?' / sentences (see `split_sentences`)
+    pub top_words: Vec<(String, usize)>, // most frequent lowercase words (stopwords included) with counts
+    pub vocab_size: usize,         // distinct lowercase words
+    #[serde(default)]
+    pub emoji_ratio: f32,          // emoji tokens (see `tokenize`) / n_words
+}
+
+/// Number of keywords `analyze_text` keeps.
+pub const DEFAULT_KEYWORDS: usize = 8;
+
+/// Number of entries `analyze_text` keeps in `top_words`.
+pub const TOP_WORDS: usize = 16;
+
+/// Small built-in English stopword set (lowercase): counted by `stopword_ratio`,
+/// skipped by keyword extraction.
+pub const STOPWORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "am", "an", "and", "any", "are", "as", "at",
+    "be", "because", "been", "before", "being", "but", "by", "can", "could", "did", "do",
+    "does", "for", "from", "had", "has", "have", "he", "her", "here", "him", "his", "how",
+    "i", "if", "in", "into", "is", "it", "its", "just", "me", "more", "my", "no", "not",
+    "now", "of", "on", "one", "only", "or", "our", "out", "over", "she", "so", "some",
+    "than", "that", "the", "their", "them", "then", "there", "these", "they", "this",
+    "those", "to", "too", "up", "us", "very", "was", "we", "were", "what", "when", "where",
+    "which", "while", "who", "why", "will", "with", "would", "you", "your",
+];
+
+/// Token as used for keywords: lowercase, surrounding punctuation stripped.
+pub fn normalize_token(w: &str) -> String {
+    w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase()
+}
+
+pub fn is_stopword(token: &str) -> bool {
+    STOPWORDS.contains(&token)
 }
 
 pub fn analyze_text(s: &str) -> Result<TextFeatures> {
+    analyze_text_with_keywords(s, DEFAULT_KEYWORDS)
+}
+
+/// `analyze_text`, keeping the top `n_keywords` keywords.
+pub fn analyze_text_with_keywords(s: &str, n_keywords: usize) -> Result<TextFeatures> {
     let n_chars = s.chars().count();
-    let words: Vec<&str> = s.split_whitespace().collect();
+    let words = tokenize(s);
     let n_words = words.len();
+    let n_emoji = words.iter().filter(|w| is_emoji_token(w)).count();
+    let emoji_ratio = if n_words>0 { n_emoji as f32 / n_words as f32 } else { 0.0 };
 
     let mut vocab = HashSet::new();
     for w in &words {
@@ -28,8 +73,9 @@ pub fn analyze_text(s: &str) -> Result<TextFeatures> {
     }
     let ttr = if n_words>0 { (vocab.len() as f32)/(n_words as f32) } else { 0.0 };
 
-    // syllables (rough English heuristic)
+    // syllables (rough English heuristic; see `estimate_syllables` for other scripts)
     fn count_syllables(w: &str) -> usize {
+        if let Some(n) = estimate_syllables(w) { return n; }
         let w = w.to_lowercase();
         let vowels = "aeiouyăîâoe";
         let mut prev_v = false;
@@ -49,18 +95,8 @@ pub fn analyze_text(s: &str) -> Result<TextFeatures> {
     let punct_count = s.chars().filter(|c| punct_set.contains(c)).count();
     let punctuation_ratio = if n_chars>0 { punct_count as f32 / n_chars as f32 } else { 0.0 };
 
-    // very simple sentiment lexicon (extend as needed)
-    fn sentiment_score(s: &str) -> f32 {
-        const POS: &[&str] = &["good","great","hope","love","happy","bright","calm","win","nice","excellent","amazing","best"];
-        const NEG: &[&str] = &["bad","sad","hate","angry","dark","fail","worst","terrible","awful","ugly","mad"];
-        let mut sc = 0i32;
-        for w in s.split_whitespace() {
-            let w = w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
-            if POS.contains(&w.as_str()) { sc += 1; }
-            if NEG.contains(&w.as_str()) { sc -= 1; }
-        }
-        (sc as f32).clamp(-5.0, 5.0)/5.0
-    }
+    let sentences = split_sentences(s);
+    let sentiment_trajectory = sentences.iter().map(|x| sentiment_score(x)).collect();
     let sentiment_score = sentiment_score(s);
 
     // char entropy
@@ -89,9 +125,245 @@ pub fn analyze_text(s: &str) -> Result<TextFeatures> {
         (h / norm) as f32
     } else { 0.0 };
 
+    // most frequent words, ties broken alphabetically
+    let mut top_words: Vec<(String, usize)> = word_hist.into_iter().collect();
+    top_words.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    top_words.truncate(TOP_WORDS);
+
+    let keywords = keywords(&words, n_keywords);
+
+    // stopwords and SHOUTING
+    let mut n_stop = 0usize;
+    let mut n_caps = 0usize;
+    for w in &words {
+        if is_stopword(&normalize_token(w)) { n_stop += 1; }
+        let letters: Vec<char> = w.chars().filter(|c| c.is_alphabetic()).collect();
+        if letters.len() >= 2 && letters.iter().all(|c| c.is_uppercase()) { n_caps += 1; }
+    }
+    let stopword_ratio = if n_words>0 { n_stop as f32 / n_words as f32 } else { 0.0 };
+    let caps_ratio = if n_words>0 { n_caps as f32 / n_words as f32 } else { 0.0 };
+
+    let n_questions = sentences.iter().filter(|x| is_question(x)).count();
+    let question_ratio = if sentences.is_empty() { 0.0 } else { n_questions as f32 / sentences.len() as f32 };
+
     Ok(TextFeatures{
         n_chars, n_words, ttr, syllables_total, syllables_per_word,
-        reading_time_minutes, punctuation_ratio, sentiment_score,
-        char_entropy_bits, word_entropy_bits
+        reading_time_minutes, punctuation_ratio, sentiment_score, sentiment_trajectory,
+        char_entropy_bits, word_entropy_bits, keywords, stopword_ratio, caps_ratio,
+        question_ratio, top_words, vocab_size: vocab.len(), emoji_ratio
     })
 }
+
+/// Whitespace-separated words; a word containing emoji is split into its emoji
+/// (one token per emoji, modifiers and ZWJ sequences included, flags as pairs) and
+/// the text around them (pieces without letters or digits are dropped).
+pub fn tokenize(s: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    for w in s.split_whitespace() {
+        if !w.chars().any(is_emoji) {
+            out.push(w);
+            continue;
+        }
+        let mut text_start: Option<usize> = None;
+        let mut chars = w.char_indices().peekable();
+        while let Some((i, ch)) = chars.next() {
+            if !is_emoji(ch) {
+                if !is_emoji_modifier(ch) { text_start.get_or_insert(i); }
+                continue;
+            }
+            push_text(&mut out, w, text_start.take(), i);
+            let mut end = i + ch.len_utf8();
+            let mut flag_half = is_regional_indicator(ch);
+            while let Some(&(j, c2)) = chars.peek() {
+                let joined = is_emoji_modifier(c2)
+                    || (flag_half && is_regional_indicator(c2))
+                    || (w[..j].ends_with(ZWJ) && is_emoji(c2));
+                if !joined && c2 != ZWJ { break; }
+                flag_half = false;
+                end = j + c2.len_utf8();
+                chars.next();
+            }
+            out.push(&w[i..end]);
+        }
+        push_text(&mut out, w, text_start, w.len());
+    }
+    out
+}
+
+/// Text between emoji, kept when it has letters or digits.
+fn push_text<'a>(out: &mut Vec<&'a str>, w: &'a str, start: Option<usize>, end: usize) {
+    if let Some(st) = start {
+        let piece = &w[st..end];
+        if piece.chars().any(|c| c.is_alphanumeric()) { out.push(piece); }
+    }
+}
+
+/// Zero-width joiner (glues emoji into one, e.g. family emoji).
+const ZWJ: char = '\u{200D}';
+
+/// Pictographic emoji and symbol blocks (no full Unicode emoji property: digits and
+/// ASCII symbols, which can also start keycap sequences, don't count).
+pub fn is_emoji(c: char) -> bool {
+    matches!(c as u32,
+        0x1F000..=0x1F2FF   // mahjong, cards, enclosed alphanumerics, regional indicators
+        | 0x1F300..=0x1F3FA // misc symbols & pictographs (skin tones excluded)
+        | 0x1F400..=0x1FAFF // pictographs, emoticons, transport, supplemental
+        | 0x2600..=0x27BF   // misc symbols, dingbats
+        | 0x2B00..=0x2BFF   // arrows & stars (e.g. the star emoji)
+    )
+}
+
+/// Characters that only modify the emoji before them: skin tones, variation selectors,
+/// the keycap mark and tag characters.
+fn is_emoji_modifier(c: char) -> bool {
+    matches!(c as u32, 0x1F3FB..=0x1F3FF | 0xFE00..=0xFE0F | 0x20E3 | 0xE0020..=0xE007F)
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c as u32, 0x1F1E6..=0x1F1FF)
+}
+
+/// Token made of emoji only (as produced by `tokenize`).
+pub fn is_emoji_token(token: &str) -> bool {
+    token.chars().next().is_some_and(is_emoji)
+}
+
+/// Average letters per syllable assumed for alphabetic scripts without Latin vowels.
+pub const LETTERS_PER_SYLLABLE: f32 = 2.5;
+
+/// Syllable count for tokens the Latin vowel heuristic can't read, None for Latin-script
+/// words: an emoji counts as one syllable, CJK ideographs / kana / hangul as one per
+/// character, other letters (Cyrillic, Greek, ...) as `LETTERS_PER_SYLLABLE` per syllable.
+pub fn estimate_syllables(token: &str) -> Option<usize> {
+    if is_emoji_token(token) { return Some(1); }
+    let mut latin = false;
+    let mut syllabic = 0usize;
+    let mut letters = 0usize;
+    for c in token.chars().filter(|c| c.is_alphabetic()) {
+        match c as u32 {
+            0..=0x024F => latin = true,
+            0x3040..=0x30FF | 0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xAC00..=0xD7AF => syllabic += 1,
+            _ => letters += 1,
+        }
+    }
+    if latin || syllabic + letters == 0 { return None; }
+    Some((syllabic + (letters as f32 / LETTERS_PER_SYLLABLE).round() as usize).max(1))
+}
+
+/// Top `n` non-stopword tokens (2+ chars) by count, ties broken alphabetically.
+fn keywords(words: &[&str], n: usize) -> Vec<(String, f32)> {
+    let mut counts = std::collections::BTreeMap::<String, usize>::new();
+    for w in words {
+        let t = normalize_token(w);
+        if t.chars().count() < 2 || is_stopword(&t) { continue; }
+        *counts.entry(t).or_default() += 1;
+    }
+    let mut ranked: Vec<(String, usize)> = counts.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let total = words.len().max(1) as f32;
+    ranked.into_iter().take(n).map(|(w, c)| (w, c as f32 / total)).collect()
+}
+
+/// Lexicon sentiment in [-1, 1]: +1/5 per positive word, -1/5 per negative one.
+/// Very simple (extend as needed).
+pub fn sentiment_score(s: &str) -> f32 {
+    const POS: &[&str] = &["good","great","hope","love","happy","bright","calm","win","nice","excellent","amazing","best"];
+    const NEG: &[&str] = &["bad","sad","hate","angry","dark","fail","worst","terrible","awful","ugly","mad"];
+    let mut sc = 0i32;
+    for w in s.split_whitespace() {
+        let w = w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+        if POS.contains(&w.as_str()) { sc += 1; }
+        if NEG.contains(&w.as_str()) { sc -= 1; }
+    }
+    (sc as f32).clamp(-5.0, 5.0)/5.0
+}
+
+/// Split into sentences at `.`, `!`, `?` (runs like "?!" or "..." stay together),
+/// keeping the terminal punctuation. Trailing text without a terminator is a sentence too.
+pub fn split_sentences(s: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start = 0usize;
+    let mut chars = s.char_indices().peekable();
+    while let Some((i, ch)) = chars.next() {
+        if matches!(ch, '.' | '!' | '?') {
+            // swallow the rest of a terminator run
+            let mut end = i + ch.len_utf8();
+            while let Some(&(j, c2)) = chars.peek() {
+                if matches!(c2, '.' | '!' | '?') { end = j + c2.len_utf8(); chars.next(); } else { break; }
+            }
+            let at_boundary = chars.peek().is_none_or(|&(_, c2)| c2.is_whitespace());
+            if at_boundary {
+                let sent = s[start..end].trim();
+                if !sent.is_empty() { out.push(sent); }
+                start = end;
+            }
+        }
+    }
+    let tail = s[start..].trim();
+    if !tail.is_empty() { out.push(tail); }
+    out
+}
+
+/// Sentence (as returned by `split_sentences`) whose terminator run contains a '?'.
+pub fn is_question(sentence: &str) -> bool {
+    sentence.chars().rev().take_while(|c| matches!(c, '.' | '!' | '?')).any(|c| c == '?')
+}
+
+/// Split into paragraphs separated by blank lines.
+pub fn split_paragraphs(s: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut end = 0usize;
+    let mut offset = 0usize;
+    for line in s.split_inclusive('\n') {
+        if line.trim().is_empty() {
+            if let Some(st) = start.take() { out.push(s[st..end].trim()); }
+        } else {
+            if start.is_none() { start = Some(offset); }
+            end = offset + line.len();
+        }
+        offset += line.len();
+    }
+    if let Some(st) = start { out.push(s[st..end].trim()); }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emoji_text_gets_tokens_syllables_and_an_emoji_ratio() {
+        let f = analyze_text("party 🎉🎉 time 👍🏽 🇫🇷 love ❤️").unwrap();
+        assert_eq!(tokenize("party 🎉🎉 time 👍🏽 🇫🇷 love ❤️"), ["party", "🎉", "🎉", "time", "👍🏽", "🇫🇷", "love", "❤️"]);
+        assert_eq!(f.n_words, 8);
+        assert!((f.emoji_ratio - 5.0 / 8.0).abs() < 1e-6, "{}", f.emoji_ratio);
+        assert!(f.syllables_total >= f.n_words);
+        assert!(f.word_entropy_bits > 0.0);
+        // emoji glued to a word split off it
+        assert_eq!(tokenize("hi🙂there"), ["hi", "🙂", "there"]);
+    }
+
+    #[test]
+    fn cyrillic_text_gets_syllables_from_its_letters() {
+        let s = "Сегодня прекрасный солнечный день, и мы идём гулять в парк.";
+        let f = analyze_text(s).unwrap();
+        assert_eq!(f.n_words, 10);
+        // "прекрасный": 10 letters at 2.5 per syllable
+        assert_eq!(estimate_syllables("прекрасный"), Some(4));
+        assert!(f.syllables_per_word > 1.5, "{}", f.syllables_per_word);
+        assert_eq!(f.emoji_ratio, 0.0);
+        assert!(f.vocab_size == 10 && f.char_entropy_bits > 3.0);
+        assert_eq!(estimate_syllables("hello"), None, "Latin words use the vowel heuristic");
+    }
+
+    #[test]
+    fn sentiment_trajectory_follows_the_sentences() {
+        let f = analyze_text("It was a terrible, awful day. Nothing happened. But then it was wonderful, the best and happy!").unwrap();
+        let t = &f.sentiment_trajectory;
+        assert_eq!(t.len(), 3, "{t:?}");
+        assert!(t[0] < 0.0 && t[1] == 0.0 && t[2] > 0.0, "{t:?}");
+        assert!(t.windows(2).all(|w| w[1] >= w[0]), "rises: {t:?}");
+        assert!(analyze_text("").unwrap().sentiment_trajectory.is_empty());
+    }
+}
@@ -2,7 +2,7 @@
 use anyhow::Result;
 use serde::{Serialize, Deserialize};
 use palette::{Srgb, IntoColor, Hsv};
-use image::{DynamicImage, GenericImageView};
+use image::{imageops::FilterType, DynamicImage, GenericImageView, RgbImage};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImageFeatures {
@@ -20,27 +20,142 @@ pub struct ImageFeatures {
     pub entropy_luma_bits: f32, // 0..8
 
     pub edge_density: f32, // [0,1]
+
+    pub color_temperature: f32, // [-1,1] mean(R-B): <0 cool/blue, >0 warm/orange
+
+    pub luma_gradient_x: f32, // [-1,1] mean luma right half - left half
+    pub luma_gradient_y: f32, // [-1,1] mean luma bottom half - top half
+
+    /// Magnitude-weighted edge orientations, 8 bins of 22.5° centered on 0°, 22.5°, ...;
+    /// bin 0 = horizontal edges, 4 = vertical, 2/6 = diagonals. Sums to 1 (all 0 if no edges).
+    pub edge_orientation_hist: [f32; 8],
+
+    pub horizontal_symmetry: f32, // [0,1] 1 - mean |luma - left/right mirror|
+    pub vertical_symmetry: f32,   // [0,1] 1 - mean |luma - top/bottom mirror|
+
+    /// GLCM on 16-level luma, 1 px horizontal offset: sum p(i,j)(i-j)^2, [0,225]
+    pub texture_contrast: f32,
+    /// GLCM on 16-level luma, 1 px horizontal offset: sum p(i,j)/(1+|i-j|), [0,1]
+    pub texture_homogeneity: f32,
 }
 
+/// Gray levels of the co-occurrence matrix behind the texture features.
+const GLCM_LEVELS: usize = 16;
+
+/// Background used under transparent pixels unless the caller picks another one.
+pub const DEFAULT_BACKGROUND: [u8; 3] = [255, 255, 255];
+
+/// Longest side of the thumbnail the features are measured on, unless the caller picks another.
+pub const DEFAULT_THUMB: u32 = 256;
+
 pub fn analyze_image_bytes(img_bytes: &[u8]) -> Result<ImageFeatures> {
     let img = image::load_from_memory(img_bytes)?;
     analyze_image(&img)
 }
 
 pub fn analyze_image(img: &DynamicImage) -> Result<ImageFeatures> {
+    analyze_image_on(img, DEFAULT_BACKGROUND)
+}
+
+pub fn analyze_image_bytes_on(img_bytes: &[u8], bg: [u8; 3]) -> Result<ImageFeatures> {
+    let img = image::load_from_memory(img_bytes)?;
+    analyze_image_on(&img, bg)
+}
+
+/// Any color type (gray, 16-bit, paletted, with/without alpha) -> 8-bit RGB,
+/// compositing alpha over `bg` instead of dropping it.
+pub fn to_rgb8_over(img: &DynamicImage, bg: [u8; 3]) -> RgbImage {
+    if !img.color().has_alpha() {
+        return img.to_rgb8();
+    }
+    let rgba = img.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let p = rgba.get_pixel(x, y);
+        let a = p[3] as f32 / 255.0;
+        let mix = |c: u8, b: u8| (c as f32 * a + b as f32 * (1.0 - a)).round() as u8;
+        image::Rgb([mix(p[0], bg[0]), mix(p[1], bg[1]), mix(p[2], bg[2])])
+    })
+}
+
+/// Same as `analyze_image`, with transparent areas composited over `bg`.
+pub fn analyze_image_on(img: &DynamicImage, bg: [u8; 3]) -> Result<ImageFeatures> {
+    analyze_image_with(img, bg, DEFAULT_THUMB)
+}
+
+/// `analyze_image_bytes_on`, measuring on a `thumb`-pixel thumbnail.
+pub fn analyze_image_bytes_with(img_bytes: &[u8], bg: [u8; 3], thumb: u32) -> Result<ImageFeatures> {
+    let img = image::load_from_memory(img_bytes)?;
+    analyze_image_with(&img, bg, thumb)
+}
+
+/// `analyze_image_on`, measuring on a thumbnail that fits in `thumb` x `thumb` pixels
+/// (larger = finer edges/texture, slower). Width, height and aspect ratio stay the original's.
+pub fn analyze_image_with(img: &DynamicImage, bg: [u8; 3], thumb: u32) -> Result<ImageFeatures> {
+    analyze_image_mode(img, bg, AnalyzeMode::Fast(thumb))
+}
+
+/// How `analyze_image_mode` downsizes the image before measuring; both fit it in
+/// `max_dim` x `max_dim` pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnalyzeMode {
+    /// Quick thumbnail sampling (`DynamicImage::thumbnail`).
+    Fast(u32),
+    /// Lanczos resampling (an image that already fits is used as is): slower, steadier
+    /// color and edge statistics.
+    Accurate(u32),
+}
+
+impl Default for AnalyzeMode {
+    fn default() -> Self { AnalyzeMode::Fast(DEFAULT_THUMB) }
+}
+
+impl AnalyzeMode {
+    pub fn max_dim(self) -> u32 {
+        match self { AnalyzeMode::Fast(d) | AnalyzeMode::Accurate(d) => d }
+    }
+
+    /// The RGB image the features are measured on.
+    fn downsize(self, img: &DynamicImage) -> RgbImage {
+        let max = self.max_dim().max(1);
+        match self {
+            AnalyzeMode::Fast(_) => img.thumbnail(max, max).to_rgb8(),
+            AnalyzeMode::Accurate(_) if img.width() <= max && img.height() <= max => img.to_rgb8(),
+            AnalyzeMode::Accurate(_) => img.resize(max, max, FilterType::Lanczos3).to_rgb8(),
+        }
+    }
+}
+
+/// `analyze_image_bytes_on` in the given `mode`.
+pub fn analyze_image_bytes_mode(img_bytes: &[u8], bg: [u8; 3], mode: AnalyzeMode) -> Result<ImageFeatures> {
+    let img = image::load_from_memory(img_bytes)?;
+    analyze_image_mode(&img, bg, mode)
+}
+
+/// `analyze_image_on`, measuring on the image downsized by `mode`. Luma (contrast,
+/// entropy, edges, symmetry, texture) comes from the same RGB thumbnail as the color stats.
+pub fn analyze_image_mode(img: &DynamicImage, bg: [u8; 3], mode: AnalyzeMode) -> Result<ImageFeatures> {
+    let flat;
+    let img = if img.color().has_alpha() {
+        flat = DynamicImage::ImageRgb8(to_rgb8_over(img, bg));
+        &flat
+    } else {
+        img
+    };
     let (w,h) = img.dimensions();
     let aspect = w as f32 / h.max(1) as f32;
 
     // Downscale for speed
-    let small = img.thumbnail(256, 256).to_rgb8();
+    let small = mode.downsize(img);
     let mut sum_h = 0.0f32; let mut sum_s = 0.0f32; let mut sum_v = 0.0f32;
     let mut hs: Vec<f32> = Vec::with_capacity((small.width()*small.height()) as usize);
 
     // Hasler-Süsstrunk colorfulness
     let mut rg = Vec::new(); let mut yb = Vec::new();
+    let mut sum_rb = 0.0f32;
 
     // Luma for contrast/entropy/edges
-    let gray = img.thumbnail(256, 256).to_luma8();
+    let gray = image::imageops::grayscale(&small);
     let mut luma_vals = Vec::with_capacity((gray.width()*gray.height()) as usize);
 
     for px in small.pixels() {
@@ -54,11 +169,15 @@ pub fn analyze_image(img: &DynamicImage) -> Result<ImageFeatures> {
         // HS colorfulness auxiliars
         rg.push(r - g);
         yb.push(0.5*(r+g) - b);
+
+        // warm/cool balance
+        sum_rb += r - b;
     }
     let n = hs.len().max(1) as f32;
     let mean_h = sum_h / n;
     let mean_s = sum_s / n;
     let mean_v = sum_v / n;
+    let color_temperature = (sum_rb / n).clamp(-1.0, 1.0);
     let hue_variance = if hs.is_empty() { 0.0 } else {
         let m = mean_h;
         (hs.iter().map(|&x| {
@@ -81,10 +200,59 @@ pub fn analyze_image(img: &DynamicImage) -> Result<ImageFeatures> {
     let colorfulness_hs = ((rg_s.powi(2) + yb_s.powi(2)).sqrt() + 0.3*(rg_m.powi(2)+yb_m.powi(2)).sqrt()) * 100.0;
 
     // Luma
-    for p in gray.pixels() { luma_vals.push(p[0] as f32 / 255.0); }
+    // (same pass) half-image sums for the brightness gradients; odd middle row/col is skipped
+    let (gw, gh) = gray.dimensions();
+    let (mut left, mut right, mut top, mut bottom) = (0.0f32, 0.0f32, 0.0f32, 0.0f32);
+    let (mut n_lr, mut n_tb) = (0usize, 0usize);
+    for (x, y, p) in gray.enumerate_pixels() {
+        let l = p[0] as f32 / 255.0;
+        luma_vals.push(l);
+        if 2 * x + 1 < gw { left += l; n_lr += 1; } else if 2 * x + 1 > gw { right += l; }
+        if 2 * y + 1 < gh { top += l; n_tb += 1; } else if 2 * y + 1 > gh { bottom += l; }
+    }
+    let luma_gradient_x = if n_lr > 0 { ((right - left) / n_lr as f32).clamp(-1.0, 1.0) } else { 0.0 };
+    let luma_gradient_y = if n_tb > 0 { ((bottom - top) / n_tb as f32).clamp(-1.0, 1.0) } else { 0.0 };
     let l_mean = luma_vals.iter().sum::<f32>()/luma_vals.len().max(1) as f32;
     let contrast_luma_std = (luma_vals.iter().map(|&x|(x-l_mean)*(x-l_mean)).sum::<f32>()/luma_vals.len().max(1) as f32).sqrt();
 
+    // Mirror symmetry on the same thumbnail
+    let mut diff_lr = 0.0f32;
+    let mut diff_tb = 0.0f32;
+    for (x, y, p) in gray.enumerate_pixels() {
+        let l = p[0] as f32;
+        diff_lr += (l - gray.get_pixel(gw - 1 - x, y)[0] as f32).abs();
+        diff_tb += (l - gray.get_pixel(x, gh - 1 - y)[0] as f32).abs();
+    }
+    let n_px = (luma_vals.len().max(1)) as f32 * 255.0;
+    let horizontal_symmetry = (1.0 - diff_lr / n_px).clamp(0.0, 1.0);
+    let vertical_symmetry = (1.0 - diff_tb / n_px).clamp(0.0, 1.0);
+
+    // Gray-level co-occurrence (16 levels, right neighbour)
+    let mut glcm = [[0u32; GLCM_LEVELS]; GLCM_LEVELS];
+    let quant = |v: u8| v as usize * GLCM_LEVELS / 256;
+    for y in 0..gh {
+        for x in 0..gw.saturating_sub(1) {
+            let i = quant(gray.get_pixel(x, y)[0]);
+            let j = quant(gray.get_pixel(x + 1, y)[0]);
+            glcm[i][j] += 1;
+        }
+    }
+    let pairs = (gh * gw.saturating_sub(1)) as f32;
+    let (mut texture_contrast, mut texture_homogeneity) = (0.0f32, 0.0f32);
+    if pairs > 0.0 {
+        for (i, row) in glcm.iter().enumerate() {
+            for (j, &c) in row.iter().enumerate() {
+                if c == 0 { continue; }
+                let p = c as f32 / pairs;
+                let d = i.abs_diff(j) as f32;
+                texture_contrast += p * d * d;
+                texture_homogeneity += p / (1.0 + d);
+            }
+        }
+    } else {
+        texture_homogeneity = 1.0; // 1 px wide: nothing changes from pixel to pixel
+    }
+
     // 256-bin entropy
     let mut hist = [0usize;256];
     for p in gray.pixels() { hist[p[0] as usize]+=1; }
@@ -114,6 +282,23 @@ pub fn analyze_image(img: &DynamicImage) -> Result<ImageFeatures> {
 
     let edge_density = if tot > 0 { active as f32 / tot as f32 } else { 0.0 };
 
+    // Edge orientation from directional Sobel (edge runs perpendicular to the gradient)
+    let gx_img = imageproc::gradients::horizontal_sobel(&gray);
+    let gy_img = imageproc::gradients::vertical_sobel(&gray);
+    let mut edge_orientation_hist = [0.0f32; 8];
+    for (gx, gy) in gx_img.pixels().zip(gy_img.pixels()) {
+        let (gx, gy) = (gx[0] as f32, gy[0] as f32);
+        let mag = (gx * gx + gy * gy).sqrt();
+        if mag <= 32.0 { continue; } // same treshold as edge_density
+        let edge_deg = (gy.atan2(gx).to_degrees() + 90.0).rem_euclid(180.0);
+        let bin = ((edge_deg / 22.5).round() as usize) % 8;
+        edge_orientation_hist[bin] += mag;
+    }
+    let hist_sum: f32 = edge_orientation_hist.iter().sum();
+    if hist_sum > 0.0 {
+        for b in edge_orientation_hist.iter_mut() { *b /= hist_sum; }
+    }
+
     Ok(ImageFeatures{
         width: w, height: h, aspect_ratio: aspect,
         hsv_mean_h: mean_h.rem_euclid(360.0), hsv_mean_s: mean_s.clamp(0.0,1.0), hsv_mean_v: mean_v.clamp(0.0,1.0),
@@ -122,5 +307,104 @@ pub fn analyze_image(img: &DynamicImage) -> Result<ImageFeatures> {
         contrast_luma_std,
         entropy_luma_bits,
         edge_density,
+        color_temperature,
+        luma_gradient_x,
+        luma_gradient_y,
+        edge_orientation_hist,
+        horizontal_symmetry,
+        vertical_symmetry,
+        texture_contrast,
+        texture_homogeneity,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    fn solid(w: u32, h: u32, rgb: [u8; 3]) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(w, h, Rgb(rgb)))
+    }
+
+    #[test]
+    fn color_temperature_is_negative_for_blue_and_positive_for_orange() {
+        let blue = analyze_image(&solid(32, 32, [20, 60, 230])).unwrap();
+        let orange = analyze_image(&solid(32, 32, [250, 140, 20])).unwrap();
+        let gray = analyze_image(&solid(32, 32, [128, 128, 128])).unwrap();
+        assert!(blue.color_temperature < -0.5, "{}", blue.color_temperature);
+        assert!(orange.color_temperature > 0.5, "{}", orange.color_temperature);
+        assert_eq!(gray.color_temperature, 0.0);
+    }
+
+    /// 8 px black/white bands, running left-right (`horizontal`) or top-bottom.
+    fn stripes(horizontal: bool) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| {
+            let band = if horizontal { y } else { x } / 8;
+            Rgb(if band % 2 == 0 { [0; 3] } else { [255; 3] })
+        }))
+    }
+
+    #[test]
+    fn horizontal_stripes_fill_the_horizontal_orientation_bin() {
+        let f = analyze_image(&stripes(true)).unwrap();
+        let hist = f.edge_orientation_hist;
+        assert!((hist.iter().sum::<f32>() - 1.0).abs() < 1e-5, "{hist:?}");
+        assert!(hist[0] > 0.99, "{hist:?}");
+
+        let v = analyze_image(&stripes(false)).unwrap().edge_orientation_hist;
+        assert!(v[4] > 0.99, "{v:?}");
+        // no edges, no orientation
+        assert_eq!(analyze_image(&solid(16, 16, [90, 90, 90])).unwrap().edge_orientation_hist, [0.0; 8]);
+    }
+
+    fn png(img: &DynamicImage) -> Vec<u8> {
+        let mut out = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut out, image::ImageFormat::Png).unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn transparent_png_is_analyzed_over_the_background() {
+        // transparent black: over white it reads as white, not as the black under the alpha
+        let clear = png(&DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(16, 16, image::Rgba([0, 0, 0, 0]))));
+        let f = analyze_image_bytes(&clear).unwrap();
+        assert!(f.hsv_mean_v > 0.99, "{}", f.hsv_mean_v);
+        let on_black = analyze_image_bytes_on(&clear, [0, 0, 0]).unwrap();
+        assert!(on_black.hsv_mean_v < 0.01, "{}", on_black.hsv_mean_v);
+        // half-transparent red over white is pink
+        let half = png(&DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(16, 16, image::Rgba([255, 0, 0, 128]))));
+        let f = analyze_image_bytes(&half).unwrap();
+        assert!((f.hsv_mean_s - 0.5).abs() < 0.02 && f.hsv_mean_v > 0.99, "s {} v {}", f.hsv_mean_s, f.hsv_mean_v);
+    }
+
+    #[test]
+    fn grayscale_pngs_read_as_neutral_gray() {
+        let gray8 = png(&DynamicImage::ImageLuma8(image::GrayImage::from_pixel(16, 8, image::Luma([128]))));
+        let gray16 = png(&DynamicImage::ImageLuma16(image::ImageBuffer::from_pixel(16, 8, image::Luma([32_896u16]))));
+        for bytes in [gray8, gray16] {
+            let f = analyze_image_bytes(&bytes).unwrap();
+            assert_eq!((f.width, f.height), (16, 8));
+            assert!((f.hsv_mean_v - 128.0 / 255.0).abs() < 0.01, "{}", f.hsv_mean_v);
+            assert_eq!(f.hsv_mean_s, 0.0);
+            assert_eq!(f.color_temperature, 0.0);
+        }
+    }
+
+    #[test]
+    fn mirrored_gradient_is_symmetric() {
+        // brightest in the middle, fading to both sides (thumbnail-sized, so not resampled)
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(256, 192, |x, _| {
+            Rgb([(255 - (2 * x as i32 - 255).unsigned_abs()) as u8; 3])
+        }));
+        let f = analyze_image(&img).unwrap();
+        assert!(f.horizontal_symmetry > 0.99, "{}", f.horizontal_symmetry);
+        assert!(f.vertical_symmetry > 0.99, "{}", f.vertical_symmetry);
+
+        // a plain left-to-right ramp mirrors onto its opposite
+        let ramp = DynamicImage::ImageRgb8(RgbImage::from_fn(256, 192, |x, _| Rgb([x as u8; 3])));
+        let f = analyze_image(&ramp).unwrap();
+        assert!(f.horizontal_symmetry < 0.6, "{}", f.horizontal_symmetry);
+        assert!(f.vertical_symmetry > 0.99, "{}", f.vertical_symmetry);
+    }
+}
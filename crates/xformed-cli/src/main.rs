@@ -2,15 +2,22 @@ use anyhow::{Context, Result};
 use base64::engine::general_purpose::STANDARD as B64;
 use base64::Engine;
 use clap::{Parser, Subcommand};
-use converters::{handle_convert, ConvertRequest, InputPayload, OutputArtifact, TransformOpts};
+use converters::{
+    handle_convert, handle_convert_variations, handle_convert_with_progress, AudioCodec, ConvertRequest, InputPayload,
+    NoiseColor, Osc, OutputArtifact, ScaleKind, ScanOrder, TransformOpts,
+};
+use std::cell::Cell;
 use std::fs;
-use std::io::{self, Read};
+use std::io::{self, IsTerminal, Read};
 use std::path::{Path, PathBuf};
 
 /// xformed-cli – zero-knobs
 /// Commands:
 ///   - text-to-audio --text "..."     (or text on STDIN)
 ///   - image-to-audio --input path.png
+///   - multi-to-audio --text "..." --input path.png
+///   - midi-to-audio --input path.mid
+///   - audio-to-audio --input path.wav
 ///   - *-features (debug): audio/text/image -> json
 #[derive(Parser, Debug)]
 #[command(name="xformed", version, about="Zero-knobs content-driven music")]
@@ -24,6 +31,74 @@ struct Cli {
     #[arg(long)]
     name: Option<String>,
 
+    /// (optional) pin the root note as MIDI number, ex: 60 = C4
+    #[arg(long)]
+    root: Option<i32>,
+
+    /// (optional) pin the scale: major | minor | blues | whole-tone
+    #[arg(long)]
+    scale: Option<ScaleKind>,
+
+    /// (optional) pin the tempo in BPM
+    #[arg(long)]
+    tempo: Option<u32>,
+
+    /// (optional) pin the oscillator stack, first = primary, ex: saw,sine,square
+    #[arg(long, value_delimiter = ',')]
+    osc: Option<Vec<Osc>>,
+
+    /// (image/multi -> audio) order the image tiles are played in:
+    /// snake | row-major | column-major | spiral (outside-in)
+    #[arg(long, default_value = "snake")]
+    scan_order: ScanOrder,
+
+    /// (optional) drum noise: white | pink | brown (pink and brown give a softer kit)
+    #[arg(long)]
+    noise: Option<NoiseColor>,
+
+    /// also write {name}.json with the features and derived style next to the audio
+    #[arg(long)]
+    include_features: bool,
+
+    /// (audio commands) output sample rate in Hz, 8000..=192000 (default 44100)
+    #[arg(long)]
+    sample_rate: Option<u32>,
+
+    /// (audio commands) audio file format: wav | flac (lossless, about half the size)
+    #[arg(long, default_value = "wav")]
+    format: AudioCodec,
+
+    /// (text/image/multi/audio -> audio, midi -> audio) merge notes shorter than this many
+    /// beats into the note before, e.g. 0.5 for a calmer line from a busy image
+    #[arg(long)]
+    min_note_beats: Option<f32>,
+
+    /// (audio commands) bars of metronome clicks before the music (0..=8), for lining up beat 1
+    #[arg(long, default_value_t = 0)]
+    count_in: u32,
+
+    /// (audio commands) bring the output to this integrated loudness (LUFS, -60..=-5), e.g. -16
+    #[arg(long, allow_hyphen_values = true)]
+    target_lufs: Option<f32>,
+
+    /// (audio commands) also write {name}.mid: melody, harmony, bass and drums on separate tracks
+    #[arg(long)]
+    midi: bool,
+
+    /// (audio commands) also write {name}.png, a piano roll of the melody
+    /// (x = beats, y = pitch, color = velocity)
+    #[arg(long)]
+    piano_roll: bool,
+
+    /// (text/image/multi/audio -> audio) skip the melody and rendering: only write
+    /// {name}.json with the features and derived style
+    #[arg(long)]
+    dry_run: bool,
+
+    /// (audio commands) render N seeded takes: {name}_0.wav, {name}_1.wav, ...
+    #[arg(long)]
+    variations: Option<usize>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -44,10 +119,46 @@ enum Commands {
         input: PathBuf,
     },
 
+    /// Text + Image -> Audio (WAV + MIDI JSON), one piece blending both
+    MultiToAudio {
+        /// Input text; if missing, read from STDIN
+        #[arg(long)]
+        text: Option<String>,
+        /// Path to image (PNG/JPEG)
+        #[arg(long)]
+        input: PathBuf,
+    },
+
+    /// MIDI -> Audio (WAV + MIDI JSON), rendered with the default style
+    MidiToAudio {
+        /// Path to a Standard MIDI File (.mid)
+        #[arg(long)]
+        input: PathBuf,
+    },
+
+    /// Audio -> Audio (WAV + MIDI JSON), a new piece following the source's pitch
+    AudioToAudio {
+        /// Path to a WAV file
+        #[arg(long)]
+        input: PathBuf,
+        /// trim leading/trailing silence before analysis
+        #[arg(long)]
+        trim_silence: bool,
+    },
+
     /// DEBUG: extract JSON with features from audio WAV
     AudioFeatures {
         #[arg(long)]
         input: PathBuf,
+        /// trim leading/trailing silence before analysis
+        #[arg(long)]
+        trim_silence: bool,
+        /// analysis frame size in samples (default 2048; need not be a power of two)
+        #[arg(long)]
+        frame_size: Option<usize>,
+        /// analysis hop size in samples, at most the frame size (default 512)
+        #[arg(long)]
+        hop_size: Option<usize>,
     },
 
     /// DEBUG: extract JSON with features from text
@@ -61,6 +172,12 @@ enum Commands {
     ImageFeatures {
         #[arg(long)]
         input: PathBuf,
+        /// thumbnail size (px) the features are measured on, 16..=2048 (default 256)
+        #[arg(long)]
+        thumb: Option<u32>,
+        /// resample the thumbnail with a Lanczos filter (slower, more precise)
+        #[arg(long)]
+        accurate: bool,
     },
 }
 
@@ -90,7 +207,7 @@ fn sanitize_basename(s: &str) -> String {
 }
 
 /// Write artifacts with an implicit "base_stem", but if name_override is Some(..),
-/// all files (WAV, .midi.json, .json) will use that stem.
+/// all files (WAV, .midi.json, .mid, .png, .json) will use that stem.
 fn write_artifacts(out_dir: &Path, base_stem: &str, name_override: Option<&str>, artifacts: &[OutputArtifact]) -> Result<()> {
     ensure_dir(out_dir)?;
     let stem = name_override.unwrap_or(base_stem);
@@ -102,11 +219,26 @@ fn write_artifacts(out_dir: &Path, base_stem: &str, name_override: Option<&str>,
                 let path = out_dir.join(format!("{stem}.wav"));
                 fs::write(&path, bytes).with_context(|| format!("write {}", path.display()))?;
             }
+            OutputArtifact::FlacBase64 { data_b64 } => {
+                let bytes = B64.decode(data_b64).context("bad flac base64")?;
+                let path = out_dir.join(format!("{stem}.flac"));
+                fs::write(&path, bytes).with_context(|| format!("write {}", path.display()))?;
+            }
             OutputArtifact::MidiJsonBase64 { data_b64 } => {
                 let bytes = B64.decode(data_b64).context("bad midi-json base64")?;
                 let path = out_dir.join(format!("{stem}.midi.json"));
                 fs::write(&path, bytes).with_context(|| format!("write {}", path.display()))?;
             }
+            OutputArtifact::MidiBase64 { data_b64 } => {
+                let bytes = B64.decode(data_b64).context("bad midi base64")?;
+                let path = out_dir.join(format!("{stem}.mid"));
+                fs::write(&path, bytes).with_context(|| format!("write {}", path.display()))?;
+            }
+            OutputArtifact::PngBase64 { data_b64 } => {
+                let bytes = B64.decode(data_b64).context("bad png base64")?;
+                let path = out_dir.join(format!("{stem}.png"));
+                fs::write(&path, bytes).with_context(|| format!("write {}", path.display()))?;
+            }
             OutputArtifact::Json { data } => {
                 let path = out_dir.join(format!("{stem}.json"));
                 let pretty = serde_json::to_vec_pretty(data)?;
@@ -117,6 +249,65 @@ fn write_artifacts(out_dir: &Path, base_stem: &str, name_override: Option<&str>,
     Ok(())
 }
 
+/// Run an audio request once, or `--variations N` times with stems `{stem}_{k}`.
+fn convert_audio(cli: &Cli, req: ConvertRequest, base_stem: &str, name_override: Option<&str>) -> Result<()> {
+    let stem = name_override.unwrap_or(base_stem);
+    match cli.variations {
+        Some(n) => {
+            for (k, resp) in handle_convert_variations(req, n)?.iter().enumerate() {
+                write_artifacts(&cli.out_dir, &format!("{stem}_{k}"), None, &resp.artifacts)?;
+            }
+        }
+        None if io::stderr().is_terminal() => {
+            let last = Cell::new(-1);
+            let resp = handle_convert_with_progress(req, Some(&|x| draw_progress(&last, x)));
+            eprintln!();
+            write_artifacts(&cli.out_dir, stem, None, &resp?.artifacts)?;
+        }
+        None => {
+            let resp = handle_convert(req)?;
+            write_artifacts(&cli.out_dir, stem, None, &resp.artifacts)?;
+        }
+    }
+    Ok(())
+}
+
+/// Width of the stderr progress bar (characters).
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+/// Redraw the stderr progress bar; only whole-percent changes are drawn.
+fn draw_progress(last: &Cell<i32>, x: f32) {
+    let pct = (x.clamp(0.0, 1.0) * 100.0) as i32;
+    if pct == last.get() {
+        return;
+    }
+    last.set(pct);
+    let filled = pct as usize * PROGRESS_BAR_WIDTH / 100;
+    eprint!("\r[{}{}] {pct:3}%", "#".repeat(filled), " ".repeat(PROGRESS_BAR_WIDTH - filled));
+}
+
+/// Options shared by every command; creative overrides stay None unless passed.
+fn opts_from_cli(cli: &Cli) -> TransformOpts {
+    TransformOpts {
+        root_midi: cli.root,
+        scale: cli.scale,
+        tempo_bpm: cli.tempo,
+        layering: cli.osc.clone(),
+        noise_color: cli.noise,
+        scan_order: cli.scan_order,
+        include_features: cli.include_features,
+        sample_rate: cli.sample_rate,
+        audio_codec: cli.format,
+        count_in_bars: cli.count_in,
+        target_lufs: cli.target_lufs,
+        midi_file: cli.midi,
+        piano_roll: cli.piano_roll,
+        dry_run: cli.dry_run,
+        min_note_beats: cli.min_note_beats,
+        ..TransformOpts::default()
+    }
+}
+
 fn main() -> Result<()> {
     let cli = Cli::parse();
     let name_override_clean = cli.name.as_ref().map(|s| sanitize_basename(s));
@@ -131,16 +322,10 @@ fn main() -> Result<()> {
             let req = ConvertRequest {
                 from: "text".into(),
                 to: "audio".into(),
-                options: TransformOpts {
-                    text_sec_per_word: None,
-                    text_min_sec: None,
-                    text_max_sec: None,
-                    target_seconds: None,
-                },
+                options: opts_from_cli(&cli),
                 payload: InputPayload::Text { text: text_in },
             };
-            let resp = handle_convert(req)?;
-            write_artifacts(&cli.out_dir, "out_from_text", name_override_ref, &resp.artifacts)?;
+            convert_audio(&cli, req, "out_from_text", name_override_ref)?;
         }
 
         Commands::ImageToAudio { input } => {
@@ -148,28 +333,59 @@ fn main() -> Result<()> {
             let req = ConvertRequest {
                 from: "image".into(),
                 to: "audio".into(),
-                options: TransformOpts {
-                    text_sec_per_word: None,
-                    text_min_sec: None,
-                    text_max_sec: None,
-                    target_seconds: None,
-                },
+                options: opts_from_cli(&cli),
                 payload: InputPayload::ImageBase64 { data_b64: B64.encode(bytes) },
             };
-            let resp = handle_convert(req)?;
-            write_artifacts(&cli.out_dir, "out_from_image", name_override_ref, &resp.artifacts)?;
+            convert_audio(&cli, req, "out_from_image", name_override_ref)?;
+        }
+
+        Commands::MultiToAudio { text, input } => {
+            let text_in = match text {
+                Some(t) => t.clone(),
+                None => read_stdin_string()?,
+            };
+            let bytes = fs::read(input).with_context(|| format!("failed reading image: {}", input.display()))?;
+            let req = ConvertRequest {
+                from: "multi".into(),
+                to: "audio".into(),
+                options: opts_from_cli(&cli),
+                payload: InputPayload::TextAndImage { text: text_in, image_b64: B64.encode(bytes) },
+            };
+            convert_audio(&cli, req, "out_from_multi", name_override_ref)?;
+        }
+
+        Commands::MidiToAudio { input } => {
+            let bytes = fs::read(input).with_context(|| format!("failed reading midi: {}", input.display()))?;
+            let req = ConvertRequest {
+                from: "midi".into(),
+                to: "audio".into(),
+                options: opts_from_cli(&cli),
+                payload: InputPayload::MidiBase64 { data_b64: B64.encode(bytes) },
+            };
+            convert_audio(&cli, req, "out_from_midi", name_override_ref)?;
         }
 
-        Commands::AudioFeatures { input } => {
+        Commands::AudioToAudio { input, trim_silence } => {
+            let bytes = fs::read(input).with_context(|| format!("failed reading audio: {}", input.display()))?;
+            let req = ConvertRequest {
+                from: "audio".into(),
+                to: "audio".into(),
+                options: TransformOpts { trim_silence: *trim_silence, ..opts_from_cli(&cli) },
+                payload: InputPayload::AudioBase64 { data_b64: B64.encode(bytes) },
+            };
+            convert_audio(&cli, req, "out_from_audio", name_override_ref)?;
+        }
+
+        Commands::AudioFeatures { input, trim_silence, frame_size, hop_size } => {
             let bytes = fs::read(input).with_context(|| format!("failed reading audio: {}", input.display()))?;
             let req = ConvertRequest {
                 from: "audio".into(),
                 to: "json".into(),
                 options: TransformOpts {
-                    text_sec_per_word: None,
-                    text_min_sec: None,
-                    text_max_sec: None,
-                    target_seconds: None,
+                    trim_silence: *trim_silence,
+                    frame_size: *frame_size,
+                    hop_size: *hop_size,
+                    ..opts_from_cli(&cli)
                 },
                 payload: InputPayload::AudioBase64 { data_b64: B64.encode(bytes) },
             };
@@ -185,29 +401,19 @@ fn main() -> Result<()> {
             let req = ConvertRequest {
                 from: "text".into(),
                 to: "json".into(),
-                options: TransformOpts {
-                    text_sec_per_word: None,
-                    text_min_sec: None,
-                    text_max_sec: None,
-                    target_seconds: None,
-                },
+                options: opts_from_cli(&cli),
                 payload: InputPayload::Text { text: text_in },
             };
             let resp = handle_convert(req)?;
             write_artifacts(&cli.out_dir, "features_text", name_override_ref, &resp.artifacts)?;
         }
 
-        Commands::ImageFeatures { input } => {
+        Commands::ImageFeatures { input, thumb, accurate } => {
             let bytes = fs::read(input).with_context(|| format!("failed reading image: {}", input.display()))?;
             let req = ConvertRequest {
                 from: "image".into(),
                 to: "json".into(),
-                options: TransformOpts {
-                    text_sec_per_word: None,
-                    text_min_sec: None,
-                    text_max_sec: None,
-                    target_seconds: None,
-                },
+                options: TransformOpts { thumb: *thumb, accurate: *accurate, ..opts_from_cli(&cli) },
                 payload: InputPayload::ImageBase64 { data_b64: B64.encode(bytes) },
             };
             let resp = handle_convert(req)?;
@@ -1,7 +1,64 @@
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
 
-use axum::{routing::{get, post}, Json, Router};
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD as B64, Engine};
+use serde::{Deserialize, Serialize};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use converters::{ConvertRequest, ConvertResponse, handle_convert};
+use converters::{
+    ConvertCache, ConvertError, ConvertRequest, ConvertResponse, InputLimits, InputPayload, OutputArtifact,
+    TransformOpts, handle_convert_cached,
+};
+
+/// Total size of the responses kept in the shared conversion cache (256 MiB).
+const CACHE_MAX_BYTES: usize = 256 << 20;
+
+/// Crate version, reported by `/health` and `/version`.
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Commit the binary was built from, when `XFORMED_GIT_HASH` was set at build time.
+const GIT_HASH: Option<&str> = option_env!("XFORMED_GIT_HASH");
+
+/// Process start, for `/health`'s uptime.
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+/// Input limits every conversion is checked against, read from the environment at startup.
+static LIMITS: OnceLock<InputLimits> = OnceLock::new();
+
+/// Default `InputLimits`, each overridable by `XFORMED_MAX_{TEXT,IMAGE,AUDIO,MIDI}_BYTES`
+/// (unset or unparsable variables keep the default).
+fn limits_from_env() -> InputLimits {
+    let d = InputLimits::default();
+    InputLimits {
+        text: env_usize("XFORMED_MAX_TEXT_BYTES").unwrap_or(d.text),
+        image: env_usize("XFORMED_MAX_IMAGE_BYTES").unwrap_or(d.image),
+        audio: env_usize("XFORMED_MAX_AUDIO_BYTES").unwrap_or(d.audio),
+        midi: env_usize("XFORMED_MAX_MIDI_BYTES").unwrap_or(d.midi),
+    }
+}
+
+fn env_usize(name: &str) -> Option<usize> {
+    std::env::var(name).ok().and_then(|v| v.trim().parse().ok())
+}
+
+fn limits() -> InputLimits {
+    *LIMITS.get_or_init(limits_from_env)
+}
+
+/// Headroom over the encoded input limits for the JSON envelope and options.
+const BODY_LIMIT_SLACK: usize = 64 << 10;
+
+/// Request body limit: `XFORMED_MAX_BODY_BYTES`, else what the input limits need.
+/// Bodies over it get a 413 before they are read in full.
+fn body_limit() -> usize {
+    env_usize("XFORMED_MAX_BODY_BYTES").unwrap_or_else(|| limits().max_encoded_bytes() + BODY_LIMIT_SLACK)
+}
 
 #[tokio::main]
 async fn main() {
@@ -10,16 +67,124 @@ async fn main() {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
+    STARTED.get_or_init(Instant::now);
+    LIMITS.get_or_init(limits_from_env);
+    let cache = Arc::new(ConvertCache::new(CACHE_MAX_BYTES));
+
     let app = Router::new()
-        .route("/health", get(|| async { "ok" }))
-        .route("/convert", post(convert));
+        .route("/health", get(health))
+        .route("/version", get(version))
+        .route("/convert", post(convert))
+        .route("/features", get(features_get).post(features_post))
+        .layer(DefaultBodyLimit::max(body_limit()))
+        .with_state(cache);
 
     let listener = tokio::net::TcpListener::bind(("127.0.0.1", 8080)).await.unwrap();
     tracing::info!("listening on http://127.0.0.1:8080");
     axum::serve(listener, app).await.unwrap();
 }
 
-async fn convert(Json(req): Json<ConvertRequest>) -> Json<ConvertResponse> {
-    let resp = handle_convert(req).expect("convert failed");
-    Json(resp)
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+    uptime_sec: u64,
+    version: &'static str,
+}
+
+/// `GET /health` -> 200 with `{status: "ok", uptime_sec, version}` while serving.
+async fn health() -> Json<Health> {
+    let uptime_sec = STARTED.get().map_or(0, |t| t.elapsed().as_secs());
+    Json(Health { status: "ok", uptime_sec, version: VERSION })
+}
+
+#[derive(Serialize)]
+struct Version {
+    version: &'static str,
+    git_hash: Option<&'static str>,
+}
+
+/// `GET /version` -> `{version, git_hash}` (`git_hash` null unless provided at build time).
+async fn version() -> Json<Version> {
+    Json(Version { version: VERSION, git_hash: GIT_HASH })
+}
+
+async fn convert(
+    State(cache): State<Arc<ConvertCache>>,
+    Json(mut req): Json<ConvertRequest>,
+) -> Result<Json<ConvertResponse>, (StatusCode, String)> {
+    req.options.limits = limits();
+    let resp = handle_convert_cached(req, &cache).map_err(convert_error)?;
+    Ok(Json(resp))
+}
+
+/// Request errors (e.g. an unsupported route) are the client's: 400, or 413 for an oversized
+/// input; the rest stays a 500.
+fn convert_error(e: anyhow::Error) -> (StatusCode, String) {
+    match e.downcast_ref::<ConvertError>() {
+        Some(ConvertError::InputTooLarge { .. }) => (StatusCode::PAYLOAD_TOO_LARGE, format!("{e:#}")),
+        Some(_) => bad_request(format!("{e:#}")),
+        None => (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")),
+    }
+}
+
+#[derive(Deserialize)]
+struct FeaturesQuery {
+    /// "text" | "image" | "audio"
+    kind: String,
+    /// GET, kind=text: the text itself
+    text: Option<String>,
+    /// GET, kind=image|audio: the file, base64 (POST takes raw bytes instead)
+    data_b64: Option<String>,
+}
+
+type FeaturesResult = Result<Json<serde_json::Value>, (StatusCode, String)>;
+
+/// `GET /features?kind=text&text=hello` -> the analysis JSON only (no envelope).
+async fn features_get(State(cache): State<Arc<ConvertCache>>, Query(q): Query<FeaturesQuery>) -> FeaturesResult {
+    let payload = match (q.kind.as_str(), q.text, q.data_b64) {
+        ("text", Some(text), _) => InputPayload::Text { text },
+        ("image", _, Some(data_b64)) => InputPayload::ImageBase64 { data_b64 },
+        ("audio", _, Some(data_b64)) => InputPayload::AudioBase64 { data_b64 },
+        (kind, ..) => return Err(bad_request(format!("kind={kind} needs `text` (text) or `data_b64` (image/audio)"))),
+    };
+    run_features(&cache, &q.kind, payload)
+}
+
+/// `POST /features?kind=image|audio|text` with the raw file (or UTF-8 text) as the body.
+async fn features_post(State(cache): State<Arc<ConvertCache>>, Query(q): Query<FeaturesQuery>, body: Bytes) -> FeaturesResult {
+    let payload = match q.kind.as_str() {
+        "text" => {
+            let text = String::from_utf8(body.to_vec()).map_err(|_| bad_request("body is not UTF-8 text".into()))?;
+            InputPayload::Text { text }
+        }
+        "image" => InputPayload::ImageBase64 { data_b64: B64.encode(&body) },
+        "audio" => InputPayload::AudioBase64 { data_b64: B64.encode(&body) },
+        kind => return Err(bad_request(format!("unknown kind: {kind}"))),
+    };
+    run_features(&cache, &q.kind, payload)
+}
+
+fn run_features(cache: &ConvertCache, kind: &str, payload: InputPayload) -> FeaturesResult {
+    let req = ConvertRequest {
+        from: kind.to_string(),
+        to: "json".into(),
+        options: TransformOpts { limits: limits(), ..TransformOpts::default() },
+        payload,
+    };
+    // analysis failures come from the uploaded file, so they stay 400s
+    let resp = handle_convert_cached(req, cache).map_err(|e| match e.downcast_ref::<ConvertError>() {
+        Some(ConvertError::InputTooLarge { .. }) => convert_error(e),
+        _ => bad_request(format!("{e:#}")),
+    })?;
+    resp.artifacts
+        .into_iter()
+        .find_map(|a| match a {
+            OutputArtifact::Json { data } => Some(Json(data)),
+            _ => None,
+        })
+        .ok_or_else(|| (StatusCode::INTERNAL_SERVER_ERROR, "no features in response".into()))
+}
+
+fn bad_request(msg: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, msg)
 }